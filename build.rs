@@ -7,11 +7,20 @@
 //! Cargo re-run the build script whenever `memory.x` is changed,
 //! updating `memory.x` ensures a rebuild of the application with the
 //! new memory settings.
+//!
+//! It also reads `rktk.json` and generates `config.rs`, which exposes the keyboard constants
+//! to the firmware so that they can be checked against the keymap and drivers at compile time,
+//! and compiles `keymap.json` into `keymap.rs` (see `build/keymap.rs`).
 
 use std::env;
 use std::fs::File;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+#[path = "build/config.rs"]
+mod config;
+#[path = "build/keymap.rs"]
+mod keymap;
 
 fn main() {
     // Put `memory.x` in our output directory and ensure it's
@@ -36,4 +45,8 @@ fn main() {
     if std::env::var("CARGO_FEATURE_DEFMT").is_ok() {
         println!("cargo:rustc-link-arg-bins=-Tdefmt.x");
     }
+
+    println!("cargo:rerun-if-changed=build");
+    let (config, limits) = config::generate_config(out);
+    config::generate_keymap(out, Path::new("keymap.json"), &config, &limits);
 }
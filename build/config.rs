@@ -0,0 +1,179 @@
+//! Reads `rktk.json` and generates `config.rs` and `keymap.rs` in `OUT_DIR`.
+//!
+//! Shared by `build.rs` and `tools/build.rs`, which must also declare the `keymap` module.
+
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::keymap;
+
+/// Fails the build with a readable message.
+pub fn fail(msg: impl std::fmt::Display) -> ! {
+    eprintln!("error: {msg}");
+    std::process::exit(1);
+}
+
+fn read_config() -> (PathBuf, Value) {
+    println!("cargo:rerun-if-env-changed=RKTK_CONFIG_PATH");
+    let path = env::var_os("RKTK_CONFIG_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("rktk.json"));
+    println!("cargo:rerun-if-changed={}", path.display());
+
+    let json = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| fail(format!("failed to read {}: {e}", path.display())));
+    let config = serde_json::from_str(&json)
+        .unwrap_or_else(|e| fail(format!("failed to parse {}: {e}", path.display())));
+    (path, config)
+}
+
+fn get_usize(config: &Value, path: &[&str]) -> usize {
+    let mut value = config;
+    for key in path {
+        value = value
+            .get(key)
+            .unwrap_or_else(|| fail(format!("rktk.json: `{}` is missing", path.join("."))));
+    }
+    value.as_u64().unwrap_or_else(|| {
+        fail(format!(
+            "rktk.json: `{}` must be an integer",
+            path.join(".")
+        ))
+    }) as usize
+}
+
+/// Checks that every key of `dynamic.keyboard.layout` is inside the matrix and appears once.
+fn check_layout(path: &Path, config: &Value, rows: usize, cols: usize) {
+    let Some(layout) = config.pointer("/dynamic/keyboard/layout/keymap") else {
+        return;
+    };
+
+    let mut seen = vec![vec![false; cols]; rows];
+    for key in layout
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|row| row.as_array())
+        .flatten()
+        .filter_map(|key| key.as_str())
+    {
+        let position = key.split_once(',').and_then(|(r, c)| {
+            Some((
+                r.trim().parse::<usize>().ok()?,
+                c.trim().parse::<usize>().ok()?,
+            ))
+        });
+        let Some((row, col)) = position else {
+            fail(format!(
+                "{}: invalid key position `{key}` in layout",
+                path.display()
+            ));
+        };
+        if row >= rows || col >= cols {
+            fail(format!(
+                "{}: key `{key}` in layout is outside of the {rows}x{cols} matrix",
+                path.display()
+            ));
+        }
+        if std::mem::replace(&mut seen[row][col], true) {
+            fail(format!(
+                "{}: key `{key}` appears twice in layout",
+                path.display()
+            ));
+        }
+    }
+}
+
+pub fn generate_config(out: &Path) -> (Value, keymap::Limits) {
+    let (path, config) = read_config();
+
+    let constants = [
+        ("ROWS", &["constant", "keyboard", "rows"][..]),
+        ("COLS", &["constant", "keyboard", "cols"]),
+        ("ENCODER_COUNT", &["constant", "keyboard", "encoder_count"]),
+        (
+            "LEFT_RGB_COUNT",
+            &["constant", "keyboard", "left_rgb_count"],
+        ),
+        (
+            "RIGHT_RGB_COUNT",
+            &["constant", "keyboard", "right_rgb_count"],
+        ),
+        (
+            "TAP_DANCE_MAX_DEFINITIONS",
+            &["constant", "key_manager", "tap_dance_max_definitions"],
+        ),
+        (
+            "TAP_DANCE_MAX_REPEATS",
+            &["constant", "key_manager", "tap_dance_max_repeats"],
+        ),
+        (
+            "COMBO_KEY_MAX_DEFINITIONS",
+            &["constant", "key_manager", "combo_key_max_definitions"],
+        ),
+        (
+            "COMBO_KEY_MAX_SOURCES",
+            &["constant", "key_manager", "combo_key_max_sources"],
+        ),
+        (
+            "ONESHOT_STATE_SIZE",
+            &["constant", "key_manager", "oneshot_state_size"],
+        ),
+    ]
+    .map(|(name, key)| (name, get_usize(&config, key)));
+
+    let rows = constants[0].1;
+    let cols = constants[1].1;
+    if cols % 2 != 0 {
+        fail(format!(
+            "{}: `constant.keyboard.cols` must be even because the matrix is split into two halves",
+            path.display()
+        ));
+    }
+    check_layout(&path, &config, rows, cols);
+
+    let mut file = File::create(out.join("config.rs")).unwrap();
+    writeln!(file, "// Generated by build.rs from {}", path.display()).unwrap();
+    for (name, value) in constants {
+        writeln!(file, "pub const {name}: usize = {value};").unwrap();
+    }
+
+    let limits = keymap::Limits {
+        rows,
+        cols,
+        tap_dance_max_definitions: constants[5].1,
+        tap_dance_max_repeats: constants[6].1,
+        combo_key_max_definitions: constants[7].1,
+        combo_key_max_sources: constants[8].1,
+        oneshot_state_size: constants[9].1,
+        combo_threshold: get_usize(
+            &config,
+            &[
+                "dynamic",
+                "key_manager",
+                "key_resolver",
+                "combo",
+                "threshold",
+            ],
+        ),
+    };
+    (config, limits)
+}
+
+pub fn generate_keymap(out: &Path, path: &Path, config: &Value, limits: &keymap::Limits) {
+    println!("cargo:rerun-if-changed={}", path.display());
+
+    let json = std::fs::read_to_string(path)
+        .unwrap_or_else(|e| fail(format!("failed to read {}: {e}", path.display())));
+    let layout =
+        keymap::layout_positions(config).unwrap_or_else(|e| fail(format!("rktk.json: {e}")));
+    let code = keymap::parse(&json, &layout)
+        .and_then(|keymap| keymap.generate(limits))
+        .unwrap_or_else(|e| fail(format!("{}: {e}", path.display())));
+
+    std::fs::write(out.join("keymap.rs"), code).unwrap();
+}
@@ -0,0 +1,2011 @@
+//! Parser and code generator for `keymap.json`.
+//!
+//! `keymap.json` holds the layers of the keymap as a map from layout position (`"row,col"`, as
+//! used in `dynamic.keyboard.layout` of `rktk.json`) to a key expression. Positions which are
+//! not listed are transparent. A key expression is one of:
+//!
+//! - a name from the rktk prelude (`A`, `L_SHFT`, `M_LEFT`, ...) or from `aliases`
+//! - `Key(Variant)` / `Media(Variant)` for a key code which has no prelude name
+//! - `SF(key)`, `TG(layer)`, `MO(layer)`, `TD(index)` or `TH(tap, hold)`
+//! - `OS(modifier)` or `OSL(layer)` for a one-shot modifier or layer, see `src/oneshot.rs`.
+//!   The optional `oneshot` section sets `timeout` (ms, 0 for none) and `cancel_on_esc`.
+//! - `CAPS_WORD`, see `src/caps_word.rs`. The optional `caps_word` section sets
+//!   `idle_timeout` (ms, 0 for none).
+//! - `LEADER`, see `src/leader.rs`
+//! - `BASE_LAYOUT`, which selects the next of the `base_layouts`
+//! - `OS_PROFILE`, which toggles between the Windows and the Mac `os_profile`
+//! - `HOST_LAYOUT`, which toggles between a US and a JIS layout on the host, see
+//!   `src/host_layout.rs`
+//! - `DM_REC1`, `DM_REC2`, `DM_RSTP`, `DM_PLY1` and `DM_PLY2` for the dynamic macros, see
+//!   `src/dynamic_macro.rs`. The optional `dynamic_macros` section sets `persist`, to keep
+//!   the recordings in storage.
+//! - `MACRO(index)`, which types one of the `text_macros`, see `src/text_macro.rs`
+//!
+//! `combo` lists key combinations as `{ "src": [key, ...], "dst": key }`. rktk matches combos
+//! on key codes, so `src` and `dst` must be plain keys. The time window in which all sources
+//! have to be pressed is `dynamic.key_manager.key_resolver.combo.threshold` in
+//! `rktk.json`.
+//!
+//! `home_row_mods` turns keys of the base layer into modifiers while held:
+//!
+//! ```json
+//! "home_row_mods": {
+//!   "tapping_term": 200,
+//!   "permissive_hold": true,
+//!   "keys": { "2,1": { "hold": "L_GUI", "tapping_term": 250 }, "2,4": { "hold": "L_SHFT" } }
+//! }
+//! ```
+//!
+//! The key in the base layer is what a tap sends and must be a plain key. `hold` is a
+//! modifier name and `tapping_term` (ms) may be set per key. `hold_on_other_key_press`,
+//! `permissive_hold` and `same_hand_roll` are explained in `src/home_row_mods.rs`.
+//!
+//! `leader` lists the sequences typed after `LEADER` and the text each one types:
+//!
+//! ```json
+//! "leader": {
+//!   "timeout": 1000,
+//!   "sequences": [{ "keys": ["G", "S"], "text": "git status\n" }]
+//! }
+//! ```
+//!
+//! Sequence keys must be plain keys, and the text printable ASCII, `\n` or `\t`.
+//!
+//! `key_overrides` replaces a key pressed together with modifiers, see
+//! `src/key_overrides.rs`:
+//!
+//! ```json
+//! "key_overrides": [{ "mods": ["L_SHFT"], "key": "BS", "replacement": "DELETE", "layers": [0] }]
+//! ```
+//!
+//! `mods` are modifier names, either side of which has to be held. `key` must be a plain
+//! key, and `replacement` a plain key or `SF(key)`. The optional `replacement_mods` are sent
+//! with the replacement. `layers` lists the highest active layers the override applies on,
+//! all layers if it is missing, and `os` the profile it applies with (`"windows"` or
+//! `"mac"`), both if it is missing.
+//!
+//! `base_layouts` names the layout of layer 0 and lists other layouts for it by the keys they
+//! change, see `src/base_layout.rs`:
+//!
+//! ```json
+//! "base_layouts": {
+//!   "base": "QWERTY",
+//!   "layouts": [{ "name": "Colemak", "keys": { "1,3": "F", "1,4": "P" } }]
+//! }
+//! ```
+//!
+//! Both the key of layer 0 and the key of the layout must be plain keys.
+//!
+//! `os_profile` maps modifiers to the ones sent to a Mac, see `src/os_profile.rs`. Modifiers
+//! which are not listed are sent as they are:
+//!
+//! ```json
+//! "os_profile": { "mac": { "L_CTRL": "L_GUI", "L_GUI": "L_CTRL" } }
+//! ```
+//!
+//! `text_macros` lists the macros typed by `MACRO(index)`, as steps typing text, tapping a
+//! key with optional modifiers, or waiting for a number of ms:
+//!
+//! ```json
+//! "text_macros": [{
+//!   "name": "sign-off",
+//!   "steps": [{ "text": "Thanks," }, { "tap": "TAB", "mods": ["L_SHFT"] }, { "delay": 50 }]
+//! }]
+//! ```
+//!
+//! The text must be printable ASCII, `\n` or `\t`, and `tap` a plain key. The macros are
+//! compiled into the wire format of `src/text_macro.rs`, and replaced by the macros kept in
+//! storage if there are any.
+//!
+//! This file is used by `build.rs` and by the host tools in `tools/`, so it only depends on
+//! `std` and `serde_json`. The `to_json` methods serialize a keymap back for the tools, so
+//! they allow `dead_code` in the build scripts.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::{self, Write as _};
+
+use serde_json::Value;
+
+/// Names exported by `rktk::config::keymap::prelude` that may be used in `keymap.json`.
+/// Extend this list when a new prelude name is needed.
+#[rustfmt::skip]
+pub const PRELUDE: &[&str] = &[
+    "_____", "__",
+    "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M",
+    "N", "O", "P", "Q", "R", "S", "T", "U", "V", "W", "X", "Y", "Z",
+    "D0", "D1", "D2", "D3", "D4", "D5", "D6", "D7", "D8", "D9",
+    "F1", "F2", "F3", "F4", "F5", "F6", "F7", "F8", "F9", "F10", "F11", "F12",
+    "KP0", "KP1", "KP2", "KP3", "KP4", "KP5", "KP6", "KP7", "KP8", "KP9",
+    "ESC", "TAB", "SPACE", "BS", "DELETE", "INSERT", "HOME", "PGUP", "PRTSC",
+    "LEFT", "DOWN", "UP", "RIGHT",
+    "MINUS", "EQUAL", "LBRC", "BSLSH", "SCLN", "QUOTE", "COMM", "DOT", "SLASH",
+    "L_CTRL", "L_SHFT", "L_ALT", "L_GUI", "R_CTRL", "R_SHFT",
+    "VOLUP", "VOLDN",
+    "M_LEFT", "M_RIGHT", "M_MIDDLE", "M_BACK", "M_FORWARD", "MO_SCRL", "AML_RESET",
+    "FLASH_CLEAR", "BLE_BOND_CLEAR", "OUTPUT_BLE", "OUTPUT_USB",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Name(String),
+    Number(u8),
+    Call(String, Vec<Expr>),
+}
+
+#[derive(Debug)]
+pub struct LayerDef {
+    pub name: String,
+    pub keys: BTreeMap<(usize, usize), Expr>,
+    /// `(clockwise, counterclockwise)` for each encoder. Empty if not specified.
+    pub encoder: Vec<(Option<Expr>, Option<Expr>)>,
+}
+
+#[derive(Debug)]
+pub struct TapDanceDef {
+    pub tap: Vec<Option<Expr>>,
+    pub hold: Vec<Option<Expr>>,
+}
+
+#[derive(Debug)]
+pub struct ComboDef {
+    pub src: Vec<Expr>,
+    pub dst: Expr,
+}
+
+#[derive(Debug)]
+pub struct HomeRowModDef {
+    /// Modifier name, one of [`MODIFIERS`].
+    pub hold: String,
+    pub tapping_term: Option<u32>,
+}
+
+#[derive(Debug)]
+pub struct HomeRowModsDef {
+    /// Default tapping term in ms.
+    pub tapping_term: u32,
+    pub hold_on_other_key_press: bool,
+    pub permissive_hold: bool,
+    pub same_hand_roll: bool,
+    pub keys: BTreeMap<(usize, usize), HomeRowModDef>,
+}
+
+#[derive(Debug)]
+pub struct OneShotDef {
+    pub timeout: u32,
+    pub cancel_on_esc: bool,
+}
+
+#[derive(Debug)]
+pub struct CapsWordDef {
+    pub idle_timeout: u32,
+}
+
+#[derive(Debug)]
+pub struct LeaderSequenceDef {
+    pub keys: Vec<Expr>,
+    pub text: String,
+}
+
+#[derive(Debug)]
+pub struct LeaderDef {
+    pub timeout: u32,
+    pub sequences: Vec<LeaderSequenceDef>,
+}
+
+#[derive(Debug)]
+pub struct KeyOverrideDef {
+    /// Modifier names, each one of [`MODIFIERS`].
+    pub mods: Vec<String>,
+    pub key: Expr,
+    pub replacement: Expr,
+    /// Modifier names sent with the replacement.
+    pub replacement_mods: Vec<String>,
+    /// `None` for all layers.
+    pub layers: Option<Vec<usize>>,
+    /// One of [`OS_PROFILES`], `None` for all.
+    pub os: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct BaseLayoutDef {
+    pub name: String,
+    /// Keys which differ from layer 0.
+    pub keys: BTreeMap<(usize, usize), Expr>,
+}
+
+#[derive(Debug)]
+pub struct BaseLayoutsDef {
+    /// Name of the layout of layer 0.
+    pub base: String,
+    pub layouts: Vec<BaseLayoutDef>,
+}
+
+#[derive(Debug)]
+pub enum TextMacroStepDef {
+    Text(String),
+    /// A key tapped with modifier names, each one of [`MODIFIERS`].
+    Tap(Expr, Vec<String>),
+    Delay(u16),
+}
+
+#[derive(Debug)]
+pub struct TextMacroDef {
+    pub name: String,
+    pub steps: Vec<TextMacroStepDef>,
+}
+
+#[derive(Debug)]
+pub struct DynamicMacrosDef {
+    pub persist: bool,
+}
+
+#[derive(Debug)]
+pub struct OsProfileDef {
+    /// Modifier name in the keymap to the modifier name sent to a Mac.
+    pub mac: BTreeMap<String, String>,
+}
+
+#[derive(Debug)]
+pub struct KeymapFile {
+    pub aliases: Vec<(String, Expr)>,
+    pub layers: Vec<LayerDef>,
+    pub tap_dance: Vec<TapDanceDef>,
+    pub combo: Vec<ComboDef>,
+    pub home_row_mods: Option<HomeRowModsDef>,
+    pub oneshot: Option<OneShotDef>,
+    pub caps_word: Option<CapsWordDef>,
+    pub leader: Option<LeaderDef>,
+    pub key_overrides: Vec<KeyOverrideDef>,
+    pub base_layouts: Option<BaseLayoutsDef>,
+    pub os_profile: Option<OsProfileDef>,
+    pub dynamic_macros: Option<DynamicMacrosDef>,
+    pub text_macros: Vec<TextMacroDef>,
+}
+
+/// Modifier names usable as `hold` of a home-row mod, with their HID modifier bit.
+pub const MODIFIERS: &[(&str, u8)] = &[
+    ("L_CTRL", 0x01),
+    ("L_SHFT", 0x02),
+    ("L_ALT", 0x04),
+    ("L_GUI", 0x08),
+    ("R_CTRL", 0x10),
+    ("R_SHFT", 0x20),
+];
+
+const DEFAULT_TAPPING_TERM: u32 = 200;
+
+const DEFAULT_ONESHOT: OneShotDef = OneShotDef {
+    timeout: 1000,
+    cancel_on_esc: true,
+};
+
+/// Keys handled by the master hooks instead of the key manager.
+pub const CAPS_WORD: &str = "CAPS_WORD";
+pub const LEADER: &str = "LEADER";
+pub const BASE_LAYOUT: &str = "BASE_LAYOUT";
+pub const OS_PROFILE: &str = "OS_PROFILE";
+pub const HOST_LAYOUT: &str = "HOST_LAYOUT";
+pub const DM_REC: [&str; 2] = ["DM_REC1", "DM_REC2"];
+pub const DM_RSTP: &str = "DM_RSTP";
+pub const DM_PLY: [&str; 2] = ["DM_PLY1", "DM_PLY2"];
+const HOOK_KEYS: &[&str] = &[
+    CAPS_WORD,
+    LEADER,
+    BASE_LAYOUT,
+    OS_PROFILE,
+    HOST_LAYOUT,
+    DM_REC[0],
+    DM_REC[1],
+    DM_RSTP,
+    DM_PLY[0],
+    DM_PLY[1],
+];
+
+/// Names of the OS profiles in `keymap.json`, with the variant of `Os` in `src/os_profile.rs`.
+pub const OS_PROFILES: &[(&str, &str)] = &[("windows", "Windows"), ("mac", "Mac")];
+
+const DEFAULT_LEADER_TIMEOUT: u32 = 1000;
+/// `MAX_SEQUENCE` in `src/leader.rs`.
+const LEADER_MAX_SEQUENCE: usize = 8;
+/// `MAX_SIZE` in `src/text_macro.rs`.
+const TEXT_MACROS_MAX_SIZE: usize = 512;
+/// `VERSION` and the step kinds in `src/text_macro.rs`.
+const TEXT_MACROS_VERSION: u8 = 1;
+const STEP_TEXT: u8 = 1;
+const STEP_TAP: u8 = 2;
+const STEP_DELAY: u8 = 3;
+
+const DEFAULT_CAPS_WORD: CapsWordDef = CapsWordDef { idle_timeout: 5000 };
+
+const DEFAULT_DYNAMIC_MACROS: DynamicMacrosDef = DynamicMacrosDef { persist: false };
+
+/// Constants of `rktk.json` the keymap has to agree with.
+pub struct Limits {
+    pub rows: usize,
+    pub cols: usize,
+    pub tap_dance_max_definitions: usize,
+    pub tap_dance_max_repeats: usize,
+    pub combo_key_max_definitions: usize,
+    pub combo_key_max_sources: usize,
+    /// `dynamic.key_manager.key_resolver.combo.threshold`, used as the chord window of the
+    /// home-row mods.
+    pub combo_threshold: usize,
+    pub oneshot_state_size: usize,
+}
+
+/// Returns the key positions in `dynamic.keyboard.layout` of `rktk.json`, in layout order.
+pub fn layout_positions(config: &Value) -> Result<Vec<(usize, usize)>, String> {
+    let layout = config
+        .pointer("/dynamic/keyboard/layout/keymap")
+        .and_then(|l| l.as_array())
+        .ok_or("`dynamic.keyboard.layout.keymap` is missing")?;
+
+    layout
+        .iter()
+        .filter_map(|row| row.as_array())
+        .flatten()
+        .filter_map(|key| key.as_str())
+        .map(|key| parse_position(key).ok_or(format!("invalid key position `{key}` in layout")))
+        .collect()
+}
+
+pub fn parse_position(s: &str) -> Option<(usize, usize)> {
+    let (row, col) = s.split_once(',')?;
+    Some((row.trim().parse().ok()?, col.trim().parse().ok()?))
+}
+
+impl fmt::Display for Expr {
+    /// Formats the expression as it is written in `keymap.json`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Name(name) => f.write_str(name),
+            Expr::Number(n) => write!(f, "{n}"),
+            Expr::Call(func, args) => {
+                write!(f, "{func}(")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{arg}")?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut parser = Parser { s, pos: 0 };
+        let expr = parser.expr()?;
+        parser.skip_ws();
+        if parser.pos != s.len() {
+            return Err(format!("unexpected `{}` in `{s}`", &s[parser.pos..]));
+        }
+        Ok(expr)
+    }
+
+    /// Rust expression of type `KeyAction`.
+    pub fn to_rust(&self) -> String {
+        match self {
+            // Does nothing in the key manager, see `src/caps_word.rs` and `src/leader.rs`.
+            Expr::Name(name) if HOOK_KEYS.contains(&name.as_str()) => "__".to_string(),
+            Expr::Name(name) => name.clone(),
+            Expr::Number(n) => n.to_string(),
+            Expr::Call(func, args) => match (func.as_str(), args.as_slice()) {
+                ("MO", [layer]) => {
+                    format!("KeyAction::Normal(KeyCode::Layer(LayerOp::Momentary({layer})))")
+                }
+                ("TH", [tap, hold]) => format!("th({}, {})", tap.to_rust(), hold.to_rust()),
+                // One-shots are plain keys to the key manager, see `src/oneshot.rs`.
+                ("OS", [modifier]) => modifier.to_rust(),
+                ("OSL", [layer]) => Expr::Call("MO".into(), vec![layer.clone()]).to_rust(),
+                ("Key", [variant]) => format!("KeyAction::Normal(KeyCode::Key(Key::{variant}))"),
+                // A custom key code, typed by the master hooks, see `src/text_macro.rs`.
+                ("MACRO", [index]) => format!("KeyAction::Normal(KeyCode::Custom1({index}))"),
+                ("Media", [variant]) => {
+                    format!("KeyAction::Normal(KeyCode::Media(Media::{variant}))")
+                }
+                (func, args) => {
+                    let args: Vec<_> = args.iter().map(|a| a.to_rust()).collect();
+                    format!("{func}({})", args.join(", "))
+                }
+            },
+        }
+    }
+}
+
+struct Parser<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn skip_ws(&mut self) {
+        while self.s[self.pos..].starts_with(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_ws();
+        if self.s[self.pos..].starts_with(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        self.skip_ws();
+        let rest = &self.s[self.pos..];
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let token = &rest[..len];
+        if token.is_empty() {
+            return Err(format!("expected a key in `{}`", self.s));
+        }
+        self.pos += len;
+
+        if token.starts_with(|c: char| c.is_ascii_digit()) {
+            return token
+                .parse()
+                .map(Expr::Number)
+                .map_err(|_| format!("invalid number `{token}` in `{}`", self.s));
+        }
+
+        if !self.eat('(') {
+            return Ok(Expr::Name(token.to_string()));
+        }
+        let mut args = Vec::new();
+        if !self.eat(')') {
+            loop {
+                args.push(self.expr()?);
+                if self.eat(')') {
+                    break;
+                }
+                if !self.eat(',') {
+                    return Err(format!("expected `,` or `)` in `{}`", self.s));
+                }
+            }
+        }
+        Ok(Expr::Call(token.to_string(), args))
+    }
+}
+
+struct Checker<'a> {
+    aliases: HashSet<&'a str>,
+    layers: usize,
+    tap_dances: usize,
+    text_macros: usize,
+}
+
+impl Checker<'_> {
+    fn check(&self, expr: &Expr) -> Result<(), String> {
+        match expr {
+            Expr::Name(name) => {
+                if PRELUDE.contains(&name.as_str())
+                    || HOOK_KEYS.contains(&name.as_str())
+                    || self.aliases.contains(name.as_str())
+                {
+                    Ok(())
+                } else {
+                    Err(format!("unknown keycode `{name}`"))
+                }
+            }
+            Expr::Number(_) => Err(format!("unexpected number `{expr}`")),
+            Expr::Call(func, args) => match (func.as_str(), args.as_slice()) {
+                ("TG" | "MO" | "OSL", [Expr::Number(n)]) if (*n as usize) < self.layers => Ok(()),
+                ("TG" | "MO" | "OSL", [Expr::Number(n)]) => {
+                    Err(format!("`{expr}`: layer {n} does not exist"))
+                }
+                ("TD", [Expr::Number(n)]) if (*n as usize) < self.tap_dances => Ok(()),
+                ("TD", [Expr::Number(n)]) => Err(format!("`{expr}`: tap dance {n} is not defined")),
+                ("MACRO", [Expr::Number(n)]) if (*n as usize) < self.text_macros => Ok(()),
+                ("MACRO", [Expr::Number(n)]) => {
+                    Err(format!("`{expr}`: text macro {n} is not defined"))
+                }
+                ("Key" | "Media", [Expr::Name(_)]) => Ok(()),
+                ("OS", [Expr::Name(m)]) if MODIFIERS.iter().any(|(name, _)| name == m) => Ok(()),
+                ("OS", _) => Err(format!("`{expr}`: expected a modifier")),
+                ("SF", [key]) => self.check_nested(key),
+                ("TH", [tap, hold]) => self.check_nested(tap).and_then(|_| self.check_nested(hold)),
+                _ => Err(format!("unknown or malformed function `{expr}`")),
+            },
+        }
+    }
+}
+
+impl Checker<'_> {
+    /// Checks an argument of another function or a tap dance or encoder action, where keys
+    /// handled by the master hooks cannot be resolved.
+    fn check_nested(&self, expr: &Expr) -> Result<(), String> {
+        match expr {
+            Expr::Call(func, _) if matches!(func.as_str(), "OS" | "OSL" | "MACRO") => {
+                Err(format!("`{expr}` cannot be used inside another key"))
+            }
+            Expr::Name(name) if HOOK_KEYS.contains(&name.as_str()) => {
+                Err(format!("`{expr}` cannot be used inside another key"))
+            }
+            _ => self.check(expr),
+        }
+    }
+}
+
+fn parse_opt_expr(value: &Value, context: &str) -> Result<Option<Expr>, String> {
+    match value {
+        Value::Null => Ok(None),
+        Value::String(s) => Expr::parse(s)
+            .map(Some)
+            .map_err(|e| format!("{context}: {e}")),
+        _ => Err(format!("{context}: expected a string or null")),
+    }
+}
+
+fn parse_opt_list(value: Option<&Value>, context: &str) -> Result<Vec<Option<Expr>>, String> {
+    match value {
+        None => Ok(Vec::new()),
+        Some(Value::Array(list)) => list
+            .iter()
+            .enumerate()
+            .map(|(i, v)| parse_opt_expr(v, &format!("{context}[{i}]")))
+            .collect(),
+        Some(_) => Err(format!("{context}: expected an array")),
+    }
+}
+
+/// Parses the `home_row_mods` section. Positions are checked by [`parse`].
+pub fn parse_home_row_mods(value: &Value) -> Result<HomeRowModsDef, String> {
+    let context = "home_row_mods";
+    let flag = |name: &str, default: bool| match value.get(name) {
+        None => Ok(default),
+        Some(v) => v
+            .as_bool()
+            .ok_or(format!("{context}.{name}: expected a boolean")),
+    };
+    let term = |value: &Value, context: &str| match value.get("tapping_term") {
+        None => Ok(None),
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .map(Some)
+            .ok_or(format!("{context}.tapping_term: expected milliseconds")),
+    };
+
+    let mut keys = BTreeMap::new();
+    if let Some(map) = value.get("keys") {
+        let map = map
+            .as_object()
+            .ok_or(format!("{context}.keys: expected an object"))?;
+        for (position, def) in map {
+            let context = format!("{context}, key {position}");
+            let pos = parse_position(position).ok_or(format!("{context}: invalid position"))?;
+            let hold = def
+                .get("hold")
+                .and_then(|h| h.as_str())
+                .ok_or(format!("{context}: `hold` must be a modifier name"))?;
+            if !MODIFIERS.iter().any(|(name, _)| *name == hold) {
+                return Err(format!("{context}: `{hold}` is not a modifier"));
+            }
+            keys.insert(
+                pos,
+                HomeRowModDef {
+                    hold: hold.to_string(),
+                    tapping_term: term(def, &context)?,
+                },
+            );
+        }
+    }
+
+    Ok(HomeRowModsDef {
+        tapping_term: term(value, context)?.unwrap_or(DEFAULT_TAPPING_TERM),
+        hold_on_other_key_press: flag("hold_on_other_key_press", false)?,
+        permissive_hold: flag("permissive_hold", false)?,
+        same_hand_roll: flag("same_hand_roll", true)?,
+        keys,
+    })
+}
+
+/// Parses the `caps_word` section.
+pub fn parse_caps_word(value: &Value) -> Result<CapsWordDef, String> {
+    let idle_timeout = match value.get("idle_timeout") {
+        None => DEFAULT_CAPS_WORD.idle_timeout,
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or("caps_word.idle_timeout: expected milliseconds")?,
+    };
+    Ok(CapsWordDef { idle_timeout })
+}
+
+impl CapsWordDef {
+    /// Serializes the section back into the format of `keymap.json`.
+    #[allow(dead_code)]
+    pub fn to_json(&self) -> Value {
+        serde_json::json!({ "idle_timeout": self.idle_timeout })
+    }
+}
+
+/// Parses the `leader` section. Keys are checked by [`parse`].
+pub fn parse_leader(value: &Value) -> Result<LeaderDef, String> {
+    let timeout = match value.get("timeout") {
+        None => DEFAULT_LEADER_TIMEOUT,
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or("leader.timeout: expected milliseconds")?,
+    };
+
+    let mut sequences = Vec::new();
+    if let Some(list) = value.get("sequences") {
+        let list = list
+            .as_array()
+            .ok_or("leader.sequences: expected an array")?;
+        for (i, def) in list.iter().enumerate() {
+            let context = format!("leader.sequences[{i}]");
+            let keys = parse_opt_list(def.get("keys"), &format!("{context}.keys"))?
+                .into_iter()
+                .collect::<Option<Vec<_>>>()
+                .ok_or(format!("{context}.keys: keys must not be null"))?;
+            if keys.is_empty() {
+                return Err(format!("{context}.keys: a sequence needs at least one key"));
+            }
+            if keys.len() > LEADER_MAX_SEQUENCE {
+                return Err(format!(
+                    "{context}.keys: at most {LEADER_MAX_SEQUENCE} keys are supported"
+                ));
+            }
+            let text = def
+                .get("text")
+                .and_then(|t| t.as_str())
+                .ok_or(format!("{context}.text: expected a string"))?;
+            check_text(text, &format!("{context}.text"))?;
+            sequences.push(LeaderSequenceDef {
+                keys,
+                text: text.to_string(),
+            });
+        }
+    }
+    for (i, def) in sequences.iter().enumerate() {
+        if sequences[..i].iter().any(|other| other.keys == def.keys) {
+            return Err(format!(
+                "leader.sequences[{i}]: the same keys are used twice"
+            ));
+        }
+    }
+
+    Ok(LeaderDef { timeout, sequences })
+}
+
+impl LeaderDef {
+    /// Serializes the section back into the format of `keymap.json`.
+    #[allow(dead_code)]
+    pub fn to_json(&self) -> Value {
+        let sequences: Vec<_> = self
+            .sequences
+            .iter()
+            .map(|def| {
+                serde_json::json!({
+                    "keys": def.keys.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+                    "text": def.text,
+                })
+            })
+            .collect();
+        serde_json::json!({ "timeout": self.timeout, "sequences": sequences })
+    }
+}
+
+/// Parses the `key_overrides` section. Keys and layers are checked by [`parse`].
+pub fn parse_key_overrides(value: &Value) -> Result<Vec<KeyOverrideDef>, String> {
+    let list = value.as_array().ok_or("`key_overrides` must be an array")?;
+    let mut overrides = Vec::new();
+    for (i, def) in list.iter().enumerate() {
+        let context = format!("key_overrides[{i}]");
+        let modifier_names = |value: Option<&Value>, context: &str| {
+            value
+                .and_then(|m| m.as_array())
+                .ok_or(format!("{context}: expected an array of modifier names"))?
+                .iter()
+                .map(|m| match m.as_str() {
+                    Some(name) if MODIFIERS.iter().any(|(n, _)| *n == name) => Ok(name.to_string()),
+                    _ => Err(format!("{context}: `{m}` is not a modifier")),
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let mods = modifier_names(def.get("mods"), &format!("{context}.mods"))?;
+        if mods.is_empty() {
+            return Err(format!("{context}.mods: an override needs a modifier"));
+        }
+        let key = parse_opt_expr(def.get("key").unwrap_or(&Value::Null), &context)?
+            .ok_or(format!("{context}: `key` is missing"))?;
+        let replacement = parse_opt_expr(def.get("replacement").unwrap_or(&Value::Null), &context)?
+            .ok_or(format!("{context}: `replacement` is missing"))?;
+        let replacement_mods = match def.get("replacement_mods") {
+            None => Vec::new(),
+            Some(v) => modifier_names(Some(v), &format!("{context}.replacement_mods"))?,
+        };
+        let layers = match def.get("layers") {
+            None => None,
+            Some(v) => Some(
+                v.as_array()
+                    .and_then(|l| {
+                        l.iter()
+                            .map(|n| n.as_u64().and_then(|n| usize::try_from(n).ok()))
+                            .collect::<Option<Vec<_>>>()
+                    })
+                    .ok_or(format!("{context}.layers: expected an array of layers"))?,
+            ),
+        };
+        let os = match def.get("os") {
+            None => None,
+            Some(v) => match v.as_str() {
+                Some(name) if OS_PROFILES.iter().any(|(n, _)| *n == name) => Some(name.to_string()),
+                _ => return Err(format!("{context}.os: `{v}` is not an OS profile")),
+            },
+        };
+        overrides.push(KeyOverrideDef {
+            mods,
+            key,
+            replacement,
+            replacement_mods,
+            layers,
+            os,
+        });
+    }
+    Ok(overrides)
+}
+
+impl KeyOverrideDef {
+    /// Serializes the override back into the format of `keymap.json`.
+    #[allow(dead_code)]
+    pub fn to_json(&self) -> Value {
+        let mut value = serde_json::json!({
+            "mods": self.mods,
+            "key": self.key.to_string(),
+            "replacement": self.replacement.to_string(),
+        });
+        if !self.replacement_mods.is_empty() {
+            value["replacement_mods"] = self.replacement_mods.clone().into();
+        }
+        if let Some(layers) = &self.layers {
+            value["layers"] = layers.clone().into();
+        }
+        if let Some(os) = &self.os {
+            value["os"] = os.clone().into();
+        }
+        value
+    }
+
+    /// HID modifier bits of `mods`.
+    fn modifier_bits(&self) -> u8 {
+        modifier_bits(&self.mods)
+    }
+
+    fn applies_on(&self, layer: usize) -> bool {
+        self.layers.as_ref().is_none_or(|l| l.contains(&layer))
+    }
+
+    /// Whether `self` and `other` apply with a common OS profile.
+    fn shares_os(&self, other: &Self) -> bool {
+        self.os.is_none() || other.os.is_none() || self.os == other.os
+    }
+}
+
+/// HID modifier bits of modifier names, each one of [`MODIFIERS`].
+fn modifier_bits(names: &[String]) -> u8 {
+    names
+        .iter()
+        .filter_map(|m| MODIFIERS.iter().find(|(name, _)| name == m))
+        .fold(0, |bits, (_, bit)| bits | bit)
+}
+
+/// Checks that `text` can be typed by the master hooks.
+fn check_text(text: &str, context: &str) -> Result<(), String> {
+    match text
+        .chars()
+        .find(|c| !(matches!(c, ' '..='~' | '\n' | '\t')))
+    {
+        Some(c) => Err(format!("{context}: `{c}` cannot be typed")),
+        None => Ok(()),
+    }
+}
+
+/// Parses the `text_macros` section. Keys and the encoded size are checked by [`parse`].
+pub fn parse_text_macros(value: &Value) -> Result<Vec<TextMacroDef>, String> {
+    let list = value.as_array().ok_or("`text_macros` must be an array")?;
+    let mut macros = Vec::new();
+    for (i, def) in list.iter().enumerate() {
+        let context = format!("text_macros[{i}]");
+        let name = def
+            .get("name")
+            .and_then(|n| n.as_str())
+            .ok_or(format!("{context}.name: expected a string"))?;
+        if name.len() > 255 {
+            return Err(format!("{context}.name: at most 255 bytes are supported"));
+        }
+        let list = def
+            .get("steps")
+            .and_then(|s| s.as_array())
+            .ok_or(format!("{context}.steps: expected an array"))?;
+        let mut steps = Vec::new();
+        for (j, step) in list.iter().enumerate() {
+            let context = format!("{context}.steps[{j}]");
+            let step = if let Some(text) = step.get("text") {
+                let text = text
+                    .as_str()
+                    .ok_or(format!("{context}.text: expected a string"))?;
+                check_text(text, &format!("{context}.text"))?;
+                TextMacroStepDef::Text(text.to_string())
+            } else if let Some(key) = step.get("tap") {
+                let key = parse_opt_expr(key, &format!("{context}.tap"))?
+                    .ok_or(format!("{context}.tap: expected a key"))?;
+                let mods = match step.get("mods") {
+                    None => Vec::new(),
+                    Some(mods) => mods
+                        .as_array()
+                        .ok_or(format!(
+                            "{context}.mods: expected an array of modifier names"
+                        ))?
+                        .iter()
+                        .map(|m| match m.as_str() {
+                            Some(name) if MODIFIERS.iter().any(|(n, _)| *n == name) => {
+                                Ok(name.to_string())
+                            }
+                            _ => Err(format!("{context}.mods: `{m}` is not a modifier")),
+                        })
+                        .collect::<Result<_, _>>()?,
+                };
+                TextMacroStepDef::Tap(key, mods)
+            } else if let Some(ms) = step.get("delay") {
+                let ms = ms
+                    .as_u64()
+                    .and_then(|ms| u16::try_from(ms).ok())
+                    .ok_or(format!("{context}.delay: expected at most 65535 ms"))?;
+                TextMacroStepDef::Delay(ms)
+            } else {
+                return Err(format!(
+                    "{context}: expected a `text`, `tap` or `delay` step"
+                ));
+            };
+            steps.push(step);
+        }
+        macros.push(TextMacroDef {
+            name: name.to_string(),
+            steps,
+        });
+    }
+    Ok(macros)
+}
+
+impl TextMacroDef {
+    /// Serializes the macro back into the format of `keymap.json`.
+    #[allow(dead_code)]
+    pub fn to_json(&self) -> Value {
+        let steps: Vec<_> = self
+            .steps
+            .iter()
+            .map(|step| match step {
+                TextMacroStepDef::Text(text) => serde_json::json!({ "text": text }),
+                TextMacroStepDef::Tap(key, mods) if mods.is_empty() => {
+                    serde_json::json!({ "tap": key.to_string() })
+                }
+                TextMacroStepDef::Tap(key, mods) => {
+                    serde_json::json!({ "tap": key.to_string(), "mods": mods })
+                }
+                TextMacroStepDef::Delay(ms) => serde_json::json!({ "delay": ms }),
+            })
+            .collect();
+        serde_json::json!({ "name": self.name, "steps": steps })
+    }
+
+    /// The macro in the wire format of `src/text_macro.rs`, with `key` giving the byte of a
+    /// tapped key.
+    pub fn encode<T>(&self, key: impl Fn(&Expr) -> T, byte: impl Fn(u8) -> T) -> Vec<T> {
+        let mut steps = Vec::new();
+        for step in &self.steps {
+            match step {
+                TextMacroStepDef::Text(text) => {
+                    for chunk in text.as_bytes().chunks(255) {
+                        steps.push(byte(STEP_TEXT));
+                        steps.push(byte(chunk.len() as u8));
+                        steps.extend(chunk.iter().map(|&c| byte(c)));
+                    }
+                }
+                TextMacroStepDef::Tap(expr, mods) => {
+                    steps.push(byte(STEP_TAP));
+                    steps.push(byte(modifier_bits(mods)));
+                    steps.push(key(expr));
+                }
+                TextMacroStepDef::Delay(ms) => {
+                    steps.push(byte(STEP_DELAY));
+                    steps.extend(ms.to_le_bytes().map(&byte));
+                }
+            }
+        }
+        let mut out = vec![byte(self.name.len() as u8)];
+        out.extend(self.name.bytes().map(&byte));
+        out.extend((steps.len() as u16).to_le_bytes().map(&byte));
+        out.extend(steps);
+        out
+    }
+}
+
+/// `text_macros` in the wire format of `src/text_macro.rs`, see [`TextMacroDef::encode`].
+pub fn encode_text_macros<T>(
+    macros: &[TextMacroDef],
+    key: impl Fn(&Expr) -> T,
+    byte: impl Fn(u8) -> T,
+) -> Vec<T> {
+    let mut out = vec![byte(TEXT_MACROS_VERSION), byte(macros.len() as u8)];
+    for def in macros {
+        out.extend(def.encode(&key, &byte));
+    }
+    out
+}
+
+/// Parses the `dynamic_macros` section.
+pub fn parse_dynamic_macros(value: &Value) -> Result<DynamicMacrosDef, String> {
+    let persist = match value.get("persist") {
+        None => DEFAULT_DYNAMIC_MACROS.persist,
+        Some(v) => v
+            .as_bool()
+            .ok_or("dynamic_macros.persist: expected a boolean")?,
+    };
+    Ok(DynamicMacrosDef { persist })
+}
+
+impl DynamicMacrosDef {
+    /// Serializes the section back into the format of `keymap.json`.
+    #[allow(dead_code)]
+    pub fn to_json(&self) -> Value {
+        serde_json::json!({ "persist": self.persist })
+    }
+}
+
+/// Parses the `os_profile` section.
+pub fn parse_os_profile(value: &Value) -> Result<OsProfileDef, String> {
+    let mut mac = BTreeMap::new();
+    if let Some(map) = value.get("mac") {
+        let map = map
+            .as_object()
+            .ok_or("os_profile.mac: expected an object")?;
+        for (from, to) in map {
+            let context = format!("os_profile.mac.{from}");
+            if !MODIFIERS.iter().any(|(name, _)| name == from) {
+                return Err(format!("{context}: `{from}` is not a modifier"));
+            }
+            match to.as_str() {
+                Some(to) if MODIFIERS.iter().any(|(name, _)| *name == to) => {
+                    mac.insert(from.clone(), to.to_string());
+                }
+                _ => return Err(format!("{context}: `{to}` is not a modifier")),
+            }
+        }
+    }
+    Ok(OsProfileDef { mac })
+}
+
+impl OsProfileDef {
+    /// Serializes the section back into the format of `keymap.json`.
+    #[allow(dead_code)]
+    pub fn to_json(&self) -> Value {
+        serde_json::json!({ "mac": self.mac })
+    }
+}
+
+/// Parses the `base_layouts` section. Positions and keys are checked by [`parse`].
+pub fn parse_base_layouts(value: &Value) -> Result<BaseLayoutsDef, String> {
+    let name = |value: Option<&Value>, context: &str| match value.and_then(|n| n.as_str()) {
+        Some(name) if !name.is_empty() && name.is_ascii() => Ok(name.to_string()),
+        _ => Err(format!("{context}: expected a non-empty ASCII name")),
+    };
+    let base = name(value.get("base"), "base_layouts.base")?;
+
+    let mut layouts: Vec<BaseLayoutDef> = Vec::new();
+    if let Some(list) = value.get("layouts") {
+        let list = list
+            .as_array()
+            .ok_or("base_layouts.layouts: expected an array")?;
+        for (i, def) in list.iter().enumerate() {
+            let context = format!("base_layouts.layouts[{i}]");
+            let name = name(def.get("name"), &format!("{context}.name"))?;
+            if name == base || layouts.iter().any(|l| l.name == name) {
+                return Err(format!("{context}: the name `{name}` is used twice"));
+            }
+            let mut keys = BTreeMap::new();
+            if let Some(map) = def.get("keys") {
+                let map = map
+                    .as_object()
+                    .ok_or(format!("{context}.keys: expected an object"))?;
+                for (position, value) in map {
+                    let context = format!("{context}, key {position}");
+                    let pos =
+                        parse_position(position).ok_or(format!("{context}: invalid position"))?;
+                    let expr = parse_opt_expr(value, &context)?
+                        .ok_or(format!("{context}: expected a key"))?;
+                    keys.insert(pos, expr);
+                }
+            }
+            layouts.push(BaseLayoutDef { name, keys });
+        }
+    }
+
+    Ok(BaseLayoutsDef { base, layouts })
+}
+
+impl BaseLayoutsDef {
+    /// Serializes the section back into the format of `keymap.json`.
+    #[allow(dead_code)]
+    pub fn to_json(&self) -> Value {
+        let layouts: Vec<_> = self
+            .layouts
+            .iter()
+            .map(|layout| {
+                let keys: serde_json::Map<_, _> = layout
+                    .keys
+                    .iter()
+                    .map(|((row, col), expr)| (format!("{row},{col}"), expr.to_string().into()))
+                    .collect();
+                serde_json::json!({ "name": layout.name, "keys": keys })
+            })
+            .collect();
+        serde_json::json!({ "base": self.base, "layouts": layouts })
+    }
+}
+
+/// Parses the `oneshot` section.
+pub fn parse_oneshot(value: &Value) -> Result<OneShotDef, String> {
+    let timeout = match value.get("timeout") {
+        None => DEFAULT_ONESHOT.timeout,
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or("oneshot.timeout: expected milliseconds")?,
+    };
+    let cancel_on_esc = match value.get("cancel_on_esc") {
+        None => DEFAULT_ONESHOT.cancel_on_esc,
+        Some(v) => v
+            .as_bool()
+            .ok_or("oneshot.cancel_on_esc: expected a boolean")?,
+    };
+    Ok(OneShotDef {
+        timeout,
+        cancel_on_esc,
+    })
+}
+
+impl OneShotDef {
+    /// Serializes the section back into the format of `keymap.json`.
+    #[allow(dead_code)]
+    pub fn to_json(&self) -> Value {
+        serde_json::json!({ "timeout": self.timeout, "cancel_on_esc": self.cancel_on_esc })
+    }
+}
+
+impl HomeRowModsDef {
+    /// Serializes the section back into the format of `keymap.json`.
+    #[allow(dead_code)]
+    pub fn to_json(&self) -> Value {
+        let keys: serde_json::Map<_, _> = self
+            .keys
+            .iter()
+            .map(|((row, col), def)| {
+                let mut value = serde_json::json!({ "hold": def.hold });
+                if let Some(term) = def.tapping_term {
+                    value["tapping_term"] = term.into();
+                }
+                (format!("{row},{col}"), value)
+            })
+            .collect();
+        serde_json::json!({
+            "tapping_term": self.tapping_term,
+            "hold_on_other_key_press": self.hold_on_other_key_press,
+            "permissive_hold": self.permissive_hold,
+            "same_hand_roll": self.same_hand_roll,
+            "keys": keys,
+        })
+    }
+}
+
+/// Parses and validates `keymap.json`. `layout` is the result of [`layout_positions`].
+pub fn parse(json: &str, layout: &[(usize, usize)]) -> Result<KeymapFile, String> {
+    let root: Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+
+    let mut aliases = Vec::new();
+    if let Some(map) = root.get("aliases") {
+        let map = map.as_object().ok_or("`aliases` must be an object")?;
+        for (name, value) in map {
+            if PRELUDE.contains(&name.as_str()) || HOOK_KEYS.contains(&name.as_str()) {
+                return Err(format!("alias `{name}` shadows a prelude name"));
+            }
+            let value = value
+                .as_str()
+                .ok_or(format!("alias `{name}` must be a string"))?;
+            let expr = Expr::parse(value).map_err(|e| format!("alias `{name}`: {e}"))?;
+            aliases.push((name.clone(), expr));
+        }
+    }
+
+    let mut layers = Vec::new();
+    for (i, layer) in root
+        .get("layers")
+        .and_then(|l| l.as_array())
+        .ok_or("`layers` must be an array")?
+        .iter()
+        .enumerate()
+    {
+        let name = layer
+            .get("name")
+            .and_then(|n| n.as_str())
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("Layer {i}"));
+
+        let mut keys = BTreeMap::new();
+        if let Some(map) = layer.get("keys") {
+            let map = map
+                .as_object()
+                .ok_or(format!("layer `{name}`: `keys` must be an object"))?;
+            for (position, value) in map {
+                let context = format!("layer `{name}`, key {position}");
+                let pos = parse_position(position).ok_or(format!("{context}: invalid position"))?;
+                if !layout.contains(&pos) {
+                    return Err(format!(
+                        "{context}: position is not in the layout of rktk.json"
+                    ));
+                }
+                let value = value
+                    .as_str()
+                    .ok_or(format!("{context}: expected a string"))?;
+                let expr = Expr::parse(value).map_err(|e| format!("{context}: {e}"))?;
+                keys.insert(pos, expr);
+            }
+        }
+
+        let mut encoder = Vec::new();
+        if let Some(list) = layer.get("encoder") {
+            let list = list
+                .as_array()
+                .ok_or(format!("layer `{name}`: `encoder` must be an array"))?;
+            for (j, pair) in list.iter().enumerate() {
+                let context = format!("layer `{name}`, encoder {j}");
+                let pair = parse_opt_list(Some(pair), &context)?;
+                let [cw, ccw] = <[Option<Expr>; 2]>::try_from(pair)
+                    .map_err(|_| format!("{context}: expected [clockwise, counterclockwise]"))?;
+                encoder.push((cw, ccw));
+            }
+        }
+
+        layers.push(LayerDef {
+            name,
+            keys,
+            encoder,
+        });
+    }
+
+    let mut tap_dance = Vec::new();
+    if let Some(list) = root.get("tap_dance") {
+        let list = list.as_array().ok_or("`tap_dance` must be an array")?;
+        for (i, def) in list.iter().enumerate() {
+            let context = format!("tap_dance[{i}]");
+            tap_dance.push(TapDanceDef {
+                tap: parse_opt_list(def.get("tap"), &format!("{context}.tap"))?,
+                hold: parse_opt_list(def.get("hold"), &format!("{context}.hold"))?,
+            });
+        }
+    }
+
+    let mut combo = Vec::new();
+    if let Some(list) = root.get("combo") {
+        let list = list.as_array().ok_or("`combo` must be an array")?;
+        for (i, def) in list.iter().enumerate() {
+            let context = format!("combo[{i}]");
+            let src = parse_opt_list(def.get("src"), &format!("{context}.src"))?
+                .into_iter()
+                .collect::<Option<Vec<_>>>()
+                .ok_or(format!("{context}.src: sources must not be null"))?;
+            if src.len() < 2 {
+                return Err(format!("{context}.src: a combo needs at least two keys"));
+            }
+            let dst = parse_opt_expr(def.get("dst").unwrap_or(&Value::Null), &context)?
+                .ok_or(format!("{context}: `dst` is missing"))?;
+            combo.push(ComboDef { src, dst });
+        }
+    }
+
+    let home_row_mods = root
+        .get("home_row_mods")
+        .map(parse_home_row_mods)
+        .transpose()?;
+
+    let oneshot = root.get("oneshot").map(parse_oneshot).transpose()?;
+    let caps_word = root.get("caps_word").map(parse_caps_word).transpose()?;
+    let leader = root.get("leader").map(parse_leader).transpose()?;
+    let key_overrides = root
+        .get("key_overrides")
+        .map(parse_key_overrides)
+        .transpose()?
+        .unwrap_or_default();
+    let base_layouts = root
+        .get("base_layouts")
+        .map(parse_base_layouts)
+        .transpose()?;
+    let os_profile = root.get("os_profile").map(parse_os_profile).transpose()?;
+    let dynamic_macros = root
+        .get("dynamic_macros")
+        .map(parse_dynamic_macros)
+        .transpose()?;
+    let text_macros = root
+        .get("text_macros")
+        .map(parse_text_macros)
+        .transpose()?
+        .unwrap_or_default();
+
+    let file = KeymapFile {
+        aliases,
+        layers,
+        tap_dance,
+        combo,
+        home_row_mods,
+        oneshot,
+        caps_word,
+        leader,
+        key_overrides,
+        base_layouts,
+        os_profile,
+        dynamic_macros,
+        text_macros,
+    };
+    file.check()?;
+    Ok(file)
+}
+
+impl KeymapFile {
+    fn check(&self) -> Result<(), String> {
+        let checker = Checker {
+            aliases: self.aliases.iter().map(|(n, _)| n.as_str()).collect(),
+            layers: self.layers.len(),
+            tap_dances: self.tap_dance.len(),
+            text_macros: self.text_macros.len(),
+        };
+
+        for (name, expr) in &self.aliases {
+            checker
+                .check(expr)
+                .map_err(|e| format!("alias `{name}`: {e}"))?;
+        }
+        for layer in &self.layers {
+            for ((row, col), expr) in &layer.keys {
+                checker
+                    .check(expr)
+                    .map_err(|e| format!("layer `{}`, key {row},{col}: {e}", layer.name))?;
+            }
+            for (i, (cw, ccw)) in layer.encoder.iter().enumerate() {
+                for expr in [cw, ccw].into_iter().flatten() {
+                    checker
+                        .check_nested(expr)
+                        .map_err(|e| format!("layer `{}`, encoder {i}: {e}", layer.name))?;
+                }
+            }
+        }
+        for (i, def) in self.tap_dance.iter().enumerate() {
+            for expr in def.tap.iter().chain(&def.hold).flatten() {
+                checker
+                    .check_nested(expr)
+                    .map_err(|e| format!("tap_dance[{i}]: {e}"))?;
+            }
+        }
+        for (i, def) in self.combo.iter().enumerate() {
+            for expr in def.src.iter().chain([&def.dst]) {
+                checker
+                    .check(expr)
+                    .and_then(|_| self.check_plain(expr))
+                    .map_err(|e| format!("combo[{i}]: {e}"))?;
+            }
+        }
+        if let Some(leader) = &self.leader {
+            for (i, def) in leader.sequences.iter().enumerate() {
+                for expr in &def.keys {
+                    checker
+                        .check(expr)
+                        .and_then(|_| self.check_plain(expr))
+                        .map_err(|e| format!("leader.sequences[{i}]: {e}"))?;
+                }
+            }
+        }
+        for (i, def) in self.key_overrides.iter().enumerate() {
+            let context = format!("key_overrides[{i}]");
+            let replacement = match self.resolve(&def.replacement) {
+                Expr::Call(func, args) if func == "SF" && args.len() == 1 => &args[0],
+                _ => &def.replacement,
+            };
+            for expr in [&def.key, replacement] {
+                checker
+                    .check(expr)
+                    .and_then(|_| self.check_key(expr))
+                    .map_err(|e| format!("{context}: {e}"))?;
+            }
+            if let Some(layer) = def
+                .layers
+                .iter()
+                .flatten()
+                .find(|&&l| l >= self.layers.len())
+            {
+                return Err(format!("{context}.layers: layer {layer} does not exist"));
+            }
+            let modifiers = def.modifier_bits();
+            let overlaps = self.key_overrides[..i].iter().any(|other| {
+                other.key == def.key
+                    && other.modifier_bits() == modifiers
+                    && other.shares_os(def)
+                    && (0..self.layers.len()).any(|l| other.applies_on(l) && def.applies_on(l))
+            });
+            if overlaps {
+                return Err(format!(
+                    "{context}: the same key and modifiers are overridden twice"
+                ));
+            }
+        }
+        if let Some(base_layouts) = &self.base_layouts {
+            for (i, layout) in base_layouts.layouts.iter().enumerate() {
+                for ((row, col), expr) in &layout.keys {
+                    let context = format!("base_layouts.layouts[{i}], key {row},{col}");
+                    let base = self
+                        .layers
+                        .first()
+                        .and_then(|layer| layer.keys.get(&(*row, *col)))
+                        .ok_or(format!("{context}: the base layer has no key here"))?;
+                    for expr in [base, expr] {
+                        checker
+                            .check(expr)
+                            .and_then(|_| self.check_key(expr))
+                            .map_err(|e| format!("{context}: {e}"))?;
+                    }
+                }
+            }
+        }
+        for (i, def) in self.text_macros.iter().enumerate() {
+            for step in &def.steps {
+                if let TextMacroStepDef::Tap(expr, _) = step {
+                    checker
+                        .check(expr)
+                        .and_then(|_| self.check_key(expr))
+                        .map_err(|e| format!("text_macros[{i}]: {e}"))?;
+                }
+            }
+        }
+        let size = encode_text_macros(&self.text_macros, |_| 0, |b| b).len();
+        if size > TEXT_MACROS_MAX_SIZE {
+            return Err(format!(
+                "text_macros: {size} bytes encoded, at most {TEXT_MACROS_MAX_SIZE} are supported"
+            ));
+        }
+        if self.text_macros.len() > 255 {
+            return Err("text_macros: at most 255 macros are supported".into());
+        }
+        if let Some(home_row_mods) = &self.home_row_mods {
+            for (row, col) in home_row_mods.keys.keys() {
+                let context = format!("home_row_mods, key {row},{col}");
+                let tap = self
+                    .layers
+                    .first()
+                    .and_then(|layer| layer.keys.get(&(*row, *col)))
+                    .ok_or(format!("{context}: the base layer has no key here"))?;
+                self.check_key(tap).map_err(|e| format!("{context}: {e}"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that `expr` is a plain key which is not a layer key, as required where the
+    /// master hooks see it as a key code.
+    fn check_key(&self, expr: &Expr) -> Result<(), String> {
+        self.check_plain(expr)?;
+        match self.resolve(expr) {
+            Expr::Call(func, _) if matches!(func.as_str(), "TG" | "MO") => {
+                Err(format!("`{expr}` is not a plain key"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that `expr` is a single key code, as required where rktk takes a `KeyCode`.
+    fn check_plain(&self, expr: &Expr) -> Result<(), String> {
+        match self.resolve(expr) {
+            Expr::Name(name) if name == "_____" => {
+                Err("a transparent key is not allowed here".into())
+            }
+            Expr::Call(func, _)
+                if matches!(func.as_str(), "TH" | "TD" | "OS" | "OSL" | "MACRO") =>
+            {
+                Err(format!("`{expr}` is not a plain key"))
+            }
+            Expr::Name(name) if HOOK_KEYS.contains(&name.as_str()) => {
+                Err(format!("`{expr}` is not a plain key"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Resolves aliases until a non-alias expression is reached.
+    pub fn resolve<'a>(&'a self, expr: &'a Expr) -> &'a Expr {
+        match expr {
+            Expr::Name(name) => match self.aliases.iter().find(|(n, _)| n == name) {
+                Some((_, aliased)) => self.resolve(aliased),
+                None => expr,
+            },
+            _ => expr,
+        }
+    }
+
+    /// Serializes the keymap back into the format of `keymap.json`.
+    #[allow(dead_code)]
+    pub fn to_json(&self) -> Value {
+        let opt = |e: &Option<Expr>| match e {
+            Some(e) => Value::String(e.to_string()),
+            None => Value::Null,
+        };
+
+        let aliases: serde_json::Map<_, _> = self
+            .aliases
+            .iter()
+            .map(|(name, expr)| (name.clone(), Value::String(expr.to_string())))
+            .collect();
+        let layers: Vec<_> = self
+            .layers
+            .iter()
+            .map(|layer| {
+                let mut value = serde_json::Map::new();
+                value.insert("name".into(), Value::String(layer.name.clone()));
+                if !layer.encoder.is_empty() {
+                    let encoder = layer
+                        .encoder
+                        .iter()
+                        .map(|(cw, ccw)| Value::Array(vec![opt(cw), opt(ccw)]))
+                        .collect();
+                    value.insert("encoder".into(), Value::Array(encoder));
+                }
+                let keys = layer
+                    .keys
+                    .iter()
+                    .map(|((row, col), expr)| (format!("{row},{col}"), expr.to_string().into()))
+                    .collect();
+                value.insert("keys".into(), Value::Object(keys));
+                Value::Object(value)
+            })
+            .collect();
+        let tap_dance: Vec<_> = self
+            .tap_dance
+            .iter()
+            .map(|def| {
+                serde_json::json!({
+                    "tap": def.tap.iter().map(opt).collect::<Vec<_>>(),
+                    "hold": def.hold.iter().map(opt).collect::<Vec<_>>(),
+                })
+            })
+            .collect();
+
+        let combo: Vec<_> = self
+            .combo
+            .iter()
+            .map(|def| {
+                serde_json::json!({
+                    "src": def.src.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+                    "dst": def.dst.to_string(),
+                })
+            })
+            .collect();
+
+        let mut root = serde_json::json!({
+            "aliases": aliases,
+            "layers": layers,
+            "tap_dance": tap_dance,
+            "combo": combo,
+        });
+        if let Some(home_row_mods) = &self.home_row_mods {
+            root["home_row_mods"] = home_row_mods.to_json();
+        }
+        if let Some(oneshot) = &self.oneshot {
+            root["oneshot"] = oneshot.to_json();
+        }
+        if let Some(caps_word) = &self.caps_word {
+            root["caps_word"] = caps_word.to_json();
+        }
+        if let Some(leader) = &self.leader {
+            root["leader"] = leader.to_json();
+        }
+        if !self.text_macros.is_empty() {
+            let text_macros = self.text_macros.iter().map(|m| m.to_json()).collect();
+            root["text_macros"] = Value::Array(text_macros);
+        }
+        if !self.key_overrides.is_empty() {
+            let key_overrides = self.key_overrides.iter().map(|o| o.to_json()).collect();
+            root["key_overrides"] = Value::Array(key_overrides);
+        }
+        if let Some(base_layouts) = &self.base_layouts {
+            root["base_layouts"] = base_layouts.to_json();
+        }
+        if let Some(os_profile) = &self.os_profile {
+            root["os_profile"] = os_profile.to_json();
+        }
+        if let Some(dynamic_macros) = &self.dynamic_macros {
+            root["dynamic_macros"] = dynamic_macros.to_json();
+        }
+        root
+    }
+
+    /// Generates the Rust source of `KEYMAP`, to be included in `src/keymap.rs`.
+    pub fn generate(&self, limits: &Limits) -> Result<String, String> {
+        if self.tap_dance.len() > limits.tap_dance_max_definitions {
+            return Err(format!(
+                "{} tap dances are defined but `tap_dance_max_definitions` in rktk.json is {}",
+                self.tap_dance.len(),
+                limits.tap_dance_max_definitions
+            ));
+        }
+        for (i, def) in self.tap_dance.iter().enumerate() {
+            if def.tap.len() > limits.tap_dance_max_repeats
+                || def.hold.len() > limits.tap_dance_max_repeats
+            {
+                return Err(format!(
+                    "tap_dance[{i}] is longer than `tap_dance_max_repeats` ({}) in rktk.json",
+                    limits.tap_dance_max_repeats
+                ));
+            }
+        }
+
+        if self.combo.len() > limits.combo_key_max_definitions {
+            return Err(format!(
+                "{} combos are defined but `combo_key_max_definitions` in rktk.json is {}",
+                self.combo.len(),
+                limits.combo_key_max_definitions
+            ));
+        }
+        for (i, def) in self.combo.iter().enumerate() {
+            if def.src.len() > limits.combo_key_max_sources {
+                return Err(format!(
+                    "combo[{i}] has more sources than `combo_key_max_sources` ({}) in rktk.json",
+                    limits.combo_key_max_sources
+                ));
+            }
+        }
+
+        let mut out = String::new();
+        writeln!(
+            out,
+            "// Generated by build.rs from keymap.json. Do not edit."
+        )
+        .unwrap();
+        writeln!(out).unwrap();
+
+        for (name, expr) in &self.aliases {
+            writeln!(out, "const {name}: KeyAction = {};", expr.to_rust()).unwrap();
+        }
+        writeln!(out).unwrap();
+
+        for (i, layer) in self.layers.iter().enumerate() {
+            writeln!(out, "#[rustfmt::skip]").unwrap();
+            writeln!(out, "/// {}", layer.name).unwrap();
+            writeln!(out, "const L{i}: LayerKeymap = [").unwrap();
+            for row in 0..limits.rows {
+                let keys: Vec<_> = (0..limits.cols)
+                    .map(|col| {
+                        layer
+                            .keys
+                            .get(&(row, col))
+                            .map(Expr::to_rust)
+                            .unwrap_or_else(|| "_____".to_string())
+                    })
+                    .collect();
+                writeln!(out, "    [ {} ],", keys.join(", ")).unwrap();
+            }
+            writeln!(out, "];").unwrap();
+            writeln!(out).unwrap();
+        }
+
+        let opt_kc = |expr: &Option<Expr>| match expr {
+            Some(expr) => format!("Some(kc({}))", expr.to_rust()),
+            None => "None".to_string(),
+        };
+        let padded = |list: &[Option<Expr>]| {
+            let mut items: Vec<_> = list.iter().map(opt_kc).collect();
+            items.resize(limits.tap_dance_max_repeats, "None".to_string());
+            items.join(", ")
+        };
+
+        writeln!(out, "pub const KEYMAP: Keymap = Keymap {{").unwrap();
+        writeln!(out, "    layers: [").unwrap();
+        for (i, layer) in self.layers.iter().enumerate() {
+            writeln!(out, "        Layer {{").unwrap();
+            writeln!(out, "            keymap: L{i},").unwrap();
+            if !layer.encoder.is_empty() {
+                let encoders: Vec<_> = layer
+                    .encoder
+                    .iter()
+                    .map(|(cw, ccw)| format!("({}, {})", opt_kc(cw), opt_kc(ccw)))
+                    .collect();
+                writeln!(out, "            encoder_keys: [{}],", encoders.join(", ")).unwrap();
+            }
+            writeln!(out, "            ..Layer::const_default()").unwrap();
+            writeln!(out, "        }},").unwrap();
+        }
+        writeln!(out, "    ],").unwrap();
+
+        writeln!(out, "    tap_dance: [").unwrap();
+        for def in &self.tap_dance {
+            writeln!(
+                out,
+                "        Some(TapDanceDefinition {{ tap: [{}], hold: [{}] }}),",
+                padded(&def.tap),
+                padded(&def.hold)
+            )
+            .unwrap();
+        }
+        for _ in self.tap_dance.len()..limits.tap_dance_max_definitions {
+            writeln!(out, "        None,").unwrap();
+        }
+        writeln!(out, "    ],").unwrap();
+        writeln!(out, "    combo: [").unwrap();
+        for def in &self.combo {
+            let mut src: Vec<_> = def
+                .src
+                .iter()
+                .map(|e| format!("Some(kc({}))", e.to_rust()))
+                .collect();
+            src.resize(limits.combo_key_max_sources, "None".to_string());
+            writeln!(
+                out,
+                "        Some(ComboDefinition {{ src: [{}], dst: kc({}) }}),",
+                src.join(", "),
+                def.dst.to_rust()
+            )
+            .unwrap();
+        }
+        for _ in self.combo.len()..limits.combo_key_max_definitions {
+            writeln!(out, "        None,").unwrap();
+        }
+        writeln!(out, "    ],").unwrap();
+        writeln!(out, "}};").unwrap();
+        writeln!(out).unwrap();
+
+        self.generate_home_row_mods(&mut out, limits);
+        writeln!(out).unwrap();
+        self.generate_oneshot(&mut out, limits)?;
+        writeln!(out).unwrap();
+        self.generate_caps_word(&mut out);
+        writeln!(out).unwrap();
+        self.generate_leader(&mut out);
+        writeln!(out).unwrap();
+        self.generate_key_overrides(&mut out);
+        writeln!(out).unwrap();
+        self.generate_base_layouts(&mut out);
+        writeln!(out).unwrap();
+        self.generate_os_profile(&mut out);
+        writeln!(out).unwrap();
+        self.generate_host_layout(&mut out);
+        writeln!(out).unwrap();
+        self.generate_dynamic_macros(&mut out);
+        writeln!(out).unwrap();
+        self.generate_text_macros(&mut out);
+
+        Ok(out)
+    }
+
+    /// The key at `position` while `layer` is the highest active layer, assuming that
+    /// transparent keys fall through to the layers below it.
+    pub fn effective_key(&self, layer: usize, position: (usize, usize)) -> Option<&Expr> {
+        self.layers[..=layer]
+            .iter()
+            .rev()
+            .filter_map(|l| l.keys.get(&position))
+            .map(|expr| self.resolve(expr))
+            .find(|expr| !matches!(expr, Expr::Name(name) if name == "_____"))
+    }
+
+    /// Positions with a key on any layer.
+    fn positions(&self) -> BTreeSet<(usize, usize)> {
+        self.layers
+            .iter()
+            .flat_map(|l| l.keys.keys().copied())
+            .collect()
+    }
+
+    fn generate_oneshot(&self, out: &mut String, limits: &Limits) -> Result<(), String> {
+        let def = self.oneshot.as_ref().unwrap_or(&DEFAULT_ONESHOT);
+        let positions = self.positions();
+
+        let mut keys = Vec::new();
+        for layer in 0..self.layers.len() {
+            for &(row, col) in &positions {
+                let kind = match self.effective_key(layer, (row, col)) {
+                    Some(Expr::Call(func, args)) => match (func.as_str(), args.as_slice()) {
+                        ("OS", [Expr::Name(m)]) => {
+                            let bit = MODIFIERS.iter().find(|(name, _)| name == m).unwrap().1;
+                            format!("OneShotKind::Modifier({bit:#04x})")
+                        }
+                        ("OSL", [Expr::Number(n)]) => format!("OneShotKind::Layer({n})"),
+                        _ => continue,
+                    },
+                    _ => continue,
+                };
+                keys.push(format!(
+                    "OneShotKey {{ layer: {layer}, row: {row}, col: {col}, kind: {kind} }}"
+                ));
+            }
+        }
+        if !keys.is_empty() && limits.oneshot_state_size == 0 {
+            return Err(
+                "one-shot keys are used but `oneshot_state_size` in rktk.json is 0".to_string(),
+            );
+        }
+
+        let cancel: Vec<_> = positions
+            .iter()
+            .filter(|_| def.cancel_on_esc)
+            .filter(|&&p| matches!(self.effective_key(0, p), Some(Expr::Name(n)) if n == "ESC"))
+            .map(|(row, col)| format!("({row}, {col})"))
+            .collect();
+
+        writeln!(out, "pub const ONESHOT: OneShotConfig = OneShotConfig {{").unwrap();
+        writeln!(out, "    keys: &[").unwrap();
+        for key in keys {
+            writeln!(out, "        {key},").unwrap();
+        }
+        writeln!(out, "    ],").unwrap();
+        writeln!(out, "    timeout_ms: {},", def.timeout).unwrap();
+        writeln!(out, "    cancel: &[{}],", cancel.join(", ")).unwrap();
+        writeln!(out, "}};").unwrap();
+        Ok(())
+    }
+
+    /// `(layer, row, col)` of the key named `name` on each layer, for the config of a key
+    /// handled by the master hooks.
+    fn hook_key_positions(&self, name: &str) -> String {
+        let positions = self.positions();
+        let mut keys = Vec::new();
+        for layer in 0..self.layers.len() {
+            for &(row, col) in &positions {
+                if matches!(self.effective_key(layer, (row, col)), Some(Expr::Name(n)) if n == name)
+                {
+                    keys.push(format!("({layer}, {row}, {col})"));
+                }
+            }
+        }
+        keys.join(", ")
+    }
+
+    fn generate_caps_word(&self, out: &mut String) {
+        let def = self.caps_word.as_ref().unwrap_or(&DEFAULT_CAPS_WORD);
+        writeln!(
+            out,
+            "pub const CAPS_WORD: CapsWordConfig = CapsWordConfig {{"
+        )
+        .unwrap();
+        writeln!(out, "    keys: &[{}],", self.hook_key_positions(CAPS_WORD)).unwrap();
+        writeln!(out, "    idle_timeout_ms: {},", def.idle_timeout).unwrap();
+        writeln!(out, "}};").unwrap();
+    }
+
+    fn generate_leader(&self, out: &mut String) {
+        let empty = LeaderDef {
+            timeout: DEFAULT_LEADER_TIMEOUT,
+            sequences: Vec::new(),
+        };
+        let def = self.leader.as_ref().unwrap_or(&empty);
+        writeln!(out, "pub const LEADER: LeaderConfig = LeaderConfig {{").unwrap();
+        writeln!(out, "    keys: &[{}],", self.hook_key_positions(LEADER)).unwrap();
+        writeln!(out, "    timeout_ms: {},", def.timeout).unwrap();
+        writeln!(out, "    sequences: &[").unwrap();
+        for sequence in &def.sequences {
+            let keys: Vec<_> = sequence
+                .keys
+                .iter()
+                .map(|e| format!("hid({})", e.to_rust()))
+                .collect();
+            writeln!(
+                out,
+                "        LeaderSequence {{ keys: &[{}], text: {:?} }},",
+                keys.join(", "),
+                sequence.text
+            )
+            .unwrap();
+        }
+        writeln!(out, "    ],").unwrap();
+        writeln!(out, "}};").unwrap();
+    }
+
+    fn generate_key_overrides(&self, out: &mut String) {
+        writeln!(out, "pub const KEY_OVERRIDES: &[KeyOverride] = &[").unwrap();
+        for def in &self.key_overrides {
+            let (replacement, replacement_modifier) = match self.resolve(&def.replacement) {
+                Expr::Call(func, args) if func == "SF" => (&args[0], 0x02),
+                _ => (&def.replacement, 0),
+            };
+            let replacement_modifier = replacement_modifier | modifier_bits(&def.replacement_mods);
+            let layers = (0..self.layers.len())
+                .filter(|&l| def.applies_on(l))
+                .fold(0u32, |bits, l| bits | 1 << l);
+            writeln!(
+                out,
+                "    KeyOverride {{ modifiers: {:#04x}, key: hid({}), replacement: hid({}), replacement_modifier: {replacement_modifier:#04x}, layers: {layers:#b}, os: {} }},",
+                def.modifier_bits(),
+                def.key.to_rust(),
+                replacement.to_rust(),
+                def.os.as_ref().map_or("None".to_string(), |os| {
+                    let variant = OS_PROFILES.iter().find(|(n, _)| n == os).unwrap().1;
+                    format!("Some(Os::{variant})")
+                })
+            )
+            .unwrap();
+        }
+        writeln!(out, "];").unwrap();
+    }
+
+    fn generate_base_layouts(&self, out: &mut String) {
+        writeln!(
+            out,
+            "pub const BASE_LAYOUTS: BaseLayoutConfig = BaseLayoutConfig {{"
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "    keys: &[{}],",
+            self.hook_key_positions(BASE_LAYOUT)
+        )
+        .unwrap();
+        writeln!(out, "    layouts: &[").unwrap();
+        let base = self
+            .base_layouts
+            .as_ref()
+            .map_or(&self.layers[0].name, |b| &b.base);
+        writeln!(out, "        BaseLayout {{ name: {base:?}, keys: &[] }},").unwrap();
+        for layout in self.base_layouts.iter().flat_map(|b| &b.layouts) {
+            writeln!(out, "        BaseLayout {{").unwrap();
+            writeln!(out, "            name: {:?},", layout.name).unwrap();
+            writeln!(out, "            keys: &[").unwrap();
+            for (&(row, col), expr) in &layout.keys {
+                // Layers through which layer 0 shows at this position.
+                let layers = (0..self.layers.len())
+                    .take_while(|&l| {
+                        l == 0
+                            || self.layers[l].keys.get(&(row, col)).is_none_or(
+                                |e| matches!(self.resolve(e), Expr::Name(n) if n == "_____"),
+                            )
+                    })
+                    .fold(0u32, |bits, l| bits | 1 << l);
+                writeln!(
+                    out,
+                    "                BaseKey {{ row: {row}, col: {col}, layers: {layers:#b}, from: hid({}), to: hid({}) }},",
+                    self.layers[0].keys[&(row, col)].to_rust(),
+                    expr.to_rust()
+                )
+                .unwrap();
+            }
+            writeln!(out, "            ],").unwrap();
+            writeln!(out, "        }},").unwrap();
+        }
+        writeln!(out, "    ],").unwrap();
+        writeln!(out, "}};").unwrap();
+    }
+
+    fn generate_os_profile(&self, out: &mut String) {
+        // Each modifier bit is sent as itself unless the profile maps it.
+        let bit = |name: &String| MODIFIERS.iter().find(|(n, _)| n == name).unwrap().1;
+        let mut mac: Vec<u8> = (0..8).map(|bit| 1 << bit).collect();
+        for (from, to) in self.os_profile.iter().flat_map(|p| &p.mac) {
+            mac[bit(from).trailing_zeros() as usize] = bit(to);
+        }
+        let mac: Vec<_> = mac.iter().map(|bits| format!("{bits:#04x}")).collect();
+        writeln!(
+            out,
+            "pub const OS_PROFILE: OsProfileConfig = OsProfileConfig {{"
+        )
+        .unwrap();
+        writeln!(out, "    keys: &[{}],", self.hook_key_positions(OS_PROFILE)).unwrap();
+        writeln!(out, "    mac_modifiers: [{}],", mac.join(", ")).unwrap();
+        writeln!(out, "}};").unwrap();
+    }
+
+    fn generate_host_layout(&self, out: &mut String) {
+        writeln!(
+            out,
+            "pub const HOST_LAYOUT: HostLayoutConfig = HostLayoutConfig {{"
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "    keys: &[{}],",
+            self.hook_key_positions(HOST_LAYOUT)
+        )
+        .unwrap();
+        writeln!(out, "}};").unwrap();
+    }
+
+    fn generate_dynamic_macros(&self, out: &mut String) {
+        let def = self
+            .dynamic_macros
+            .as_ref()
+            .unwrap_or(&DEFAULT_DYNAMIC_MACROS);
+        let slots = |names: [&str; 2]| {
+            names
+                .map(|name| format!("&[{}]", self.hook_key_positions(name)))
+                .join(", ")
+        };
+        writeln!(
+            out,
+            "pub const DYNAMIC_MACROS: DynamicMacroConfig = DynamicMacroConfig {{"
+        )
+        .unwrap();
+        writeln!(out, "    record: [{}],", slots(DM_REC)).unwrap();
+        writeln!(out, "    stop: &[{}],", self.hook_key_positions(DM_RSTP)).unwrap();
+        writeln!(out, "    play: [{}],", slots(DM_PLY)).unwrap();
+        writeln!(out, "    persist: {},", def.persist).unwrap();
+        writeln!(out, "}};").unwrap();
+    }
+
+    fn generate_text_macros(&self, out: &mut String) {
+        writeln!(
+            out,
+            "pub const TEXT_MACROS: TextMacroConfig = TextMacroConfig {{"
+        )
+        .unwrap();
+        writeln!(out, "    macro_at: text_macro_at,").unwrap();
+        writeln!(out, "    defaults: &[").unwrap();
+        writeln!(
+            out,
+            "        {TEXT_MACROS_VERSION}, {},",
+            self.text_macros.len()
+        )
+        .unwrap();
+        for def in &self.text_macros {
+            let bytes = def.encode(|e| format!("hid({})", e.to_rust()), |b| b.to_string());
+            writeln!(out, "        // {}", def.name).unwrap();
+            writeln!(out, "        {},", bytes.join(", ")).unwrap();
+        }
+        writeln!(out, "    ],").unwrap();
+        writeln!(out, "}};").unwrap();
+    }
+
+    fn generate_home_row_mods(&self, out: &mut String, limits: &Limits) {
+        let empty = HomeRowModsDef {
+            tapping_term: DEFAULT_TAPPING_TERM,
+            hold_on_other_key_press: false,
+            permissive_hold: false,
+            same_hand_roll: false,
+            keys: BTreeMap::new(),
+        };
+        let def = self.home_row_mods.as_ref().unwrap_or(&empty);
+
+        writeln!(
+            out,
+            "pub const HOME_ROW_MODS: HomeRowModsConfig = HomeRowModsConfig {{"
+        )
+        .unwrap();
+        writeln!(out, "    keys: &[").unwrap();
+        for ((row, col), key) in &def.keys {
+            let modifier = MODIFIERS
+                .iter()
+                .find(|(name, _)| *name == key.hold)
+                .map(|(_, bit)| *bit)
+                .unwrap();
+            writeln!(
+                out,
+                "        HomeRowMod {{ row: {row}, col: {col}, modifier: {modifier:#04x}, tapping_term_ms: {} }},",
+                key.tapping_term.unwrap_or(def.tapping_term)
+            )
+            .unwrap();
+        }
+        writeln!(out, "    ],").unwrap();
+        writeln!(
+            out,
+            "    hold_on_other_key_press: {},",
+            def.hold_on_other_key_press
+        )
+        .unwrap();
+        writeln!(out, "    permissive_hold: {},", def.permissive_hold).unwrap();
+        writeln!(out, "    same_hand_roll: {},", def.same_hand_roll).unwrap();
+        writeln!(out, "    chord_window_ms: {},", limits.combo_threshold).unwrap();
+        writeln!(out, "}};").unwrap();
+    }
+}
@@ -1,7 +1,12 @@
 MEMORY
 {
   /* for softdevice v6 */
-  FLASH : ORIGIN = 0x00026000, LENGTH = 828K
+  FLASH : ORIGIN = 0x00026000, LENGTH = 796K
+  /* Persistent storage of every build. See `src/storage.rs`. */
+  STORAGE : ORIGIN = 0x000ED000, LENGTH = 32K
   /* RAM MAX: 256K (0x40000) */
   RAM : ORIGIN = 0x20008000, LENGTH = 0x38000
 }
+
+__storage_start = ORIGIN(STORAGE);
+__storage_end = ORIGIN(STORAGE) + LENGTH(STORAGE);
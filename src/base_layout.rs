@@ -0,0 +1,157 @@
+//! Base layouts selectable at runtime, applied to the keyboard reports of the key manager.
+//!
+//! Layer 0 of the keymap is the first layout. The others are defined in the `base_layouts`
+//! section of `keymap.json` by the keys they change, and compile into a [`BaseLayout`] each.
+//! When a key of layer 0 is pressed, [`BaseLayouts`] remembers what the selected layout sends
+//! at its position, and replaces the key code in the reports until the key is released and
+//! has left the report. The key manager holds back the press of a combo source, so a quick
+//! tap of one only reaches the report after its release. The other layers are left alone,
+//! except where they are transparent down to layer 0.
+//!
+//! The `BASE_LAYOUT` key selects the next layout. The master hooks keep the selection in
+//! storage and show its name on the display.
+
+use crate::key_events::{KeyEvent, KeyboardState};
+
+/// Keys of layer 0 which can be held at once and still be translated.
+const MAX_HELD: usize = 10;
+
+#[derive(Debug, Clone, Copy)]
+pub struct BaseKey {
+    pub row: u8,
+    pub col: u8,
+    /// Bit `n` is set if layer 0 shows through at this position while `n` is the highest
+    /// active layer.
+    pub layers: u32,
+    /// HID key code of layer 0.
+    pub from: u8,
+    /// HID key code of this layout.
+    pub to: u8,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BaseLayout {
+    pub name: &'static str,
+    /// Keys which differ from layer 0.
+    pub keys: &'static [BaseKey],
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BaseLayoutConfig {
+    /// `(layer, row, col)` of the keys selecting the next layout, with `layer` the highest
+    /// active layer.
+    pub keys: &'static [(u8, u8, u8)],
+    /// Layer 0 first, with no keys.
+    pub layouts: &'static [BaseLayout],
+}
+
+#[derive(Debug, Clone, Copy)]
+struct HeldKey {
+    key: BaseKey,
+    released: bool,
+}
+
+pub struct BaseLayouts {
+    config: BaseLayoutConfig,
+    layer: u8,
+    selected: usize,
+    /// Keys translated until they are released and a report no longer holds them.
+    held: [Option<HeldKey>; MAX_HELD],
+}
+
+impl BaseLayouts {
+    pub const fn new(config: BaseLayoutConfig) -> Self {
+        assert!(
+            !config.layouts.is_empty() && config.layouts[0].keys.is_empty(),
+            "keymap.json: the first base layout must be layer 0"
+        );
+        Self {
+            config,
+            layer: 0,
+            selected: 0,
+            held: [None; MAX_HELD],
+        }
+    }
+
+    pub fn set_layer(&mut self, highest_layer: u8) {
+        self.layer = highest_layer;
+    }
+
+    /// Index of the selected layout.
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Selects a layout, e.g. the one read from storage. Out of range indices select layer 0.
+    pub fn select(&mut self, index: usize) {
+        self.selected = if index < self.config.layouts.len() {
+            index
+        } else {
+            0
+        };
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.config.layouts[self.selected].name
+    }
+
+    /// Feeds a key event passed to the key manager.
+    pub fn key_event(&mut self, event: KeyEvent) {
+        let at_event = |h: &HeldKey| (h.key.row, h.key.col) == (event.row, event.col);
+        if !event.pressed {
+            for held in self.held.iter_mut().flatten() {
+                if at_event(held) {
+                    held.released = true;
+                }
+            }
+            return;
+        }
+        for held in &mut self.held {
+            if held.is_some_and(|h| at_event(&h)) {
+                *held = None;
+            }
+        }
+
+        if self
+            .config
+            .keys
+            .contains(&(self.layer, event.row, event.col))
+        {
+            self.select(self.selected + 1);
+            return;
+        }
+
+        let layer = self.layer;
+        let key = self.config.layouts[self.selected].keys.iter().find(|k| {
+            (k.row, k.col) == (event.row, event.col) && layer < 32 && k.layers & (1 << layer) != 0
+        });
+        if let (Some(key), Some(slot)) = (key, self.held.iter_mut().find(|h| h.is_none())) {
+            *slot = Some(HeldKey {
+                key: *key,
+                released: false,
+            });
+        }
+    }
+
+    /// Translates the keys of layer 0 in a keyboard report, and forgets the released keys
+    /// which are not in it.
+    pub fn report(&mut self, report: &mut KeyboardState) {
+        let mut used = [false; MAX_HELD];
+        for keycode in &mut report.keycodes {
+            // Each slot is looked up with the code of the key manager, so that a translated
+            // key is never translated again.
+            let held = self.held.iter().zip(&mut used).find(|(held, used)| {
+                !**used && held.is_some_and(|h| h.key.from == *keycode && *keycode != 0)
+            });
+            if let Some((Some(held), used)) = held {
+                *used = true;
+                *keycode = held.key.to;
+            }
+        }
+        for (held, used) in self.held.iter_mut().zip(used) {
+            if !used && held.is_some_and(|h| h.released) {
+                *held = None;
+            }
+        }
+    }
+}
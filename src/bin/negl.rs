@@ -0,0 +1,169 @@
+#![no_std]
+#![no_main]
+#![feature(impl_trait_in_assoc_type)]
+
+use embassy_executor::Spawner;
+use embassy_nrf::usb::vbus_detect::SoftwareVbusDetect;
+use negl_nrf52840::{
+    board::{self, Board, Irqs},
+    *,
+};
+use once_cell::sync::OnceCell;
+use rktk::{
+    config::new_rktk_opts,
+    drivers::{dummy, Drivers},
+};
+use rktk_drivers_common::usb::{CommonUsbDriverConfig, CommonUsbReporterBuilder, UsbDriverConfig};
+
+static SOFTWARE_VBUS: OnceCell<SoftwareVbusDetect> = OnceCell::new();
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    let p = negl_nrf52840::init_peri();
+    let mut board = Board::new(p);
+
+    let hand = match misc::stored_hand() {
+        Some(hand) => Some(hand),
+        None => {
+            let mut held = None;
+            board
+                .held_keys(|row, col| held = held.or(misc::hand_of_held_key(row, col)))
+                .await;
+            if let Some(hand) = held {
+                misc::store_hand(hand);
+            }
+            held
+        }
+    };
+    let Some(hand) = hand else {
+        let mut display = board.display.create().await;
+        misc::show_unknown_hand(&mut display).await;
+        return;
+    };
+    misc::set_hand(hand);
+
+    match misc::detect_role() {
+        misc::Role::Master => master(board, spawner).await,
+        misc::Role::Slave => slave(board, spawner).await,
+    }
+}
+
+#[cfg_attr(not(feature = "trouble"), allow(unused_variables))]
+async fn master(board: Board, spawner: Spawner) {
+    // create shared SPI bus
+    // NOTE: This must be done as soon as possible, otherwise the SPI device will start acting strangely.
+    let spi = board.spi.create();
+
+    #[cfg(feature = "trouble")]
+    let mpsl = init_mpsl(spawner, board.ble.mpsl);
+
+    #[cfg(feature = "trouble")]
+    let trouble_ble_reporter = {
+        use rand_chacha::{rand_core::SeedableRng as _, ChaCha12Rng};
+        use rktk::singleton;
+        use rktk_drivers_common::trouble::reporter::{
+            TroubleReporterBuilder, TroubleReporterConfig,
+        };
+
+        let mut rng = singleton!(
+            embassy_nrf::rng::Rng::new(board.ble.rng, Irqs),
+            embassy_nrf::rng::Rng<embassy_nrf::peripherals::RNG>
+        );
+        let rng_2 = singleton!(ChaCha12Rng::from_rng(&mut rng).unwrap(), ChaCha12Rng);
+        let sdc = build_sdc(board.ble.sdc, rng, mpsl);
+        TroubleReporterBuilder::<_, _, 1, 5, 72>::new(
+            sdc.unwrap(),
+            rng_2,
+            TroubleReporterConfig {
+                advertise_name: "negL Trouble",
+                peripheral_config: None,
+            },
+        )
+    };
+
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "sd")] {
+            let (ble_builder, flash) = init_sd().await;
+            let ble_builder = Some(ble_builder);
+            let storage = storage::create_sd_storage(flash);
+        } else if #[cfg(feature = "trouble")] {
+            let ble_builder = Some(trouble_ble_reporter);
+            let storage = storage::create_mpsl_storage(mpsl, board.nvmc);
+        } else {
+            let ble_builder = dummy::ble_builder();
+            let storage = storage::create_nvmc_storage(board.nvmc);
+        }
+    }
+
+    let usb = {
+        let vbus = SOFTWARE_VBUS.get_or_init(|| SoftwareVbusDetect::new(true, true));
+        let embassy_driver = embassy_nrf::usb::Driver::new(board.usb, Irqs, vbus);
+        let mut driver_config = UsbDriverConfig::new(0xc0de, 0xcafe);
+        driver_config.product = Some("negL");
+        let opts = CommonUsbDriverConfig::new(embassy_driver, driver_config);
+        Some(CommonUsbReporterBuilder::new(opts))
+    };
+
+    storage::schema::migrate(&storage).await;
+
+    let drivers = Drivers {
+        keyscan: board.keyscan.create(&spi),
+        system: board.system.create(),
+        mouse: Some(board.mouse.create(&spi)),
+        usb_builder: usb,
+        display: Some(board.display.create().await),
+        split: Some(board.split.create()),
+        rgb: Some(board.rgb.create()),
+        storage: Some(storage::SharedStorage(&storage)),
+        ble_builder,
+        debounce: Some(board::create_debounce()),
+        encoder: Some(board.encoder.create()),
+    };
+
+    rktk::task::start(
+        drivers,
+        hooks::create_hooks(board.led_off, &storage),
+        new_rktk_opts(&keymap::KEYMAP, misc::hand()),
+    )
+    .await;
+}
+
+#[cfg_attr(not(feature = "trouble"), allow(unused_variables))]
+async fn slave(board: Board, spawner: Spawner) {
+    let spi = board.spi.create();
+
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "sd")] {
+            let (_, flash) = init_sd().await;
+            let storage = storage::create_sd_storage(flash);
+        } else if #[cfg(feature = "trouble")] {
+            let mpsl = init_mpsl(spawner, board.ble.mpsl);
+            let storage = storage::create_mpsl_storage(mpsl, board.nvmc);
+        } else {
+            let storage = storage::create_nvmc_storage(board.nvmc);
+        }
+    }
+
+    storage::schema::migrate(&storage).await;
+
+    let drivers = Drivers {
+        keyscan: board.keyscan.create(&spi),
+        system: board.system.create(),
+        mouse: Some(board.mouse.create(&spi)),
+        usb_builder: dummy::usb_builder(),
+        display: Some(board.display.create().await),
+        split: Some(board.split.create()),
+        rgb: Some(board.rgb.create()),
+        storage: Some(storage::SharedStorage(&storage)),
+        ble_builder: dummy::ble_builder(),
+        debounce: Some(board::create_debounce()),
+        encoder: Some(board.encoder.create()),
+    };
+
+    rktk::task::start(
+        drivers,
+        hooks::create_hooks(board.led_off, &storage),
+        new_rktk_opts(&keymap::KEYMAP, misc::hand()),
+    )
+    .await;
+}
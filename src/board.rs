@@ -0,0 +1,432 @@
+//! Pin assignments and driver constructors of the negL PCB.
+//!
+//! [`Board::new`] splits [`Peripherals`] into one resource struct per driver, using the
+//! [`pins::PIN_MAP`] of the selected PCB revision. Each struct has a `create` method which
+//! builds the driver, so the binary only decides which drivers to use.
+
+pub mod pins;
+
+use embassy_embedded_hal::shared_bus::asynch::spi::SpiDevice;
+use embassy_nrf::{
+    bind_interrupts,
+    buffered_uarte::BufferedUarte,
+    gpio::{AnyPin, Input, Level, Output, OutputDrive, Pull},
+    peripherals::{NVMC, PPI_CH0, PPI_CH1, PPI_GROUP0, PWM0, SPI2, TIMER1, TWISPI0, UARTE0, USBD},
+    spim::Spim,
+    twim::Twim,
+    Peripherals,
+};
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex};
+use rktk::{
+    drivers::interface::{
+        debounce::DebounceDriver, display::DisplayDriver, encoder::EncoderDriver,
+        keyscan::KeyscanDriver, mouse::MouseDriver, rgb::RgbDriver, split::SplitDriver,
+        system::SystemDriver,
+    },
+    singleton,
+};
+use rktk_drivers_common::{
+    debounce::EagerDebounceDriver,
+    display::ssd1306::{self, Ssd1306Driver},
+    encoder::GeneralEncoder,
+    keyscan::shift_register_matrix::ShiftRegisterMatrix,
+    mouse::paw3395::Paw3395,
+    panic_utils,
+};
+use rktk_drivers_nrf::{
+    mouse::paw3395, rgb::ws2812_pwm::Ws2812Pwm, split::uart_full_duplex::UartFullDuplexSplitDriver,
+    system::NrfSystemDriver,
+};
+
+use crate::{config, misc};
+
+use self::pins::PIN_MAP;
+
+#[cfg(not(feature = "trouble"))]
+bind_interrupts!(pub struct Irqs {
+    USBD => embassy_nrf::usb::InterruptHandler<embassy_nrf::peripherals::USBD>;
+    SPI2 => embassy_nrf::spim::InterruptHandler<embassy_nrf::peripherals::SPI2>;
+    TWISPI0 => embassy_nrf::twim::InterruptHandler<embassy_nrf::peripherals::TWISPI0>;
+    UARTE0 => embassy_nrf::buffered_uarte::InterruptHandler<embassy_nrf::peripherals::UARTE0>;
+});
+
+#[cfg(feature = "trouble")]
+bind_interrupts!(pub struct Irqs {
+    USBD => embassy_nrf::usb::InterruptHandler<embassy_nrf::peripherals::USBD>;
+    SPI2 => embassy_nrf::spim::InterruptHandler<embassy_nrf::peripherals::SPI2>;
+    TWISPI0 => embassy_nrf::twim::InterruptHandler<embassy_nrf::peripherals::TWISPI0>;
+    UARTE0 => embassy_nrf::buffered_uarte::InterruptHandler<embassy_nrf::peripherals::UARTE0>;
+    RNG => embassy_nrf::rng::InterruptHandler<embassy_nrf::peripherals::RNG>;
+    EGU0_SWI0 => nrf_sdc::mpsl::LowPrioInterruptHandler;
+    CLOCK_POWER => nrf_sdc::mpsl::ClockInterruptHandler;
+    RADIO => nrf_sdc::mpsl::HighPrioInterruptHandler;
+    TIMER0 => nrf_sdc::mpsl::HighPrioInterruptHandler;
+    RTC0 => nrf_sdc::mpsl::HighPrioInterruptHandler;
+});
+
+/// Number of outputs of the shift register, which drives the columns of one half.
+const SHIFT_REGISTER_OUTPUTS: usize = 8;
+
+const _: () = assert!(
+    config::COLS == 2 * SHIFT_REGISTER_OUTPUTS,
+    "rktk.json: `constant.keyboard.cols` must be twice the number of shift register outputs"
+);
+const _: () = assert!(
+    config::ENCODER_COUNT == 1,
+    "rktk.json: `constant.keyboard.encoder_count` does not match the encoders on the board"
+);
+
+/// Number of WS2812 LEDs chained on each half, which [`RgbResources`] drives.
+const LEFT_LEDS: usize = 37;
+const RIGHT_LEDS: usize = 34;
+
+const _: () = assert!(
+    config::LEFT_RGB_COUNT == LEFT_LEDS,
+    "rktk.json: `constant.keyboard.left_rgb_count` does not match the LEDs on the left half"
+);
+const _: () = assert!(
+    config::RIGHT_RGB_COUNT == RIGHT_LEDS,
+    "rktk.json: `constant.keyboard.right_rgb_count` does not match the LEDs on the right half"
+);
+
+/// SPI bus shared by the shift register and the trackball sensor.
+pub type SharedSpi = Mutex<ThreadModeRawMutex, Spim<'static, SPI2>>;
+
+pub struct Board {
+    pub spi: SpiResources,
+    pub keyscan: KeyscanResources,
+    pub mouse: MouseResources,
+    pub split: SplitResources,
+    pub display: DisplayResources,
+    pub encoder: EncoderResources,
+    pub system: SystemResources,
+    pub rgb: RgbResources,
+    pub led_off: AnyPin,
+    pub usb: USBD,
+    pub nvmc: NVMC,
+    #[cfg(feature = "trouble")]
+    pub ble: BleResources,
+}
+
+impl Board {
+    pub fn new(p: Peripherals) -> Self {
+        // SAFETY: `p` is consumed here, so its pins are not used elsewhere, and `PIN_MAP` is
+        // checked for duplicates at compile time.
+        unsafe { Self::from_pin_map(p) }
+    }
+
+    unsafe fn from_pin_map(p: Peripherals) -> Self {
+        Self {
+            spi: SpiResources {
+                spi: p.SPI2,
+                sck: PIN_MAP.spi_sck.take(),
+                miso: PIN_MAP.spi_miso.take(),
+                mosi: PIN_MAP.spi_mosi.take(),
+            },
+            keyscan: KeyscanResources {
+                cs: PIN_MAP.shift_register_cs.take(),
+                rows: PIN_MAP.rows.map(|row| row.take()),
+            },
+            mouse: MouseResources {
+                cs: PIN_MAP.ball_cs.take(),
+            },
+            split: SplitResources {
+                uarte: p.UARTE0,
+                timer: p.TIMER1,
+                ppi_ch1: p.PPI_CH0,
+                ppi_ch2: p.PPI_CH1,
+                ppi_group: p.PPI_GROUP0,
+                rx: PIN_MAP.split_rx.take(),
+                tx: PIN_MAP.split_tx.take(),
+            },
+            display: DisplayResources {
+                twim: p.TWISPI0,
+                sda: PIN_MAP.display_sda.take(),
+                scl: PIN_MAP.display_scl.take(),
+            },
+            encoder: EncoderResources {
+                a: PIN_MAP.encoder_a.take(),
+                b: PIN_MAP.encoder_b.take(),
+            },
+            system: SystemResources {
+                vcc_cutoff: PIN_MAP.vcc_cutoff.take(),
+            },
+            rgb: RgbResources {
+                pwm: p.PWM0,
+                data: PIN_MAP.ws2812.take(),
+            },
+            led_off: PIN_MAP.led_off.take(),
+            usb: p.USBD,
+            nvmc: p.NVMC,
+            #[cfg(feature = "trouble")]
+            ble: BleResources {
+                rng: p.RNG,
+                mpsl: MpslResources {
+                    rtc0: p.RTC0,
+                    timer0: p.TIMER0,
+                    temp: p.TEMP,
+                    ppi_ch19: p.PPI_CH19,
+                    ppi_ch30: p.PPI_CH30,
+                    ppi_ch31: p.PPI_CH31,
+                },
+                sdc: SdcResources {
+                    ppi_ch17: p.PPI_CH17,
+                    ppi_ch18: p.PPI_CH18,
+                    ppi_ch20: p.PPI_CH20,
+                    ppi_ch21: p.PPI_CH21,
+                    ppi_ch22: p.PPI_CH22,
+                    ppi_ch23: p.PPI_CH23,
+                    ppi_ch24: p.PPI_CH24,
+                    ppi_ch25: p.PPI_CH25,
+                    ppi_ch26: p.PPI_CH26,
+                    ppi_ch27: p.PPI_CH27,
+                    ppi_ch28: p.PPI_CH28,
+                    ppi_ch29: p.PPI_CH29,
+                },
+            },
+        }
+    }
+}
+
+impl Board {
+    /// Calls `f` with the position in the matrix of each key held now, before the hand is
+    /// known. The SPI bus and the pins of the key scan are only borrowed, so their drivers can
+    /// be created afterwards.
+    pub async fn held_keys(&mut self, mut f: impl FnMut(usize, usize)) {
+        let spi = Mutex::new(Spim::new(
+            &mut self.spi.spi,
+            Irqs,
+            &mut self.spi.sck,
+            &mut self.spi.miso,
+            &mut self.spi.mosi,
+            spi_config(),
+        ));
+        let mut matrix = shift_register_matrix(
+            &spi,
+            Output::new(&mut self.keyscan.cs, Level::High, OutputDrive::Standard),
+            self.keyscan
+                .rows
+                .each_mut()
+                .map(|row| Input::new(row, Pull::Down)),
+        );
+        matrix
+            .scan(|event| {
+                if event.pressed {
+                    f(event.row as usize, event.col as usize);
+                }
+            })
+            .await;
+    }
+}
+
+pub struct SpiResources {
+    pub spi: SPI2,
+    pub sck: AnyPin,
+    pub miso: AnyPin,
+    pub mosi: AnyPin,
+}
+
+impl SpiResources {
+    /// NOTE: This must be called as soon as possible, otherwise the SPI device will start acting strangely.
+    pub fn create(self) -> SharedSpi {
+        Mutex::new(Spim::new(
+            self.spi,
+            Irqs,
+            self.sck,
+            self.miso,
+            self.mosi,
+            spi_config(),
+        ))
+    }
+}
+
+fn spi_config() -> embassy_nrf::spim::Config {
+    let mut spi_config = paw3395::recommended_spi_config();
+    spi_config.sck_drive = OutputDrive::Standard;
+    spi_config.mosi_drive = OutputDrive::Standard;
+    spi_config.frequency = embassy_nrf::spim::Frequency::K250;
+    spi_config
+}
+
+pub struct KeyscanResources {
+    pub cs: AnyPin,
+    /// ROW0 to ROW4
+    pub rows: [AnyPin; config::ROWS],
+}
+
+impl KeyscanResources {
+    pub fn create(self, spi: &SharedSpi) -> impl KeyscanDriver + '_ {
+        shift_register_matrix(
+            spi,
+            Output::new(self.cs, Level::High, OutputDrive::Standard),
+            self.rows.map(|row| Input::new(row, Pull::Down)),
+        )
+    }
+}
+
+fn shift_register_matrix<'a, 'd>(
+    spi: &'a Mutex<ThreadModeRawMutex, Spim<'d, SPI2>>,
+    cs: Output<'d>,
+    rows: [Input<'d>; config::ROWS],
+) -> impl KeyscanDriver + 'a
+where
+    'd: 'a,
+{
+    ShiftRegisterMatrix::<
+        _,
+        _,
+        _,
+        SHIFT_REGISTER_OUTPUTS,
+        { config::ROWS },
+        { config::ROWS },
+        { config::COLS / 2 },
+    >::new(
+        SpiDevice::new(spi, cs),
+        rows,
+        misc::translate_key_position,
+        None,
+    )
+}
+
+pub struct MouseResources {
+    pub cs: AnyPin,
+}
+
+impl MouseResources {
+    pub fn create(self, spi: &SharedSpi) -> impl MouseDriver + '_ {
+        let ball_cs = Output::new(self.cs, Level::High, OutputDrive::Standard);
+        let ball_spi_device = SpiDevice::new(spi, ball_cs);
+        Paw3395::new(ball_spi_device, misc::PAW3395_CONFIG)
+    }
+}
+
+pub struct SplitResources {
+    pub uarte: UARTE0,
+    pub timer: TIMER1,
+    pub ppi_ch1: PPI_CH0,
+    pub ppi_ch2: PPI_CH1,
+    pub ppi_group: PPI_GROUP0,
+    pub rx: AnyPin,
+    pub tx: AnyPin,
+}
+
+impl SplitResources {
+    pub fn create(self) -> impl SplitDriver {
+        let uarte_config = embassy_nrf::uarte::Config::default();
+        UartFullDuplexSplitDriver::new(BufferedUarte::new(
+            self.uarte,
+            self.timer,
+            self.ppi_ch1,
+            self.ppi_ch2,
+            self.ppi_group,
+            Irqs,
+            self.rx,
+            self.tx,
+            uarte_config,
+            singleton!([0; 256], [u8; 256]),
+            singleton!([0; 256], [u8; 256]),
+        ))
+    }
+}
+
+pub struct DisplayResources {
+    pub twim: TWISPI0,
+    pub sda: AnyPin,
+    pub scl: AnyPin,
+}
+
+impl DisplayResources {
+    pub async fn create(self) -> impl DisplayDriver {
+        let mut display = Ssd1306Driver::new(
+            Twim::new(
+                self.twim,
+                Irqs,
+                self.sda,
+                self.scl,
+                rktk_drivers_nrf::display::ssd1306::recommended_i2c_config(),
+            ),
+            ssd1306::prelude::DisplaySize128x32,
+            ssd1306::prelude::DisplayRotation::Rotate90,
+        );
+        panic_utils::display_message_if_panicked(&mut display).await;
+        display
+    }
+}
+
+pub struct EncoderResources {
+    pub a: AnyPin,
+    pub b: AnyPin,
+}
+
+impl EncoderResources {
+    pub fn create(self) -> impl EncoderDriver {
+        GeneralEncoder::new([(
+            Input::new(self.a, Pull::Down),
+            Input::new(self.b, Pull::Down),
+        )])
+    }
+}
+
+pub struct SystemResources {
+    pub vcc_cutoff: AnyPin,
+}
+
+impl SystemResources {
+    pub fn create(self) -> impl SystemDriver {
+        let vcc_cutoff = (
+            Output::new(self.vcc_cutoff, Level::High, OutputDrive::Standard),
+            Level::Low,
+        );
+        NrfSystemDriver::new(Some(vcc_cutoff))
+    }
+}
+
+pub struct RgbResources {
+    pub pwm: PWM0,
+    pub data: AnyPin,
+}
+
+impl RgbResources {
+    pub fn create(self) -> impl RgbDriver {
+        Ws2812Pwm::new(self.pwm, self.data)
+    }
+}
+
+#[cfg(feature = "trouble")]
+pub struct BleResources {
+    pub rng: embassy_nrf::peripherals::RNG,
+    pub mpsl: MpslResources,
+    pub sdc: SdcResources,
+}
+
+/// Peripherals of the MPSL, which both roles start to write flash. See [`crate::init_mpsl`].
+#[cfg(feature = "trouble")]
+pub struct MpslResources {
+    pub rtc0: embassy_nrf::peripherals::RTC0,
+    pub timer0: embassy_nrf::peripherals::TIMER0,
+    pub temp: embassy_nrf::peripherals::TEMP,
+    pub ppi_ch19: embassy_nrf::peripherals::PPI_CH19,
+    pub ppi_ch30: embassy_nrf::peripherals::PPI_CH30,
+    pub ppi_ch31: embassy_nrf::peripherals::PPI_CH31,
+}
+
+/// Peripherals of the SoftDevice Controller, only used by the master. See [`crate::build_sdc`].
+#[cfg(feature = "trouble")]
+pub struct SdcResources {
+    pub ppi_ch17: embassy_nrf::peripherals::PPI_CH17,
+    pub ppi_ch18: embassy_nrf::peripherals::PPI_CH18,
+    pub ppi_ch20: embassy_nrf::peripherals::PPI_CH20,
+    pub ppi_ch21: embassy_nrf::peripherals::PPI_CH21,
+    pub ppi_ch22: embassy_nrf::peripherals::PPI_CH22,
+    pub ppi_ch23: embassy_nrf::peripherals::PPI_CH23,
+    pub ppi_ch24: embassy_nrf::peripherals::PPI_CH24,
+    pub ppi_ch25: embassy_nrf::peripherals::PPI_CH25,
+    pub ppi_ch26: embassy_nrf::peripherals::PPI_CH26,
+    pub ppi_ch27: embassy_nrf::peripherals::PPI_CH27,
+    pub ppi_ch28: embassy_nrf::peripherals::PPI_CH28,
+    pub ppi_ch29: embassy_nrf::peripherals::PPI_CH29,
+}
+
+pub fn create_debounce() -> impl DebounceDriver {
+    EagerDebounceDriver::new(embassy_time::Duration::from_millis(10), true)
+}
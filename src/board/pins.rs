@@ -0,0 +1,150 @@
+//! Pin assignments of each PCB revision.
+//!
+//! A revision is selected with a `rev*` cargo feature, and the build fails unless exactly one
+//! is enabled. To support a new revision, add a [`PinMap`] constant for it, a matching feature,
+//! and count the feature in `SELECTED_REVISIONS`.
+
+use embassy_nrf::gpio::AnyPin;
+
+use crate::config;
+
+/// A GPIO identified by its port and number, e.g. `P1_04` is `Pin::new(1, 4)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pin {
+    port: u8,
+    pin: u8,
+}
+
+impl Pin {
+    pub const fn new(port: u8, pin: u8) -> Self {
+        assert!(port <= 1 && pin < 32, "nRF52840 has no such pin");
+        assert!(port == 0 || pin < 16, "nRF52840 has no such pin");
+        Self { port, pin }
+    }
+
+    const fn psel(self) -> u8 {
+        self.port * 32 + self.pin
+    }
+
+    /// # Safety
+    ///
+    /// The pin must not be used anywhere else. [`super::Board::new`] guarantees this by taking
+    /// all peripherals and checking that [`PinMap`] has no duplicates.
+    pub(super) unsafe fn take(self) -> AnyPin {
+        AnyPin::steal(self.psel())
+    }
+}
+
+pub struct PinMap {
+    pub spi_sck: Pin,
+    pub spi_miso: Pin,
+    pub spi_mosi: Pin,
+    /// Chip select of the shift register driving the matrix columns.
+    pub shift_register_cs: Pin,
+    /// ROW0 to ROW4
+    pub rows: [Pin; config::ROWS],
+    /// Chip select of the trackball sensor.
+    pub ball_cs: Pin,
+    pub split_rx: Pin,
+    pub split_tx: Pin,
+    pub display_sda: Pin,
+    pub display_scl: Pin,
+    pub encoder_a: Pin,
+    pub encoder_b: Pin,
+    pub ws2812: Pin,
+    pub vcc_cutoff: Pin,
+    pub led_off: Pin,
+}
+
+impl PinMap {
+    const fn all(&self) -> [Pin; 14 + config::ROWS] {
+        let others = [
+            self.spi_sck,
+            self.spi_miso,
+            self.spi_mosi,
+            self.shift_register_cs,
+            self.ball_cs,
+            self.split_rx,
+            self.split_tx,
+            self.display_sda,
+            self.display_scl,
+            self.encoder_a,
+            self.encoder_b,
+            self.ws2812,
+            self.vcc_cutoff,
+            self.led_off,
+        ];
+
+        let mut all = [self.spi_sck; 14 + config::ROWS];
+        let mut i = 0;
+        while i < all.len() {
+            all[i] = if i < others.len() {
+                others[i]
+            } else {
+                self.rows[i - others.len()]
+            };
+            i += 1;
+        }
+        all
+    }
+
+    const fn assert_unique(&self) {
+        let pins = self.all();
+        let mut i = 0;
+        while i < pins.len() {
+            let mut j = i + 1;
+            while j < pins.len() {
+                assert!(
+                    pins[i].psel() != pins[j].psel(),
+                    "a pin is assigned twice in the pin map"
+                );
+                j += 1;
+            }
+            i += 1;
+        }
+    }
+}
+
+/// First revision of the negL PCB.
+pub const REV1: PinMap = PinMap {
+    spi_sck: Pin::new(0, 17),
+    spi_miso: Pin::new(0, 22),
+    spi_mosi: Pin::new(0, 20),
+    shift_register_cs: Pin::new(1, 4),
+    rows: [
+        Pin::new(1, 15),
+        Pin::new(1, 13),
+        Pin::new(1, 11),
+        Pin::new(0, 10),
+        Pin::new(0, 9),
+    ],
+    ball_cs: Pin::new(1, 6),
+    split_rx: Pin::new(0, 8),
+    split_tx: Pin::new(0, 6),
+    display_sda: Pin::new(1, 0),
+    display_scl: Pin::new(0, 11),
+    encoder_a: Pin::new(0, 2),
+    encoder_b: Pin::new(0, 29),
+    ws2812: Pin::new(0, 24),
+    vcc_cutoff: Pin::new(0, 13),
+    led_off: Pin::new(0, 31),
+};
+
+/// Number of `rev*` features enabled. A new revision is counted here as well, so that the
+/// order of the branches below never decides between two enabled revisions.
+const SELECTED_REVISIONS: usize = cfg!(feature = "rev1") as usize;
+
+const _: () = assert!(
+    SELECTED_REVISIONS <= 1,
+    "More than one PCB revision is selected. Enable only one of the `rev*` features."
+);
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "rev1")] {
+        pub const PIN_MAP: PinMap = REV1;
+    } else {
+        compile_error!("No PCB revision is selected. Enable one of the `rev*` features.");
+    }
+}
+
+const _: () = PIN_MAP.assert_unique();
@@ -0,0 +1,103 @@
+//! Caps Word, applied to the keyboard reports of the key manager.
+//!
+//! The `CAPS_WORD` key toggles [`CapsWord`]. While it is on, Shift is added to reports whose
+//! keys are all letters or `-`, so that letters are capitalized and `-` becomes `_`. Digits,
+//! Backspace and Delete keep it on. It turns off on any other key, on a key pressed together
+//! with Ctrl, Alt or GUI, and after `idle_timeout_ms` without a key.
+
+use crate::key_events::KeyEvent;
+
+const SHIFT: u8 = 0x02 | 0x20;
+const MINUS: u8 = 0x2d;
+const BACKSPACE: u8 = 0x2a;
+const DELETE: u8 = 0x4c;
+
+#[derive(Debug, Clone, Copy)]
+pub struct CapsWordConfig {
+    /// `(layer, row, col)` of the Caps Word keys, with `layer` the highest active layer.
+    pub keys: &'static [(u8, u8, u8)],
+    /// 0 disables the timeout.
+    pub idle_timeout_ms: u32,
+}
+
+pub struct CapsWord {
+    config: CapsWordConfig,
+    layer: u8,
+    active: bool,
+    last_key_ms: u64,
+    /// Shift added to the current report.
+    shift: u8,
+}
+
+impl CapsWord {
+    pub const fn new(config: CapsWordConfig) -> Self {
+        Self {
+            config,
+            layer: 0,
+            active: false,
+            last_key_ms: 0,
+            shift: 0,
+        }
+    }
+
+    pub fn set_layer(&mut self, highest_layer: u8) {
+        self.layer = highest_layer;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Feeds a key event passed to the key manager, and toggles on a Caps Word key press.
+    pub fn key_event(&mut self, event: KeyEvent, now_ms: u64) {
+        if event.pressed
+            && self
+                .config
+                .keys
+                .contains(&(self.layer, event.row, event.col))
+        {
+            self.active = !self.active;
+            self.last_key_ms = now_ms;
+        }
+    }
+
+    /// Turns Caps Word off after the idle timeout. Call this on every scan.
+    pub fn tick(&mut self, now_ms: u64) {
+        let timeout = self.config.idle_timeout_ms as u64;
+        if self.active && timeout != 0 && now_ms.saturating_sub(self.last_key_ms) >= timeout {
+            self.active = false;
+        }
+    }
+
+    /// Feeds a new keyboard report. `modifier` includes modifiers added by other resolvers.
+    pub fn report(&mut self, modifier: u8, keycodes: &[u8], now_ms: u64) {
+        self.shift = 0;
+        let mut keys = keycodes.iter().copied().filter(|&k| k != 0).peekable();
+        if !self.active || keys.peek().is_none() {
+            return;
+        }
+        self.last_key_ms = now_ms;
+
+        if modifier & !SHIFT != 0 {
+            self.active = false;
+        } else if keys.clone().all(|k| is_letter(k) || k == MINUS) {
+            self.shift = 0x02;
+        } else if !keys.all(|k| is_letter(k) || is_word_key(k)) {
+            self.active = false;
+        }
+    }
+
+    /// Modifier bits to add to the current report.
+    pub fn modifiers(&self) -> u8 {
+        self.shift
+    }
+}
+
+fn is_letter(keycode: u8) -> bool {
+    (0x04..=0x1d).contains(&keycode)
+}
+
+/// Keys other than letters which do not end a word.
+fn is_word_key(keycode: u8) -> bool {
+    matches!(keycode, 0x1e..=0x27 | MINUS | BACKSPACE | DELETE)
+}
@@ -0,0 +1,64 @@
+//! Constants of `rktk.json`, generated by `build.rs`.
+//!
+//! The same facts are encoded in [`crate::keymap::KEYMAP`] and in the drivers of
+//! [`crate::board`]. They are checked against these constants here, so that editing only one
+//! of them fails the build instead of misbehaving at runtime.
+
+include!(concat!(env!("OUT_DIR"), "/config.rs"));
+
+const _: () = {
+    use crate::keymap::KEYMAP;
+
+    let mut i = 0;
+    while i < KEYMAP.layers.len() {
+        let layer = &KEYMAP.layers[i];
+        assert!(
+            layer.keymap.len() == ROWS,
+            "keymap.rs: a layer has a different number of rows than `constant.keyboard.rows` in rktk.json"
+        );
+        let mut row = 0;
+        while row < ROWS {
+            assert!(
+                layer.keymap[row].len() == COLS,
+                "keymap.rs: a row has a different number of columns than `constant.keyboard.cols` in rktk.json"
+            );
+            row += 1;
+        }
+        assert!(
+            layer.encoder_keys.len() == ENCODER_COUNT,
+            "keymap.rs: number of encoder keys differs from `constant.keyboard.encoder_count` in rktk.json"
+        );
+        i += 1;
+    }
+
+    assert!(
+        KEYMAP.tap_dance.len() == TAP_DANCE_MAX_DEFINITIONS,
+        "keymap.rs: number of tap dances differs from `constant.key_manager.tap_dance_max_definitions` in rktk.json"
+    );
+    let mut i = 0;
+    while i < KEYMAP.tap_dance.len() {
+        if let Some(tap_dance) = &KEYMAP.tap_dance[i] {
+            assert!(
+                tap_dance.tap.len() == TAP_DANCE_MAX_REPEATS
+                    && tap_dance.hold.len() == TAP_DANCE_MAX_REPEATS,
+                "keymap.rs: a tap dance has a different length than `constant.key_manager.tap_dance_max_repeats` in rktk.json"
+            );
+        }
+        i += 1;
+    }
+
+    assert!(
+        KEYMAP.combo.len() == COMBO_KEY_MAX_DEFINITIONS,
+        "keymap.rs: number of combos differs from `constant.key_manager.combo_key_max_definitions` in rktk.json"
+    );
+    let mut i = 0;
+    while i < KEYMAP.combo.len() {
+        if let Some(combo) = &KEYMAP.combo[i] {
+            assert!(
+                combo.src.len() == COMBO_KEY_MAX_SOURCES,
+                "keymap.rs: a combo has a different number of sources than `constant.key_manager.combo_key_max_sources` in rktk.json"
+            );
+        }
+        i += 1;
+    }
+};
@@ -0,0 +1,202 @@
+//! Dynamic macros, recorded from and played back into the keyboard reports for the host.
+//!
+//! [`DynamicMacros`] reacts to the `DM_REC1`/`DM_REC2`, `DM_RSTP` and `DM_PLY1`/`DM_PLY2`
+//! keys:
+//!
+//! - a record key starts recording its slot, or stops the recording if one is running
+//! - the stop key stops the recording
+//! - a play key sends the reports of its slot, one per scan, unless the slot is being
+//!   recorded
+//!
+//! While recording, every report for the host which differs from the previous one is kept,
+//! whichever half the keys are on, since the slave sends its keys to the master. Playback
+//! sends the same reports without the timing, and the report of the key manager afterwards.
+//! A recording which fills its slot stops by itself.
+//!
+//! The recordings live in fixed buffers of the master hooks rather than on the heap, so a
+//! long macro never competes with rktk for the 32 KiB heap set up in `init_peri`. With
+//! `persist`, the master hooks also keep them in storage, encoded by [`DynamicMacros::encode`].
+
+use crate::key_events::{KeyEvent, KeyboardState};
+
+pub const SLOTS: usize = 2;
+/// Reports a slot holds, including the release at its end.
+pub const MAX_REPORTS: usize = 256;
+/// Bytes of an encoded slot: the number of reports, then the modifier and the key codes of
+/// each report.
+pub const ENCODED_SIZE: usize = 2 + MAX_REPORTS * 7;
+
+const EMPTY: KeyboardState = KeyboardState {
+    modifier: 0,
+    keycodes: [0; 6],
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct DynamicMacroConfig {
+    /// `(layer, row, col)` of the keys recording each slot, with `layer` the highest active
+    /// layer.
+    pub record: [&'static [(u8, u8, u8)]; SLOTS],
+    /// `(layer, row, col)` of the keys stopping a recording.
+    pub stop: &'static [(u8, u8, u8)],
+    /// `(layer, row, col)` of the keys playing each slot.
+    pub play: [&'static [(u8, u8, u8)]; SLOTS],
+    /// Whether the master hooks keep the recordings in storage.
+    pub persist: bool,
+}
+
+struct Slot {
+    reports: [KeyboardState; MAX_REPORTS],
+    len: usize,
+}
+
+pub struct DynamicMacros {
+    config: DynamicMacroConfig,
+    layer: u8,
+    slots: [Slot; SLOTS],
+    recording: Option<usize>,
+    /// Slot whose recording stopped since the last [`Self::take_recorded`].
+    recorded: Option<usize>,
+    /// Slot and index of the next report to play.
+    playing: Option<(usize, usize)>,
+}
+
+impl DynamicMacros {
+    pub const fn new(config: DynamicMacroConfig) -> Self {
+        Self {
+            config,
+            layer: 0,
+            slots: [
+                Slot {
+                    reports: [EMPTY; MAX_REPORTS],
+                    len: 0,
+                },
+                Slot {
+                    reports: [EMPTY; MAX_REPORTS],
+                    len: 0,
+                },
+            ],
+            recording: None,
+            recorded: None,
+            playing: None,
+        }
+    }
+
+    pub fn set_layer(&mut self, highest_layer: u8) {
+        self.layer = highest_layer;
+    }
+
+    pub fn persist(&self) -> bool {
+        self.config.persist
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing.is_some()
+    }
+
+    /// Feeds a key event passed to the key manager.
+    pub fn key_event(&mut self, event: KeyEvent) {
+        if !event.pressed {
+            return;
+        }
+        let key = (self.layer, event.row, event.col);
+        let record = (0..SLOTS).find(|&slot| self.config.record[slot].contains(&key));
+        if self.recording.is_some() && (record.is_some() || self.config.stop.contains(&key)) {
+            self.stop();
+        } else if let Some(slot) = record {
+            self.slots[slot].len = 0;
+            self.recording = Some(slot);
+            if self.playing.is_some_and(|(playing, _)| playing == slot) {
+                self.playing = None;
+            }
+        } else if let Some(slot) = (0..SLOTS).find(|&slot| self.config.play[slot].contains(&key)) {
+            if self.recording != Some(slot) && self.slots[slot].len > 0 {
+                self.playing = Some((slot, 0));
+            }
+        }
+    }
+
+    /// Keeps a report for the host while recording.
+    pub fn record(&mut self, report: &KeyboardState) {
+        let Some(slot) = self.recording else {
+            return;
+        };
+        let slot_reports = &mut self.slots[slot];
+        let previous = match slot_reports.len {
+            0 => EMPTY,
+            len => slot_reports.reports[len - 1],
+        };
+        if *report == previous {
+            return;
+        }
+        // The last report is kept free for the release added by `stop`.
+        if slot_reports.len == MAX_REPORTS - 1 {
+            self.stop();
+            return;
+        }
+        slot_reports.reports[slot_reports.len] = *report;
+        slot_reports.len += 1;
+    }
+
+    fn stop(&mut self) {
+        let Some(slot) = self.recording.take() else {
+            return;
+        };
+        let slot_reports = &mut self.slots[slot];
+        if slot_reports.len > 0 && slot_reports.reports[slot_reports.len - 1] != EMPTY {
+            slot_reports.reports[slot_reports.len] = EMPTY;
+            slot_reports.len += 1;
+        }
+        self.recorded = Some(slot);
+    }
+
+    /// Next report of the macro being played.
+    pub fn next_output(&mut self) -> Option<KeyboardState> {
+        let (slot, index) = self.playing?;
+        let slot_reports = &self.slots[slot];
+        if index >= slot_reports.len {
+            self.playing = None;
+            return None;
+        }
+        self.playing = Some((slot, index + 1));
+        Some(slot_reports.reports[index])
+    }
+
+    /// The slot whose recording stopped since the last call, to be stored.
+    pub fn take_recorded(&mut self) -> Option<usize> {
+        self.recorded.take()
+    }
+
+    pub fn encode(&self, slot: usize, buf: &mut [u8; ENCODED_SIZE]) {
+        let slot_reports = &self.slots[slot];
+        buf[..2].copy_from_slice(&(slot_reports.len as u16).to_le_bytes());
+        for (report, bytes) in slot_reports.reports[..slot_reports.len]
+            .iter()
+            .zip(buf[2..].chunks_exact_mut(7))
+        {
+            bytes[0] = report.modifier;
+            bytes[1..].copy_from_slice(&report.keycodes);
+        }
+    }
+
+    /// Restores a slot from [`Self::encode`]. A slot which does not fit is left empty.
+    pub fn decode(&mut self, slot: usize, buf: &[u8; ENCODED_SIZE]) {
+        let len = usize::from(u16::from_le_bytes([buf[0], buf[1]]));
+        let slot_reports = &mut self.slots[slot];
+        slot_reports.len = 0;
+        if len > MAX_REPORTS {
+            return;
+        }
+        for (report, bytes) in slot_reports.reports[..len]
+            .iter_mut()
+            .zip(buf[2..].chunks_exact(7))
+        {
+            report.modifier = bytes[0];
+            report.keycodes.copy_from_slice(&bytes[1..]);
+        }
+        slot_reports.len = len;
+    }
+}
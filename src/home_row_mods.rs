@@ -0,0 +1,269 @@
+//! Home-row modifiers resolved in front of the key manager.
+//!
+//! rktk's tap-hold uses one global tapping term and has no roll handling, which makes mods on
+//! letter keys misfire for fast typists. Instead, the keys listed in `home_row_mods` of
+//! `keymap.json` keep their plain letter in the keymap, and [`HomeRowMods`] holds back their
+//! events (and every event after them, to keep the order) until it is clear whether the key
+//! is tapped or held:
+//!
+//! - released before its tapping term: tap, the held back events are forwarded unchanged
+//! - held for its tapping term: hold, the press is dropped and its modifier is added to the
+//!   keyboard report until release
+//! - another key on the same hand pressed (`same_hand_roll`): tap, since that is a roll.
+//!   Home-row mods of one hand pressed within `chord_window_ms` of each other are a chord
+//!   instead: they stay pending so that they can be held together, and become taps together
+//!   so that combos on them still fire.
+//! - a key on the other hand pressed (`hold_on_other_key_press`): hold
+//! - a key on the other hand pressed and released (`permissive_hold`): hold
+//!
+//! Home-row mods only act while the base layer is the highest active layer, so that the same
+//! positions keep their meaning on other layers.
+
+use crate::key_events::{EventQueue, KeyEvent};
+
+/// Maximum number of home-row mod keys.
+pub const MAX_KEYS: usize = 10;
+
+#[derive(Debug, Clone, Copy)]
+pub struct HomeRowMod {
+    pub row: u8,
+    pub col: u8,
+    /// HID modifier bits added while held.
+    pub modifier: u8,
+    pub tapping_term_ms: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HomeRowModsConfig {
+    pub keys: &'static [HomeRowMod],
+    pub hold_on_other_key_press: bool,
+    pub permissive_hold: bool,
+    pub same_hand_roll: bool,
+    /// Usually the combo threshold of the key manager.
+    pub chord_window_ms: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyState {
+    /// Not pressed, or pressed while disabled and passed through.
+    Idle,
+    Pending {
+        since_ms: u64,
+        /// Key pressed after this one, which decides a permissive hold when released.
+        other: Option<(u8, u8)>,
+    },
+    Tap,
+    /// `active` once the press has left the queue, i.e. the modifier is applied.
+    Hold {
+        active: bool,
+    },
+}
+
+pub struct HomeRowMods {
+    config: HomeRowModsConfig,
+    /// Columns per half; keys with `col < half_cols` are on the left hand.
+    half_cols: u8,
+    states: [KeyState; MAX_KEYS],
+    /// Events held back, in arrival order.
+    queue: EventQueue,
+    /// Events ready to be passed to the key manager.
+    out: EventQueue,
+    enabled: bool,
+    reported_modifiers: u8,
+}
+
+impl HomeRowMods {
+    pub const fn new(config: HomeRowModsConfig, cols: usize) -> Self {
+        assert!(
+            config.keys.len() <= MAX_KEYS,
+            "keymap.json: too many home_row_mods"
+        );
+        Self {
+            config,
+            half_cols: (cols / 2) as u8,
+            states: [KeyState::Idle; MAX_KEYS],
+            queue: EventQueue::new(),
+            out: EventQueue::new(),
+            enabled: true,
+            reported_modifiers: 0,
+        }
+    }
+
+    /// Home-row mods only act while `highest_layer` is the base layer.
+    pub fn set_layer(&mut self, highest_layer: u8) {
+        self.enabled = highest_layer == 0;
+    }
+
+    fn index(&self, row: u8, col: u8) -> Option<usize> {
+        self.config
+            .keys
+            .iter()
+            .position(|k| k.row == row && k.col == col)
+    }
+
+    fn is_left(&self, col: u8) -> bool {
+        col < self.half_cols
+    }
+
+    /// Feeds a key event. Events to pass on are returned by [`Self::pop_event`].
+    pub fn event(&mut self, event: KeyEvent, now_ms: u64) {
+        // Too many events held back: give up on the pending keys rather than drop events.
+        if self.queue.is_full() {
+            self.resolve_all_pending(KeyState::Tap);
+            self.flush();
+        }
+
+        let own = self.index(event.row, event.col);
+        match (own, event.pressed) {
+            (Some(i), true) if self.enabled => {
+                self.on_other_press(event, Some(now_ms));
+                self.states[i] = KeyState::Pending {
+                    since_ms: now_ms,
+                    other: None,
+                };
+            }
+            (Some(i), false) => {
+                if let KeyState::Pending { since_ms, .. } = self.states[i] {
+                    self.states[i] = KeyState::Tap;
+                    self.tap_chord(i, since_ms);
+                }
+                self.on_other_release(event);
+            }
+            (_, true) => self.on_other_press(event, None),
+            (_, false) => self.on_other_release(event),
+        }
+
+        self.queue.push(event);
+        self.flush();
+    }
+
+    /// Resolves keys held for their tapping term. Call this on every scan.
+    pub fn tick(&mut self, now_ms: u64) {
+        for (i, key) in self.config.keys.iter().enumerate() {
+            if let KeyState::Pending { since_ms, .. } = self.states[i] {
+                if now_ms.saturating_sub(since_ms) >= key.tapping_term_ms as u64 {
+                    self.states[i] = KeyState::Hold { active: false };
+                }
+            }
+        }
+        self.flush();
+    }
+
+    /// Next event to pass to the key manager.
+    pub fn pop_event(&mut self) -> Option<KeyEvent> {
+        self.out.pop()
+    }
+
+    /// Modifier bits of the home-row mods which are currently held.
+    pub fn modifiers(&self) -> u8 {
+        self.config
+            .keys
+            .iter()
+            .zip(&self.states)
+            .filter(|(_, s)| matches!(s, KeyState::Hold { active: true }))
+            .fold(0, |acc, (k, _)| acc | k.modifier)
+    }
+
+    /// Returns the modifiers if they changed since the last call, so that a keyboard report
+    /// is sent even when the key manager has nothing new.
+    pub fn take_modifier_change(&mut self) -> Option<u8> {
+        let modifiers = self.modifiers();
+        (modifiers != self.reported_modifiers).then(|| {
+            self.reported_modifiers = modifiers;
+            modifiers
+        })
+    }
+
+    /// `home_row_mod_since` is the press time if the pressed key is a home-row mod itself.
+    fn on_other_press(&mut self, event: KeyEvent, home_row_mod_since: Option<u64>) {
+        let other_left = self.is_left(event.col);
+        for (i, key) in self.config.keys.iter().enumerate() {
+            let KeyState::Pending { since_ms, other } = self.states[i] else {
+                continue;
+            };
+            let same_hand = self.is_left(key.col) == other_left;
+            self.states[i] = if same_hand {
+                let chord = home_row_mod_since.is_some_and(|now_ms| {
+                    now_ms.saturating_sub(since_ms) <= self.config.chord_window_ms as u64
+                });
+                if self.config.same_hand_roll && !chord {
+                    KeyState::Tap
+                } else {
+                    continue;
+                }
+            } else if self.config.hold_on_other_key_press {
+                KeyState::Hold { active: false }
+            } else if self.config.permissive_hold && other.is_none() {
+                KeyState::Pending {
+                    since_ms,
+                    other: Some((event.row, event.col)),
+                }
+            } else {
+                continue;
+            };
+        }
+    }
+
+    fn on_other_release(&mut self, event: KeyEvent) {
+        for state in &mut self.states {
+            if let KeyState::Pending {
+                other: Some(other), ..
+            } = *state
+            {
+                if other == (event.row, event.col) {
+                    *state = KeyState::Hold { active: false };
+                }
+            }
+        }
+    }
+
+    /// Makes the pending keys which were pressed in a chord with key `i` taps as well.
+    fn tap_chord(&mut self, i: usize, since_ms: u64) {
+        let left = self.is_left(self.config.keys[i].col);
+        for (j, key) in self.config.keys.iter().enumerate() {
+            if let KeyState::Pending {
+                since_ms: other, ..
+            } = self.states[j]
+            {
+                if self.is_left(key.col) == left
+                    && other.abs_diff(since_ms) <= self.config.chord_window_ms as u64
+                {
+                    self.states[j] = KeyState::Tap;
+                }
+            }
+        }
+    }
+
+    fn resolve_all_pending(&mut self, to: KeyState) {
+        for state in &mut self.states {
+            if let KeyState::Pending { .. } = state {
+                *state = to;
+            }
+        }
+    }
+
+    /// Moves events from the front of the queue to the output until a pending key is reached.
+    fn flush(&mut self) {
+        while let Some(event) = self.queue.front() {
+            let Some(i) = self.index(event.row, event.col) else {
+                self.queue.pop();
+                self.out.push(event);
+                continue;
+            };
+
+            match (self.states[i], event.pressed) {
+                (KeyState::Pending { .. }, true) => break,
+                (KeyState::Hold { .. }, true) => {
+                    self.states[i] = KeyState::Hold { active: true };
+                }
+                (KeyState::Hold { .. }, false) => self.states[i] = KeyState::Idle,
+                (KeyState::Tap, false) => {
+                    self.states[i] = KeyState::Idle;
+                    self.out.push(event);
+                }
+                _ => self.out.push(event),
+            }
+            self.queue.pop();
+        }
+    }
+}
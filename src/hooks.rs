@@ -2,25 +2,60 @@ use embassy_nrf::{
     gpio::{Output, Pin},
     Peripheral,
 };
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::Instant;
 use rktk::{
+    config::keymap::keymanager::state::KeyChangeEvent,
     drivers::interface::{
         reporter::ReporterDriver,
         rgb::{RgbCommand, RgbDriver, RgbMode},
+        storage::StorageDriver,
     },
     hooks::{
-        channels::rgb::rgb_sender,
+        channels::{keyboard::keyboard_event_sender, rgb::rgb_sender},
         empty_hooks::{EmptyCommonHooks, EmptySlaveHooks},
         interface::{master::Report, rgb::RGB8, MasterHooks, RgbHooks},
         Hooks,
     },
+    task::display::{DisplayMessage, DISPLAY_CONTROLLER},
 };
+use usbd_hid::descriptor::KeyboardReport;
 
-pub fn create_hooks(
+use crate::{
+    config,
+    dynamic_macro::{ENCODED_SIZE, SLOTS},
+    host_layout::Host,
+    key_events::{KeyEvent, KeyboardState, Replay},
+    key_pipeline::KeyPipeline,
+    oneshot::OneShotKind,
+    os_profile::Os,
+    storage::settings,
+    text_macro::MAX_SIZE,
+};
+
+/// Endpoint taking text macros in their wire format, see [`crate::text_macro`], padded to
+/// [`MAX_SIZE`]. The master hooks check them and store them in place of the stored ones on the
+/// next state update, and type them from then on.
+pub static TEXT_MACROS_WRITE: Signal<CriticalSectionRawMutex, [u8; MAX_SIZE]> = Signal::new();
+
+/// `storage` is the storage driver handed to rktk through [`crate::storage::SharedStorage`].
+pub fn create_hooks<S: StorageDriver>(
     led_off_pin: impl Peripheral<P = impl Pin> + 'static,
-) -> Hooks<EmptyCommonHooks, NegMasterHooks, EmptySlaveHooks, NegRgbHooks> {
+    storage: &S,
+) -> Hooks<EmptyCommonHooks, NegMasterHooks<'_, S>, EmptySlaveHooks, NegRgbHooks> {
     Hooks {
         common: EmptyCommonHooks,
-        master: NegMasterHooks { latest_led: None },
+        master: NegMasterHooks {
+            latest_led: None,
+            pipeline: KeyPipeline::new(),
+            replay: Replay::new(),
+            storage,
+            base_layout: None,
+            os_profile: None,
+            host_layout: None,
+            macros_loaded: false,
+            text_macros_loaded: false,
+        },
         slave: EmptySlaveHooks,
         rgb: NegRgbHooks {
             led_off: embassy_nrf::gpio::Output::new(
@@ -32,23 +67,240 @@ pub fn create_hooks(
     }
 }
 
-pub struct NegMasterHooks {
+pub struct NegMasterHooks<'a, S> {
     latest_led: Option<RgbCommand>,
+    pipeline: KeyPipeline,
+    /// Events released by the resolvers which go back through the keyboard event channel.
+    replay: Replay,
+    storage: &'a S,
+    /// Base layout shown on the display and kept in storage. `None` until it has been read
+    /// from storage on the first state update.
+    base_layout: Option<usize>,
+    /// OS profile kept in storage, `None` until it has been read the same way.
+    os_profile: Option<Os>,
+    /// Layout of the host kept in storage, `None` until it has been read the same way.
+    host_layout: Option<Host>,
+    /// Whether the dynamic macros have been read from storage.
+    macros_loaded: bool,
+    /// Whether the text macros have been read from storage.
+    text_macros_loaded: bool,
 }
 
-impl MasterHooks for NegMasterHooks {
+impl<S: StorageDriver> NegMasterHooks<'_, S> {
+    /// Passes the first event released by the resolvers on in place of `event`. Returns
+    /// whether there was one to pass on.
+    ///
+    /// The others stay with the resolvers until [`Self::replay_events`]: taking them now would
+    /// let the watchers see e.g. the release of a tap before the report of its press.
+    fn release_events(&mut self, event: &mut KeyChangeEvent, now_ms: u64) -> bool {
+        let Some(first) = self.replay.next(|| self.pipeline.next_event(now_ms)) else {
+            return false;
+        };
+        *event = KeyChangeEvent {
+            row: first.row,
+            col: first.col,
+            pressed: first.pressed,
+        };
+        true
+    }
+
+    /// Sends the events the resolvers released back into the event channel, tagged. Called once
+    /// the report of the key manager went through the resolvers.
+    fn replay_events(&mut self, now_ms: u64) {
+        let sender = keyboard_event_sender();
+        self.replay.send(
+            || self.pipeline.next_event(now_ms),
+            |tagged| {
+                sender
+                    .try_send(KeyChangeEvent {
+                        row: tagged.row,
+                        col: tagged.col,
+                        pressed: tagged.pressed,
+                    })
+                    .map_err(|_| tagged)
+            },
+        );
+    }
+
+    /// Reads the base layout from storage on the first call, and afterwards stores it when
+    /// the `BASE_LAYOUT` key selected another one. Either way, its name is displayed.
+    async fn sync_base_layout(&mut self) {
+        if self.base_layout.is_none() {
+            let stored = settings::load_base_layout(self.storage).await;
+            self.pipeline.select_base_layout(stored.into());
+        }
+
+        let selected = self.pipeline.base_layout().selected();
+        if self.base_layout == Some(selected) {
+            return;
+        }
+        if self.base_layout.is_some() {
+            // Writing flash delays this report by a few milliseconds, which is only noticed
+            // right after switching layouts.
+            settings::store_base_layout(self.storage, selected as u8).await;
+        }
+        self.base_layout = Some(selected);
+        let name = self.pipeline.base_layout().name();
+        let _ = DISPLAY_CONTROLLER.try_send(DisplayMessage::Message(name));
+    }
+
+    /// Same as [`Self::sync_base_layout`] for the OS profile toggled by the `OS_PROFILE` key.
+    async fn sync_os_profile(&mut self) {
+        if self.os_profile.is_none() {
+            let stored = settings::load_os_profile(self.storage).await;
+            self.pipeline.select_os_profile(stored);
+        }
+
+        let os = self.pipeline.os_profile();
+        if self.os_profile == Some(os) {
+            return;
+        }
+        if self.os_profile.is_some() {
+            settings::store_os_profile(self.storage, os).await;
+        }
+        self.os_profile = Some(os);
+        let _ = DISPLAY_CONTROLLER.try_send(DisplayMessage::Message(os.name()));
+    }
+
+    /// Same as [`Self::sync_base_layout`] for the layout of the host toggled by the
+    /// `HOST_LAYOUT` key.
+    async fn sync_host_layout(&mut self) {
+        if self.host_layout.is_none() {
+            let stored = settings::load_host_layout(self.storage).await;
+            self.pipeline.select_host_layout(stored);
+        }
+
+        let host = self.pipeline.host_layout();
+        if self.host_layout == Some(host) {
+            return;
+        }
+        if self.host_layout.is_some() {
+            settings::store_host_layout(self.storage, host).await;
+        }
+        self.host_layout = Some(host);
+        let _ = DISPLAY_CONTROLLER.try_send(DisplayMessage::Message(host.name()));
+    }
+
+    /// Reads the dynamic macros from storage on the first call, and afterwards stores a macro
+    /// when its recording stops. Does nothing unless they are to be kept in storage.
+    async fn sync_dynamic_macros(&mut self) {
+        if !self.pipeline.dynamic_macros_persist() {
+            return;
+        }
+        if !self.macros_loaded {
+            for slot in 0..SLOTS {
+                if let Some(buf) = settings::load_dynamic_macro(self.storage, slot).await {
+                    self.pipeline.load_dynamic_macro(slot, &buf);
+                }
+            }
+            self.macros_loaded = true;
+        }
+
+        let mut buf = [0; ENCODED_SIZE];
+        if let Some(slot) = self.pipeline.take_recorded_macro(&mut buf) {
+            settings::store_dynamic_macro(self.storage, slot, &buf).await;
+        }
+    }
+
+    /// Replaces the text macros of `keymap.json` with the ones in storage, on the first call.
+    async fn load_text_macros(&mut self) {
+        if self.text_macros_loaded {
+            return;
+        }
+        self.text_macros_loaded = true;
+        if let Some(buf) = settings::load_text_macros(self.storage).await {
+            if self.pipeline.load_text_macros(&buf).is_err() {
+                rktk_log::error!("The stored text macros are invalid");
+            }
+        }
+    }
+
+    /// Stores the text macros sent to [`TEXT_MACROS_WRITE`], if any, and types them from now on.
+    async fn write_text_macros(&mut self) {
+        let Some(bytes) = TEXT_MACROS_WRITE.try_take() else {
+            return;
+        };
+        match settings::write_text_macros(self.storage, &bytes).await {
+            Ok(buf) => {
+                // Already validated.
+                let _ = self.pipeline.load_text_macros(&buf);
+            }
+            Err(settings::TextMacrosWriteError::Invalid(_)) => {
+                rktk_log::error!("Rejected invalid text macros");
+            }
+            Err(settings::TextMacrosWriteError::Storage) => {
+                rktk_log::error!("Failed to store the text macros");
+            }
+        }
+    }
+}
+
+impl<S: StorageDriver> MasterHooks for NegMasterHooks<'_, S> {
+    async fn on_keyboard_event(&mut self, event: &mut KeyChangeEvent) -> bool {
+        let key = KeyEvent {
+            row: event.row,
+            col: event.col,
+            pressed: event.pressed,
+        };
+        if let Some(returned) = self.replay.returned(key) {
+            event.row = returned.row;
+            return true;
+        }
+
+        let now_ms = Instant::now().as_millis();
+        self.pipeline.event(key, now_ms);
+        self.release_events(event, now_ms)
+    }
+
     async fn on_state_update(
         &mut self,
         state_report: &mut Report,
         _usb_reporter: &Option<impl ReporterDriver>,
         _ble_reporter: &Option<impl ReporterDriver>,
     ) -> bool {
-        let led = match state_report.highest_layer {
-            1 => RgbCommand::Start(RgbMode::SolidColor(0, 0, 10)),
-            2 => RgbCommand::Start(RgbMode::SolidColor(10, 0, 0)),
-            3 => RgbCommand::Start(RgbMode::SolidColor(0, 10, 0)),
-            4 => RgbCommand::Start(RgbMode::SolidColor(10, 10, 0)),
-            _ => RgbCommand::Reset,
+        let now_ms = Instant::now().as_millis();
+        self.pipeline.update(state_report.highest_layer, now_ms);
+        self.sync_base_layout().await;
+        self.sync_os_profile().await;
+        self.sync_host_layout().await;
+        self.sync_dynamic_macros().await;
+        self.load_text_macros().await;
+        self.write_text_macros().await;
+
+        let manager_report = state_report
+            .keyboard_report
+            .as_ref()
+            .map(|report| KeyboardState {
+                modifier: report.modifier,
+                keycodes: report.keycodes,
+            });
+        state_report.keyboard_report =
+            self.pipeline
+                .keyboard_report(manager_report, now_ms)
+                .map(|state| {
+                    let mut report = KeyboardReport::default();
+                    report.modifier = state.modifier;
+                    report.keycodes = state.keycodes;
+                    report
+                });
+        self.replay_events(now_ms);
+
+        let led = match self.pipeline.oneshot_pending() {
+            // White while a one-shot modifier waits for the next key.
+            Some(OneShotKind::Modifier(_)) => RgbCommand::Start(RgbMode::SolidColor(10, 10, 10)),
+            Some(OneShotKind::Layer(layer)) => layer_led(layer),
+            // Orange while a dynamic macro is recorded, cyan while a leader sequence is typed,
+            // magenta while Caps Word is on.
+            None if self.pipeline.dynamic_macro_recording() => {
+                RgbCommand::Start(RgbMode::SolidColor(10, 4, 0))
+            }
+            None if self.pipeline.leader_active() => {
+                RgbCommand::Start(RgbMode::SolidColor(0, 10, 10))
+            }
+            None if self.pipeline.caps_word_active() => {
+                RgbCommand::Start(RgbMode::SolidColor(10, 0, 10))
+            }
+            None => layer_led(state_report.highest_layer),
         };
 
         if let Some(latest_led) = &self.latest_led {
@@ -65,6 +317,16 @@ impl MasterHooks for NegMasterHooks {
     }
 }
 
+fn layer_led(layer: u8) -> RgbCommand {
+    match layer {
+        1 => RgbCommand::Start(RgbMode::SolidColor(0, 0, 10)),
+        2 => RgbCommand::Start(RgbMode::SolidColor(10, 0, 0)),
+        3 => RgbCommand::Start(RgbMode::SolidColor(0, 10, 0)),
+        4 => RgbCommand::Start(RgbMode::SolidColor(10, 10, 0)),
+        _ => RgbCommand::Reset,
+    }
+}
+
 pub struct NegRgbHooks {
     pub led_off: Output<'static>,
 }
@@ -79,6 +341,13 @@ impl RgbHooks for NegRgbHooks {
         command: &RgbCommand,
         _rgb_data: &mut Option<[RGB8; N]>,
     ) {
+        // rktk sizes the LED buffer of each half from `rktk.json` on its own.
+        const {
+            assert!(
+                N == config::LEFT_RGB_COUNT || N == config::RIGHT_RGB_COUNT,
+                "the LED buffer of rktk does not match `left_rgb_count` or `right_rgb_count` in rktk.json"
+            )
+        };
         if *command == RgbCommand::Reset {
             self.led_off.set_high();
         } else {
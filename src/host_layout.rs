@@ -0,0 +1,177 @@
+//! Keyboard layout the host is set to, applied last to the keyboard reports for the host.
+//!
+//! The keymap is written for hosts set to a US layout. With the JIS layout, a key which the
+//! host would read as another character is replaced by the key and Shift typing the intended
+//! character on JIS, e.g. Shift+2 for `@` becomes the unshifted key right of P. When a report
+//! holds several translated keys, the last one decides whether Shift is held. Reports with
+//! Ctrl, Alt or GUI held are sent as they are, since applications look shortcuts up by key
+//! and not by character.
+//!
+//! Lang1 and Lang2 turn the IME on and off on a Mac. Windows takes Henkan and Muhenkan for
+//! that, so they are sent instead with the JIS layout and the Windows [`crate::os_profile`].
+//!
+//! The `HOST_LAYOUT` key toggles the layout. The master hooks keep the layout in storage and
+//! show it on the display.
+
+use crate::{
+    key_events::{KeyEvent, KeyboardState},
+    os_profile::Os,
+};
+
+const LEFT_SHIFT: u8 = 0x02;
+/// Left and right Shift.
+const SHIFT: u8 = 0x22;
+
+const LANG1: u8 = 0x90;
+const LANG2: u8 = 0x91;
+const HENKAN: u8 = 0x8a;
+const MUHENKAN: u8 = 0x8b;
+
+/// `(key, shifted)` on a US layout to `(key, shifted)` typing the same character on JIS.
+#[rustfmt::skip]
+const JIS: &[(u8, bool, u8, bool)] = &[
+    (0x1f, true, 0x2f, false),  // @
+    (0x23, true, 0x2e, false),  // ^
+    (0x24, true, 0x23, true),   // &
+    (0x25, true, 0x34, true),   // *
+    (0x26, true, 0x25, true),   // (
+    (0x27, true, 0x26, true),   // )
+    (0x2d, true, 0x87, true),   // _
+    (0x2e, false, 0x2d, true),  // =
+    (0x2e, true, 0x33, true),   // +
+    (0x2f, false, 0x30, false), // [
+    (0x2f, true, 0x30, true),   // {
+    (0x30, false, 0x32, false), // ]
+    (0x30, true, 0x32, true),   // }
+    (0x31, false, 0x87, false), // \
+    (0x31, true, 0x89, true),   // |
+    (0x33, true, 0x34, false),  // :
+    (0x34, false, 0x24, true),  // '
+    (0x34, true, 0x1f, true),   // "
+    (0x35, false, 0x2f, true),  // `
+    (0x35, true, 0x2e, true),   // ~
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Host {
+    Us,
+    Jis,
+}
+
+impl Host {
+    pub fn name(self) -> &'static str {
+        match self {
+            Host::Us => "US",
+            Host::Jis => "JIS",
+        }
+    }
+
+    /// Value kept in storage.
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Host::Us => 0,
+            Host::Jis => 1,
+        }
+    }
+
+    /// Unknown values are [`Host::Us`], the layout the keymap is written for.
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Host::Jis,
+            _ => Host::Us,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HostLayoutConfig {
+    /// `(layer, row, col)` of the keys toggling the layout, with `layer` the highest active
+    /// layer.
+    pub keys: &'static [(u8, u8, u8)],
+}
+
+pub struct HostLayout {
+    config: HostLayoutConfig,
+    layer: u8,
+    host: Host,
+    os: Os,
+}
+
+impl HostLayout {
+    pub const fn new(config: HostLayoutConfig) -> Self {
+        Self {
+            config,
+            layer: 0,
+            host: Host::Us,
+            os: Os::Windows,
+        }
+    }
+
+    pub fn set_layer(&mut self, highest_layer: u8) {
+        self.layer = highest_layer;
+    }
+
+    pub fn set_os(&mut self, os: Os) {
+        self.os = os;
+    }
+
+    pub fn host(&self) -> Host {
+        self.host
+    }
+
+    /// Selects a layout, e.g. the one read from storage.
+    pub fn select(&mut self, host: Host) {
+        self.host = host;
+    }
+
+    /// Feeds a key event passed to the key manager, and toggles on a `HOST_LAYOUT` key press.
+    pub fn key_event(&mut self, event: KeyEvent) {
+        if event.pressed
+            && self
+                .config
+                .keys
+                .contains(&(self.layer, event.row, event.col))
+        {
+            self.host = match self.host {
+                Host::Us => Host::Jis,
+                Host::Jis => Host::Us,
+            };
+        }
+    }
+
+    /// Translates a keyboard report for the host.
+    pub fn report(&self, report: &mut KeyboardState) {
+        if self.host == Host::Us {
+            return;
+        }
+        if self.os == Os::Windows {
+            for keycode in &mut report.keycodes {
+                match *keycode {
+                    LANG1 => *keycode = HENKAN,
+                    LANG2 => *keycode = MUHENKAN,
+                    _ => {}
+                }
+            }
+        }
+        if report.modifier & !SHIFT != 0 {
+            return;
+        }
+
+        let shifted = report.modifier & SHIFT != 0;
+        let mut shift = None;
+        for keycode in &mut report.keycodes {
+            if let Some(&(_, _, to, to_shifted)) = JIS
+                .iter()
+                .find(|(from, from_shifted, ..)| *from == *keycode && *from_shifted == shifted)
+            {
+                *keycode = to;
+                shift = Some(to_shifted);
+            }
+        }
+        match shift {
+            Some(true) if !shifted => report.modifier |= LEFT_SHIFT,
+            Some(false) => report.modifier &= !SHIFT,
+            _ => {}
+        }
+    }
+}
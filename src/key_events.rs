@@ -0,0 +1,144 @@
+//! Key events between the key scan and the key manager, and keyboard reports between the key
+//! manager and the host, for the resolvers which sit in between (see
+//! [`crate::key_pipeline`]).
+
+/// Key event, as seen by the key manager.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub row: u8,
+    pub col: u8,
+    pub pressed: bool,
+}
+
+/// Keyboard report, as produced by the key manager and sent to the host.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyboardState {
+    /// HID modifier bits.
+    pub modifier: u8,
+    /// HID key codes, 0 for none.
+    pub keycodes: [u8; 6],
+}
+
+/// Maximum number of events an [`EventQueue`] holds.
+pub const QUEUE_SIZE: usize = 16;
+
+/// Fixed-size FIFO of key events. Pushing to a full queue drops the event.
+pub struct EventQueue {
+    items: [Option<KeyEvent>; QUEUE_SIZE],
+    len: usize,
+}
+
+impl EventQueue {
+    pub const fn new() -> Self {
+        Self {
+            items: [None; QUEUE_SIZE],
+            len: 0,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == QUEUE_SIZE
+    }
+
+    pub fn push(&mut self, event: KeyEvent) {
+        if self.len < QUEUE_SIZE {
+            self.items[self.len] = Some(event);
+            self.len += 1;
+        }
+    }
+
+    pub fn front(&self) -> Option<KeyEvent> {
+        self.items[0].filter(|_| self.len > 0)
+    }
+
+    pub fn pop(&mut self) -> Option<KeyEvent> {
+        let event = self.front()?;
+        self.items.copy_within(1..self.len, 0);
+        self.len -= 1;
+        self.items[self.len] = None;
+        Some(event)
+    }
+}
+
+impl Default for EventQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Bit set in the row of the events a [`Replay`] sends back. Rows of the key scan are below
+/// [`crate::config::ROWS`], so they never have it.
+pub const REPLAYED: u8 = 0x80;
+
+const _: () = assert!(crate::config::ROWS <= REPLAYED as usize);
+
+/// Events released by the resolvers beyond the one the master hook passes on in place of the
+/// event it was given. rktk 0.2 gives the hook no way to hand the key manager more than that
+/// one event, so they are sent back into the keyboard event channel of rktk, tagged with
+/// [`REPLAYED`], and passed on untagged when they return.
+///
+/// Released events reach the key manager in the order they were released: while some are in
+/// the channel, events of the scan only go to the resolvers, and what those release is sent
+/// back behind them. An event which finds the channel full is held and sent first on the next
+/// state update.
+pub struct Replay {
+    /// Number of events sent back which have not returned yet.
+    in_channel: usize,
+    held: Option<KeyEvent>,
+}
+
+impl Replay {
+    pub const fn new() -> Self {
+        Self {
+            in_channel: 0,
+            held: None,
+        }
+    }
+
+    /// The event sent back which `event` is, untagged, or `None` for an event of the scan.
+    pub fn returned(&mut self, event: KeyEvent) -> Option<KeyEvent> {
+        if event.row & REPLAYED == 0 {
+            return None;
+        }
+        self.in_channel = self.in_channel.saturating_sub(1);
+        Some(KeyEvent {
+            row: event.row & !REPLAYED,
+            ..event
+        })
+    }
+
+    /// The event to pass on in place of an event of the scan: none while events sent back are
+    /// in the channel, else the held event, else the next one of `released`.
+    pub fn next(&mut self, released: impl FnOnce() -> Option<KeyEvent>) -> Option<KeyEvent> {
+        if self.in_channel > 0 {
+            return None;
+        }
+        self.held.take().or_else(released)
+    }
+
+    /// Sends the held event and the events of `released` back with `try_send`, which gives the
+    /// event back if the channel is full. The first event which cannot be sent is held.
+    pub fn send(
+        &mut self,
+        mut released: impl FnMut() -> Option<KeyEvent>,
+        mut try_send: impl FnMut(KeyEvent) -> Result<(), KeyEvent>,
+    ) {
+        while let Some(event) = self.held.take().or_else(&mut released) {
+            let tagged = KeyEvent {
+                row: event.row | REPLAYED,
+                ..event
+            };
+            if try_send(tagged).is_err() {
+                self.held = Some(event);
+                return;
+            }
+            self.in_channel += 1;
+        }
+    }
+}
+
+impl Default for Replay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
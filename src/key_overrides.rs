@@ -0,0 +1,118 @@
+//! Key overrides, applied to the keyboard reports of the key manager.
+//!
+//! A [`KeyOverride`] replaces `key` by `replacement` when it is pressed while all of its
+//! `modifiers` are held, e.g. Shift+Backspace sends Delete. The modifiers which triggered it
+//! are taken out of the report, and the `replacement_modifier` is added, for as long as the
+//! override is active. Left and right modifiers are not told apart. The modifiers are the
+//! ones sent to the host, after [`crate::os_profile`], and an override with an `os` only
+//! applies with that profile.
+//!
+//! An override starts when its key is pressed and ends when the key is released, so
+//! releasing the modifiers first keeps sending the replacement instead of the key. Pressing
+//! another key ends it as well, and the key then stays out of the reports until it is
+//! released.
+
+use crate::{key_events::KeyboardState, os_profile::Os};
+
+#[derive(Debug, Clone, Copy)]
+pub struct KeyOverride {
+    /// HID modifier bits which must be held. Either side of a modifier counts.
+    pub modifiers: u8,
+    /// HID key code of the overridden key.
+    pub key: u8,
+    pub replacement: u8,
+    /// HID modifier bits sent with `replacement`.
+    pub replacement_modifier: u8,
+    /// Bit `n` is set if the override applies while `n` is the highest active layer.
+    pub layers: u32,
+    /// The OS profile the override applies with, `None` for all.
+    pub os: Option<Os>,
+}
+
+pub struct KeyOverrides {
+    overrides: &'static [KeyOverride],
+    layer: u8,
+    os: Os,
+    /// Key codes of the previous report, to tell which keys are newly pressed.
+    previous: [u8; 6],
+    active: Option<KeyOverride>,
+    /// Keys of ended overrides which are still held, and must stay out of the reports.
+    ended: [u8; 6],
+}
+
+impl KeyOverrides {
+    pub const fn new(overrides: &'static [KeyOverride]) -> Self {
+        Self {
+            overrides,
+            layer: 0,
+            os: Os::Windows,
+            previous: [0; 6],
+            active: None,
+            ended: [0; 6],
+        }
+    }
+
+    pub fn set_layer(&mut self, highest_layer: u8) {
+        self.layer = highest_layer;
+    }
+
+    pub fn set_os(&mut self, os: Os) {
+        self.os = os;
+    }
+
+    /// Applies the overrides to a keyboard report, including the modifiers added by the
+    /// resolvers in front of this one.
+    pub fn report(&mut self, report: &mut KeyboardState) {
+        for ended in &mut self.ended {
+            if !report.keycodes.contains(ended) {
+                *ended = 0;
+            }
+        }
+        if let Some(active) = self.active {
+            if !report.keycodes.contains(&active.key) {
+                self.active = None;
+            }
+        }
+
+        let previous = core::mem::replace(&mut self.previous, report.keycodes);
+        for keycode in report.keycodes {
+            if keycode == 0 || previous.contains(&keycode) {
+                continue;
+            }
+            if let Some(active) = self.active.take() {
+                if let Some(slot) = self.ended.iter_mut().find(|e| **e == 0) {
+                    *slot = active.key;
+                }
+            }
+            let held = both_sides(report.modifier);
+            self.active = self.overrides.iter().copied().find(|o| {
+                o.key == keycode
+                    && held & o.modifiers == o.modifiers
+                    && self.layer < 32
+                    && o.layers & (1 << self.layer) != 0
+                    && o.os.is_none_or(|os| os == self.os)
+            });
+        }
+
+        for keycode in &mut report.keycodes {
+            if self.ended.contains(keycode) {
+                *keycode = 0;
+            }
+        }
+        if let Some(active) = self.active {
+            for keycode in &mut report.keycodes {
+                if *keycode == active.key {
+                    *keycode = active.replacement;
+                }
+            }
+            report.modifier &= !both_sides(active.modifiers);
+            report.modifier |= active.replacement_modifier;
+        }
+    }
+}
+
+/// `modifier` with each modifier on both the left and the right side.
+fn both_sides(modifier: u8) -> u8 {
+    let sides = (modifier | modifier >> 4) & 0x0f;
+    sides | sides << 4
+}
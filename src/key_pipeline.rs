@@ -0,0 +1,261 @@
+//! The resolvers around the key manager, in the order the master hooks run them.
+//!
+//! Key events from the scan go through [`HomeRowMods`] and [`OneShot`] before they reach the
+//! key manager, and [`BaseLayouts`], [`OsProfile`], [`HostLayout`], [`CapsWord`], [`Leader`],
+//! [`DynamicMacros`] and [`TextMacros`] watch the events which do. The keyboard reports of
+//! the key manager are then translated to the selected base layout by [`BaseLayouts`], lose
+//! the keys taken by [`Leader`], get the modifiers of [`HomeRowMods`], have their modifiers
+//! remapped by [`OsProfile`], go through [`KeyOverrides`] and get the Shift of [`CapsWord`].
+//! The text of [`Leader`] and the macros of [`DynamicMacros`] and [`TextMacros`] take their
+//! place while they are sent. Last, [`DynamicMacros`] records the reports, and [`HostLayout`]
+//! translates them to the layout of the host.
+//!
+//! The keys of the resolvers in `keymap.json` (`BASE_LAYOUT`, `CAPS_WORD`, `LEADER`, ...)
+//! compile to keys which do nothing in the key manager. The resolvers watch the key events
+//! passed to the key manager for them.
+//!
+//! The master hooks and the host simulator of `tools/` both drive a [`KeyPipeline`], so they
+//! cannot disagree on the order. For that, the resolvers only depend on [`crate::key_events`]
+//! and on each other, and the same code is compiled into `tools/`.
+
+use crate::{
+    base_layout::BaseLayouts,
+    caps_word::CapsWord,
+    config::{COLS, ONESHOT_STATE_SIZE},
+    dynamic_macro::{DynamicMacros, ENCODED_SIZE},
+    home_row_mods::HomeRowMods,
+    host_layout::{Host, HostLayout},
+    key_events::{KeyEvent, KeyboardState},
+    key_overrides::KeyOverrides,
+    keymap::{
+        BASE_LAYOUTS, CAPS_WORD, DYNAMIC_MACROS, HOME_ROW_MODS, HOST_LAYOUT, KEY_OVERRIDES, LEADER,
+        ONESHOT, OS_PROFILE, TEXT_MACROS,
+    },
+    leader::Leader,
+    oneshot::{OneShot, OneShotKind},
+    os_profile::{Os, OsProfile},
+    text_macro::{DecodeError, TextMacros, MAX_SIZE},
+};
+
+pub struct KeyPipeline {
+    home_row_mods: HomeRowMods,
+    /// Sees the events after `home_row_mods`.
+    oneshot: OneShot<ONESHOT_STATE_SIZE>,
+    base_layout: BaseLayouts,
+    os_profile: OsProfile,
+    host_layout: HostLayout,
+    caps_word: CapsWord,
+    leader: Leader,
+    dynamic_macros: DynamicMacros,
+    text_macros: TextMacros,
+    key_overrides: KeyOverrides,
+    /// Last keyboard report of the key manager.
+    manager_report: KeyboardState,
+    /// Set while text of the leader replaces the reports of the key manager.
+    sending: bool,
+}
+
+impl KeyPipeline {
+    pub const fn new() -> Self {
+        Self {
+            home_row_mods: HomeRowMods::new(HOME_ROW_MODS, COLS),
+            oneshot: OneShot::new(ONESHOT),
+            base_layout: BaseLayouts::new(BASE_LAYOUTS),
+            os_profile: OsProfile::new(OS_PROFILE),
+            host_layout: HostLayout::new(HOST_LAYOUT),
+            caps_word: CapsWord::new(CAPS_WORD),
+            leader: Leader::new(LEADER),
+            dynamic_macros: DynamicMacros::new(DYNAMIC_MACROS),
+            text_macros: TextMacros::new(TEXT_MACROS),
+            key_overrides: KeyOverrides::new(KEY_OVERRIDES),
+            manager_report: KeyboardState {
+                modifier: 0,
+                keycodes: [0; 6],
+            },
+            sending: false,
+        }
+    }
+
+    /// Feeds a key event from the scan. Events for the key manager are returned by
+    /// [`Self::next_event`].
+    pub fn event(&mut self, event: KeyEvent, now_ms: u64) {
+        self.home_row_mods.event(event, now_ms);
+    }
+
+    /// Next event for the key manager.
+    pub fn next_event(&mut self, now_ms: u64) -> Option<KeyEvent> {
+        loop {
+            if let Some(event) = self.oneshot.pop_event() {
+                self.base_layout.key_event(event);
+                self.os_profile.key_event(event);
+                self.host_layout.key_event(event);
+                self.caps_word.key_event(event, now_ms);
+                self.leader.key_event(event, now_ms);
+                self.dynamic_macros.key_event(event);
+                self.text_macros.key_event(event);
+                return Some(event);
+            }
+            let event = self.home_row_mods.pop_event()?;
+            self.oneshot.event(event, now_ms);
+        }
+    }
+
+    /// Call this on every scan with the highest active layer of the key manager.
+    pub fn update(&mut self, highest_layer: u8, now_ms: u64) {
+        self.home_row_mods.set_layer(highest_layer);
+        self.oneshot.set_layer(highest_layer);
+        self.base_layout.set_layer(highest_layer);
+        self.os_profile.set_layer(highest_layer);
+        self.host_layout.set_layer(highest_layer);
+        self.caps_word.set_layer(highest_layer);
+        self.leader.set_layer(highest_layer);
+        self.dynamic_macros.set_layer(highest_layer);
+        self.text_macros.set_layer(highest_layer);
+        self.key_overrides.set_layer(highest_layer);
+        self.home_row_mods.tick(now_ms);
+        self.oneshot.tick(now_ms);
+        self.caps_word.tick(now_ms);
+        self.leader.tick(now_ms);
+    }
+
+    /// Turns the keyboard report of the key manager, if it sent a new one, into the report
+    /// for the host. Returns `None` if there is nothing new to send.
+    pub fn keyboard_report(
+        &mut self,
+        manager_report: Option<KeyboardState>,
+        now_ms: u64,
+    ) -> Option<KeyboardState> {
+        if let Some(report) = manager_report {
+            self.manager_report = report;
+        }
+        let output = self
+            .dynamic_macros
+            .next_output()
+            .or_else(|| self.text_macros.next_output(now_ms))
+            .or_else(|| self.leader.next_output());
+        let mut report = match output {
+            Some(report) => {
+                self.sending = true;
+                report
+            }
+            None => self.manager_output(manager_report.is_some(), now_ms)?,
+        };
+        self.dynamic_macros.record(&report);
+        self.host_layout.set_os(self.os_profile.os());
+        self.host_layout.report(&mut report);
+        Some(report)
+    }
+
+    /// The report of the key manager through the resolvers, if it is new or has to be sent
+    /// again.
+    fn manager_output(&mut self, new: bool, now_ms: u64) -> Option<KeyboardState> {
+        let modifiers = self.home_row_mods.modifiers();
+        let modifiers_changed = self.home_row_mods.take_modifier_change().is_some();
+        // After the text of a sequence or a macro, the report of the key manager is sent
+        // again.
+        let resend = core::mem::take(&mut self.sending) || modifiers_changed;
+        if !new && !resend {
+            return None;
+        }
+
+        let mut report = self.manager_report;
+        self.base_layout.report(&mut report);
+        self.leader.report(&mut report, now_ms);
+        report.modifier |= modifiers;
+        report.modifier = self.os_profile.modifiers(report.modifier);
+        self.key_overrides.set_os(self.os_profile.os());
+        self.key_overrides.report(&mut report);
+        self.caps_word
+            .report(report.modifier, &report.keycodes, now_ms);
+        report.modifier |= self.caps_word.modifiers();
+        Some(report)
+    }
+
+    /// The report for the host if the home-row mods changed since the last report, without
+    /// taking text of the leader. The simulator of `tools/` calls this after passing on the
+    /// events of a scan, where the firmware waits for its next state update.
+    pub fn modifier_report(&mut self, now_ms: u64) -> Option<KeyboardState> {
+        if self.sending
+            || self.leader.is_sending()
+            || self.dynamic_macros.is_playing()
+            || self.text_macros.is_playing()
+        {
+            return None;
+        }
+        self.keyboard_report(None, now_ms)
+    }
+
+    /// The selected base layout, see [`BaseLayouts::selected`].
+    pub fn base_layout(&self) -> &BaseLayouts {
+        &self.base_layout
+    }
+
+    /// Selects a base layout, e.g. the one read from storage on boot.
+    pub fn select_base_layout(&mut self, index: usize) {
+        self.base_layout.select(index);
+    }
+
+    pub fn os_profile(&self) -> Os {
+        self.os_profile.os()
+    }
+
+    /// Selects an OS profile, e.g. the one read from storage on boot.
+    pub fn select_os_profile(&mut self, os: Os) {
+        self.os_profile.select(os);
+    }
+
+    pub fn host_layout(&self) -> Host {
+        self.host_layout.host()
+    }
+
+    /// Selects the layout of the host, e.g. the one read from storage on boot.
+    pub fn select_host_layout(&mut self, host: Host) {
+        self.host_layout.select(host);
+    }
+
+    /// The one-shots which wait for the next key, see [`OneShot::pending`].
+    pub fn oneshot_pending(&self) -> Option<OneShotKind> {
+        self.oneshot.pending()
+    }
+
+    pub fn caps_word_active(&self) -> bool {
+        self.caps_word.is_active()
+    }
+
+    pub fn leader_active(&self) -> bool {
+        self.leader.is_active()
+    }
+
+    pub fn dynamic_macro_recording(&self) -> bool {
+        self.dynamic_macros.is_recording()
+    }
+
+    /// Whether the master hooks keep the dynamic macros in storage.
+    pub fn dynamic_macros_persist(&self) -> bool {
+        self.dynamic_macros.persist()
+    }
+
+    /// The slot of a dynamic macro whose recording stopped since the last call, encoded to
+    /// be stored.
+    pub fn take_recorded_macro(&mut self, buf: &mut [u8; ENCODED_SIZE]) -> Option<usize> {
+        let slot = self.dynamic_macros.take_recorded()?;
+        self.dynamic_macros.encode(slot, buf);
+        Some(slot)
+    }
+
+    /// Restores a dynamic macro read from storage.
+    pub fn load_dynamic_macro(&mut self, slot: usize, buf: &[u8; ENCODED_SIZE]) {
+        self.dynamic_macros.decode(slot, buf);
+    }
+
+    /// Replaces the text macros of `keymap.json` with ones read from storage.
+    pub fn load_text_macros(&mut self, buf: &[u8; MAX_SIZE]) -> Result<(), DecodeError> {
+        self.text_macros.load(buf)
+    }
+}
+
+impl Default for KeyPipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
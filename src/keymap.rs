@@ -1,114 +1,59 @@
+//! The keymap is defined in `keymap.json` and compiled into [`KEYMAP`], [`HOME_ROW_MODS`],
+//! [`ONESHOT`], [`CAPS_WORD`], [`LEADER`], [`KEY_OVERRIDES`], [`BASE_LAYOUTS`],
+//! [`OS_PROFILE`], [`HOST_LAYOUT`], [`DYNAMIC_MACROS`] and [`TEXT_MACROS`] by `build.rs`.
+
 use rktk::config::keymap::{
-    keymanager::keymap::TapDanceDefinition, prelude::*, Keymap, Layer, LayerKeymap,
+    keymanager::keymap::{ComboDefinition, TapDanceDefinition},
+    prelude::*,
+    Keymap, Layer, LayerKeymap,
 };
 
-const L2ENTER: KeyAction = KeyAction::TapHold(
-    KeyCode::Key(Key::Enter),
-    KeyCode::Layer(LayerOp::Momentary(2)),
-);
-
-const L2SPC: KeyAction = KeyAction::TapHold(
-    KeyCode::Key(Key::Space),
-    KeyCode::Layer(LayerOp::Momentary(2)),
-);
-
-const L3SPC: KeyAction = KeyAction::TapHold(
-    KeyCode::Key(Key::Space),
-    KeyCode::Layer(LayerOp::Momentary(3)),
-);
-
-const L4GRV: KeyAction = KeyAction::TapHold(
-    KeyCode::Key(Key::Grave),
-    KeyCode::Layer(LayerOp::Momentary(4)),
-);
-
-#[rustfmt::skip]
-const L0: LayerKeymap = [
-    [ L4GRV , D1    , D2    , D3    , D4    , D5    , _____ , _____ , /**/ _____ , _____ , D6    , D7    , D8    , D9    , D0    , EQUAL ],
-    [  TAB  , Q     , W     , E     , R     , T     , _____ , _____ , /**/ _____ , _____ , Y     , U     , I     , O     , P     , MINUS ],
-    [  ESC  , A     , S     , D     , F     , G     , _____ , _____ , /**/ _____ , _____ , H     , J     , K     , L     , SCLN  , QUOTE ],
-    [ L_SHFT, Z     , X     , C     , V     , B     , LBRC  , _____ , /**/ _____ , TD(0) , N     , M     , COMM  , DOT   , SLASH , BSLSH ],
-    [ L_CTRL, L_GUI , TG(2) , L_ALT , L3SPC , L2SPC , SPACE , _____ , /**/ BS    , BS    ,L2ENTER, _____ , _____ , _____ , R_SHFT, R_CTRL],
-];
-
-#[rustfmt::skip]
-/// Auto mouse layer
-const L1: LayerKeymap = [
-    [ _____ , _____ , _____ , _____ , _____ , _____ , _____ ,_____ , /**/ _____ ,_____ , _____ , _____ , _____ , _____ , _____ , _____ ],
-    [ _____ , _____ , _____ , _____ , _____ , _____ , _____ ,_____ , /**/ _____ ,_____ , _____ , _____ , _____ , _____ , _____ , _____ ],
-    [ _____ , _____ , _____ , _____ , _____ , _____ , _____ ,_____ , /**/ _____ ,_____,AML_RESET,M_LEFT,MO_SCRL,M_RIGHT, _____ , _____ ],
-    [ _____ , _____ , _____ , _____ , _____ , _____ , _____ ,_____ , /**/ _____ ,_____ , _____ ,M_BACK,M_MIDDLE,M_FORWARD,_____, _____ ],
-    [ _____ , _____ , _____ , _____ , _____ , _____ , _____ ,_____ , /**/ _____ ,_____ , _____ , _____ , _____ , _____ , _____ , _____ ],
-];
-
-#[rustfmt::skip]
-/// Mouse layer
-const L2: LayerKeymap = [
-    [ _____ , F1    , F2    , F3    , F4    , F5    , _____ ,_____ , /**/ _____ ,_____ , F6    , F7    , F8    , TG(2) , F10   , F11   ],
-    [ _____ , _____ , INSERT, HOME  , PGUP  , _____ , _____ ,_____ , /**/ _____ ,_____ , LEFT  , DOWN  , UP    , RIGHT , _____ , F12   ],
-    [ _____ , _____ , _____ , _____ , _____ , _____ , _____ ,_____ , /**/ _____ ,_____,AML_RESET,M_LEFT,MO_SCRL,M_RIGHT, _____ , VOLUP ],
-    [ _____ , _____ , _____ , _____ , _____ , _____ , _____ ,_____ , /**/ _____ ,_____ , _____ ,M_BACK,M_MIDDLE,M_FORWARD,_____, VOLDN ],
-    [ _____ , _____ , _____ , _____ , _____ , _____ , _____ ,_____ , /**/ _____ ,DELETE, _____ , _____ , _____ , _____ , PRTSC , _____ ],
-];
-
-const FL_CLR: KeyAction = FLASH_CLEAR;
-#[rustfmt::skip]
-const L3: LayerKeymap = [
-    [ FL_CLR,BLE_BOND_CLEAR, _____ , _____ , _____ , _____ , _____ ,_____ , /**/ _____ ,__ ,OUTPUT_BLE,OUTPUT_USB , __, _____ , _____ , _____ ],
-    [ _____ , _____        , KP7   , KP8   , KP9   , _____ , _____ ,_____ , /**/ _____ ,_____ , SF(D1), SF(D2), SF(D3), SF(D4), SF(D5), _____ ],
-    [ _____ , _____        , KP4   , KP5   , KP6   , _____ , _____ ,_____ , /**/ _____ ,_____ , SF(D6), SF(D7), SF(D8), SF(D9), SF(D0), _____ ],
-    [ _____ , _____        , KP1   , KP2   , KP3   , _____ , _____ ,_____ , /**/ _____ ,_____ , QUOTE,SF(QUOTE),EQUAL,SF(EQUAL), _____, _____ ],
-    [ _____ , _____        , KP0   , _____ , _____ , _____ , _____ ,_____ , /**/ _____ ,_____ , _____ , _____ , _____ , _____ , _____ , _____ ],
-];
-
-#[rustfmt::skip]
-const L4: LayerKeymap = [
-    [ _____ , _____ , _____ , _____ , _____ , _____ , _____ ,_____ , /**/ _____ ,_____ , _____ , _____ , _____ , _____ , _____ , _____ ],
-    [ _____ , _____ , _____ , _____ , _____ , _____ , _____ ,_____ , /**/ _____ ,_____ , _____ , _____ , _____ , _____ , _____ , _____ ],
-    [ _____ , _____ , _____ , _____ , _____ , _____ , _____ ,_____ , /**/ _____ ,_____ , _____ , _____ , _____ , _____ , _____ , _____ ],
-    [ _____ , _____ , _____ , _____ , _____ , _____ , _____ ,_____ , /**/ _____ ,_____ , _____ , _____ , _____ , _____ , _____ , _____ ],
-    [ _____ , _____ , _____ , _____ , _____ , _____ , _____ ,_____ , /**/ _____ ,_____ , _____ , _____ , _____ , _____ , _____ , _____ ],
-];
-
-pub const KEYMAP: Keymap = Keymap {
-    layers: [
-        Layer {
-            keymap: L0,
-            encoder_keys: [(
-                Some(KeyCode::Media(Media::VolumeIncrement)),
-                Some(KeyCode::Media(Media::VolumeDecrement)),
-            )],
-            ..Layer::const_default()
-        },
-        Layer {
-            keymap: L1,
-            encoder_keys: [(
-                Some(KeyCode::Media(Media::VolumeIncrement)),
-                Some(KeyCode::Media(Media::VolumeDecrement)),
-            )],
-            ..Layer::const_default()
-        },
-        Layer {
-            keymap: L2,
-            ..Layer::const_default()
-        },
-        Layer {
-            keymap: L3,
-            ..Layer::const_default()
-        },
-        Layer {
-            keymap: L4,
-            ..Layer::const_default()
-        },
-    ],
-    tap_dance: [Some(TapDanceDefinition {
-        tap: [
-            Some(KeyCode::Key(Key::RightBracket)),
-            Some(KeyCode::Layer(LayerOp::Toggle(2))),
-            Some(KeyCode::Layer(LayerOp::Toggle(3))),
-            Some(KeyCode::Layer(LayerOp::Toggle(4))),
-        ],
-        hold: [None, None, None, None],
-    })],
-    combo: [],
+use crate::{
+    base_layout::{BaseKey, BaseLayout, BaseLayoutConfig},
+    caps_word::CapsWordConfig,
+    dynamic_macro::DynamicMacroConfig,
+    home_row_mods::{HomeRowMod, HomeRowModsConfig},
+    host_layout::HostLayoutConfig,
+    key_overrides::KeyOverride,
+    leader::{LeaderConfig, LeaderSequence},
+    oneshot::{OneShotConfig, OneShotKey, OneShotKind},
+    os_profile::{Os, OsProfileConfig},
+    text_macro::TextMacroConfig,
 };
+
+/// Key code of a plain key action, for places where rktk expects a bare [`KeyCode`].
+const fn kc(action: KeyAction) -> KeyCode {
+    match action {
+        KeyAction::Normal(code) => code,
+        _ => panic!("keymap.json: expected a plain key"),
+    }
+}
+
+/// HID key code of a plain key action, for the resolvers in front of the host.
+const fn hid(action: KeyAction) -> u8 {
+    match kc(action) {
+        KeyCode::Key(key) => key as u8,
+        _ => panic!("keymap.json: expected a key"),
+    }
+}
+
+/// Index of the text macro typed by the key at `row, col` while `layer` is the highest active
+/// layer: `n` for `MACRO(n)`, which is the custom key code `Custom1(n)`. Transparent keys fall
+/// through to the layers below, as `build.rs` assumes.
+fn text_macro_at(layer: u8, row: u8, col: u8) -> Option<u8> {
+    let mut layer = layer as usize;
+    loop {
+        match KEYMAP.layers[layer].keymap[row as usize][col as usize] {
+            KeyAction::Normal(KeyCode::Custom1(index)) => return Some(index),
+            KeyAction::Inherit if layer > 0 => layer -= 1,
+            _ => return None,
+        }
+    }
+}
+
+/// Tap-hold action built from two plain key actions.
+const fn th(tap: KeyAction, hold: KeyAction) -> KeyAction {
+    KeyAction::TapHold(kc(tap), kc(hold))
+}
+
+include!(concat!(env!("OUT_DIR"), "/keymap.rs"));
@@ -0,0 +1,180 @@
+//! Leader key sequences, matched on the keyboard reports of the key manager.
+//!
+//! Once the `LEADER` key is pressed, the keys pressed next are taken out of the reports, so
+//! they do not reach the host, and are matched against the `leader` sequences:
+//!
+//! - the keys are a sequence and no longer sequence starts with them: its text is typed
+//! - the keys are a sequence, but a longer one starts with them: its text is typed unless
+//!   another key is pressed within `timeout_ms`
+//! - no sequence starts with the keys: the leader ends without typing anything
+//! - no key is pressed within `timeout_ms`: the same, unless the keys are a sequence
+//!
+//! The timeout restarts with every key. Sequences match on key codes, so they work on every
+//! layer which has the keys.
+
+use crate::{
+    key_events::{KeyEvent, KeyboardState},
+    send_string::SendString,
+};
+
+/// Maximum number of keys in a sequence.
+pub const MAX_SEQUENCE: usize = 8;
+
+#[derive(Debug, Clone, Copy)]
+pub struct LeaderSequence {
+    /// HID key codes, in the order they are pressed.
+    pub keys: &'static [u8],
+    pub text: &'static str,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LeaderConfig {
+    /// `(layer, row, col)` of the leader keys, with `layer` the highest active layer.
+    pub keys: &'static [(u8, u8, u8)],
+    /// 0 disables the timeout.
+    pub timeout_ms: u32,
+    pub sequences: &'static [LeaderSequence],
+}
+
+pub struct Leader {
+    config: LeaderConfig,
+    layer: u8,
+    /// Time of the leader key or of the last key of the sequence while collecting.
+    collecting_since: Option<u64>,
+    sequence: [u8; MAX_SEQUENCE],
+    len: usize,
+    /// Key codes of the previous report, to tell which keys are newly pressed.
+    previous: [u8; 6],
+    /// Keys taken for the sequence which are still held, and must stay out of the reports.
+    taken: [u8; 6],
+    output: SendString,
+}
+
+impl Leader {
+    pub const fn new(config: LeaderConfig) -> Self {
+        let mut i = 0;
+        while i < config.sequences.len() {
+            assert!(
+                config.sequences[i].keys.len() <= MAX_SEQUENCE,
+                "keymap.json: leader sequence too long"
+            );
+            i += 1;
+        }
+        Self {
+            config,
+            layer: 0,
+            collecting_since: None,
+            sequence: [0; MAX_SEQUENCE],
+            len: 0,
+            previous: [0; 6],
+            taken: [0; 6],
+            output: SendString::new(),
+        }
+    }
+
+    pub fn set_layer(&mut self, highest_layer: u8) {
+        self.layer = highest_layer;
+    }
+
+    /// Whether a sequence is being collected.
+    pub fn is_active(&self) -> bool {
+        self.collecting_since.is_some()
+    }
+
+    /// Feeds a key event passed to the key manager, and starts a sequence on a leader key
+    /// press.
+    pub fn key_event(&mut self, event: KeyEvent, now_ms: u64) {
+        if event.pressed
+            && self
+                .config
+                .keys
+                .contains(&(self.layer, event.row, event.col))
+        {
+            self.collecting_since = Some(now_ms);
+            self.len = 0;
+        }
+    }
+
+    /// Ends the sequence after the timeout. Call this on every scan.
+    pub fn tick(&mut self, now_ms: u64) {
+        let timeout = self.config.timeout_ms as u64;
+        let Some(since_ms) = self.collecting_since else {
+            return;
+        };
+        if timeout != 0 && now_ms.saturating_sub(since_ms) >= timeout {
+            self.collecting_since = None;
+            if let Some(sequence) = self.find(|keys| keys == self.keys()) {
+                self.output.start(sequence.text);
+            }
+        }
+    }
+
+    /// Takes the keys of a sequence out of a new keyboard report.
+    pub fn report(&mut self, report: &mut KeyboardState, now_ms: u64) {
+        for taken in &mut self.taken {
+            if !report.keycodes.contains(taken) {
+                *taken = 0;
+            }
+        }
+
+        let previous = core::mem::replace(&mut self.previous, report.keycodes);
+        for keycode in report.keycodes {
+            if keycode == 0 || previous.contains(&keycode) || self.collecting_since.is_none() {
+                continue;
+            }
+            if let Some(slot) = self.taken.iter_mut().find(|t| **t == 0) {
+                *slot = keycode;
+            }
+            self.push(keycode, now_ms);
+        }
+
+        for keycode in &mut report.keycodes {
+            if self.taken.contains(keycode) {
+                *keycode = 0;
+            }
+        }
+    }
+
+    /// Whether text of a matched sequence is left to send.
+    pub fn is_sending(&self) -> bool {
+        self.output.is_sending()
+    }
+
+    /// Next report of the text of a matched sequence, which replaces the key manager's.
+    pub fn next_output(&mut self) -> Option<KeyboardState> {
+        self.output.next_report()
+    }
+
+    fn keys(&self) -> &[u8] {
+        &self.sequence[..self.len]
+    }
+
+    fn find(&self, matches: impl Fn(&[u8]) -> bool) -> Option<LeaderSequence> {
+        self.config
+            .sequences
+            .iter()
+            .find(|s| matches(s.keys))
+            .copied()
+    }
+
+    fn push(&mut self, keycode: u8, now_ms: u64) {
+        if self.len < MAX_SEQUENCE {
+            self.sequence[self.len] = keycode;
+            self.len += 1;
+        }
+        self.collecting_since = Some(now_ms);
+
+        let keys = self.keys();
+        let exact = self.find(|s| s == keys);
+        let longer = self.find(|s| s.len() > keys.len() && s.starts_with(keys));
+        match (exact, longer) {
+            (Some(sequence), None) => {
+                self.collecting_since = None;
+                self.output.start(sequence.text);
+            }
+            (None, None) => self.collecting_since = None,
+            // Ambiguous or a prefix: wait for the next key or the timeout.
+            (_, Some(_)) => {}
+        }
+    }
+}
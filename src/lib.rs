@@ -3,13 +3,27 @@
 use core::panic::PanicInfo;
 
 use embassy_nrf::Peripherals;
-use rktk::config::Hand;
 use rktk_drivers_common::panic_utils;
 
-pub mod drivers;
+pub mod base_layout;
+pub mod board;
+pub mod caps_word;
+pub mod config;
+pub mod dynamic_macro;
+pub mod home_row_mods;
 pub mod hooks;
+pub mod host_layout;
+pub mod key_events;
+pub mod key_overrides;
+pub mod key_pipeline;
 pub mod keymap;
+pub mod leader;
 pub mod misc;
+pub mod oneshot;
+pub mod os_profile;
+pub mod send_string;
+pub mod storage;
+pub mod text_macro;
 
 #[cfg(feature = "alloc")]
 extern crate alloc;
@@ -68,6 +82,8 @@ use rktk_drivers_nrf::softdevice::ble::SoftdeviceBleReporterBuilder;
 #[cfg(feature = "sd")]
 use rktk_drivers_nrf::softdevice::flash::SharedFlash;
 
+/// Initializes the softdevice and returns the BLE reporter builder together with the
+/// flash handle, which is passed to [`storage::create_sd_storage`].
 #[cfg(feature = "sd")]
 pub async fn init_sd() -> (SoftdeviceBleReporterBuilder, &'static SharedFlash) {
     let sd = init_softdevice("negL");
@@ -81,7 +97,7 @@ pub async fn init_sd() -> (SoftdeviceBleReporterBuilder, &'static SharedFlash) {
             ..Default::default()
         },
     );
-    let (flash, _cache) = get_flash(sd);
+    let (flash, _) = get_flash(sd);
 
     rktk_drivers_nrf::softdevice::start_softdevice(sd).await;
     embassy_time::Timer::after_millis(200).await;
@@ -92,16 +108,64 @@ pub async fn init_sd() -> (SoftdeviceBleReporterBuilder, &'static SharedFlash) {
     )
 }
 
-pub const HAND: Hand = {
-    #[cfg(feature = "left")]
-    {
-        Hand::Left
-    }
-    #[cfg(feature = "right")]
-    {
-        Hand::Right
-    }
-};
+#[cfg(feature = "trouble")]
+use nrf_sdc::{self as sdc, mpsl};
+
+/// Starts the MPSL, which schedules the radio and flash operations of the SoftDevice
+/// Controller. Both roles start it with `trouble`, as flash is written through
+/// [`mpsl::Flash`] so that erasing a page does not stall a radio event.
+#[cfg(feature = "trouble")]
+pub fn init_mpsl(
+    spawner: embassy_executor::Spawner,
+    p: board::MpslResources,
+) -> &'static mpsl::MultiprotocolServiceLayer<'static> {
+    let mpsl_p =
+        mpsl::Peripherals::new(p.rtc0, p.timer0, p.temp, p.ppi_ch19, p.ppi_ch30, p.ppi_ch31);
+    let lfclk_cfg = mpsl::raw::mpsl_clock_lfclk_cfg_t {
+        source: mpsl::raw::MPSL_CLOCK_LF_SRC_XTAL as u8,
+        rc_ctiv: 0,
+        rc_temp_ctiv: 0,
+        accuracy_ppm: mpsl::raw::MPSL_DEFAULT_CLOCK_ACCURACY_PPM as u16,
+        skip_wait_lfclk_started: mpsl::raw::MPSL_DEFAULT_SKIP_WAIT_LFCLK_STARTED != 0,
+    };
+    let mpsl = rktk::singleton!(
+        mpsl::MultiprotocolServiceLayer::new(mpsl_p, board::Irqs, lfclk_cfg).unwrap(),
+        mpsl::MultiprotocolServiceLayer<'static>
+    );
+    spawner.must_spawn(mpsl_task(mpsl));
+    mpsl
+}
+
+#[cfg(feature = "trouble")]
+#[embassy_executor::task]
+async fn mpsl_task(mpsl: &'static mpsl::MultiprotocolServiceLayer<'static>) -> ! {
+    mpsl.run().await
+}
+
+/// Memory of the SoftDevice Controller, enough for one peripheral link with the buffers
+/// passed to `buffer_cfg` in [`build_sdc`].
+#[cfg(feature = "trouble")]
+const SDC_MEM_SIZE: usize = 3312;
+
+/// Builds the SoftDevice Controller of the master on top of `mpsl`.
+#[cfg(feature = "trouble")]
+pub fn build_sdc(
+    p: board::SdcResources,
+    rng: &'static mut embassy_nrf::rng::Rng<'static, embassy_nrf::peripherals::RNG>,
+    mpsl: &'static mpsl::MultiprotocolServiceLayer<'static>,
+) -> Result<sdc::SoftdeviceController<'static>, sdc::Error> {
+    let sdc_p = sdc::Peripherals::new(
+        p.ppi_ch17, p.ppi_ch18, p.ppi_ch20, p.ppi_ch21, p.ppi_ch22, p.ppi_ch23, p.ppi_ch24,
+        p.ppi_ch25, p.ppi_ch26, p.ppi_ch27, p.ppi_ch28, p.ppi_ch29,
+    );
+    let mem = rktk::singleton!(sdc::Mem::<SDC_MEM_SIZE>::new(), sdc::Mem<SDC_MEM_SIZE>);
+    sdc::Builder::new()?
+        .support_adv()?
+        .support_peripheral()?
+        .peripheral_count(1)?
+        .buffer_cfg(72, 72, 3, 3)?
+        .build(sdc_p, rng, mpsl, mem)
+}
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
@@ -1,4 +1,6 @@
-use rktk::config::Hand;
+use embedded_graphics::prelude::Point;
+use once_cell::sync::OnceCell;
+use rktk::{config::Hand, drivers::interface::display::DisplayDriver};
 use rktk_drivers_common::mouse::paw3395;
 
 pub const PAW3395_CONFIG: paw3395::config::Config = paw3395::config::Config {
@@ -6,25 +8,145 @@ pub const PAW3395_CONFIG: paw3395::config::Config = paw3395::config::Config {
     lift_cutoff: paw3395::config::LiftCutoff::_2mm,
 };
 
-pub fn translate_key_position(row: usize, col: usize) -> Option<(usize, usize)> {
-    #[cfg(feature = "left")]
-    {
-        Some((row, 7 - col))
+/// UICR `CUSTOMER[0]`, which holds the hand of the board.
+///
+/// This is written once per board, e.g. `nrfjprog --memwr 0x10001080 --val 0x4c454654` for the
+/// left half (`"LEFT"`) or `--val 0x52474854` for the right half (`"RGHT"`), or by the firmware
+/// from [`hand_of_held_key`] on the first boot. UICR survives firmware updates, so the same
+/// image can be flashed to both halves.
+const UICR_HAND_ADDR: *mut u32 = 0x1000_1080 as *mut u32;
+const UICR_HAND_LEFT: u32 = u32::from_be_bytes(*b"LEFT");
+const UICR_HAND_RIGHT: u32 = u32::from_be_bytes(*b"RGHT");
+/// Value of a UICR register which was never written.
+const UICR_ERASED: u32 = u32::MAX;
+
+/// Hand given at build time with `NEGL_HAND=left` or `NEGL_HAND=right`, for boards which are
+/// flashed over UF2 and so cannot have UICR written. It takes precedence over UICR.
+const BUILD_HAND: Option<Hand> = match option_env!("NEGL_HAND") {
+    None => None,
+    Some(hand) => match hand.as_bytes() {
+        b"left" => Some(Hand::Left),
+        b"right" => Some(Hand::Right),
+        _ => panic!("NEGL_HAND must be `left` or `right`"),
+    },
+};
+
+static HAND: OnceCell<Hand> = OnceCell::new();
+
+/// Hand of this board from [`BUILD_HAND`] or else UICR, `None` if neither gives it.
+pub fn stored_hand() -> Option<Hand> {
+    if BUILD_HAND.is_some() {
+        return BUILD_HAND;
+    }
+    match unsafe { core::ptr::read_volatile(UICR_HAND_ADDR) } {
+        UICR_HAND_LEFT => Some(Hand::Left),
+        UICR_HAND_RIGHT => Some(Hand::Right),
+        _ => None,
     }
+}
 
-    #[cfg(feature = "right")]
-    {
-        Some((row, col))
+/// Hand chosen by the key held on boot, given by its position in the matrix of the half: the
+/// top-left key of the half, which is the outermost key of the top row on the left half and
+/// the innermost one on the right half.
+pub fn hand_of_held_key(row: usize, col: usize) -> Option<Hand> {
+    match (row, col) {
+        (0, c) if c == crate::config::COLS / 2 - 1 => Some(Hand::Left),
+        (0, 0) => Some(Hand::Right),
+        _ => None,
     }
 }
 
-pub const HAND: Hand = {
-    #[cfg(feature = "left")]
-    {
-        Hand::Left
+/// Writes `hand` to UICR, so that [`stored_hand`] finds it on the next boots. Does nothing
+/// unless UICR was never written, as its bits can only be cleared without erasing all of it.
+/// Must be called before the softdevice or the MPSL own the NVMC.
+pub fn store_hand(hand: Hand) {
+    use embassy_nrf::pac::{nvmc::vals::Wen, NVMC};
+
+    if unsafe { core::ptr::read_volatile(UICR_HAND_ADDR) } != UICR_ERASED {
+        rktk_log::warn!("UICR CUSTOMER[0] is already written, so the hand is not stored");
+        return;
+    }
+    let value = match hand {
+        Hand::Left => UICR_HAND_LEFT,
+        Hand::Right => UICR_HAND_RIGHT,
+    };
+    NVMC.config().write(|w| w.set_wen(Wen::WEN));
+    unsafe { core::ptr::write_volatile(UICR_HAND_ADDR, value) };
+    while !NVMC.ready().read().ready() {}
+    NVMC.config().write(|w| w.set_wen(Wen::REN));
+}
+
+/// Sets the hand of this board for the rest of the run. Called once on boot.
+pub fn set_hand(hand: Hand) {
+    let _ = HAND.set(hand);
+}
+
+/// Hand of this board, `None` until [`set_hand`] was called.
+pub fn hand() -> Option<Hand> {
+    HAND.get().copied()
+}
+
+/// Shown on the display when the hand is unknown. Lines are at most 4 characters wide, to fit
+/// the upright display.
+const UNKNOWN_HAND_MESSAGE: [&str; 8] = ["No", "hand", "Hold", "top", "left", "key", "on", "boot"];
+
+/// Tells on `display` how to give the hand, for a board which has none stored and had no key
+/// held on boot. The board then waits to be reset instead of guessing: a wrong hand swaps the
+/// columns of one half and can make both halves master.
+pub async fn show_unknown_hand(display: &mut impl DisplayDriver) {
+    for (i, line) in UNKNOWN_HAND_MESSAGE.iter().enumerate() {
+        let _ = display
+            .update_text(line, Point::new(0, i as i32 * 16))
+            .await;
     }
-    #[cfg(feature = "right")]
-    {
-        Hand::Right
+}
+
+/// Position of a key in the keymap from its position in the matrix of the half. Until the
+/// hand is known, keys keep their position in the matrix, for [`hand_of_held_key`].
+pub fn translate_key_position(row: usize, col: usize) -> Option<(usize, usize)> {
+    match hand() {
+        Some(Hand::Left) => Some((row, crate::config::COLS / 2 - 1 - col)),
+        Some(Hand::Right) | None => Some((row, col)),
     }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Master,
+    Slave,
+}
+
+/// Hand that is master in BLE builds, given at build time with `NEGL_BLE_MASTER=left` or
+/// `NEGL_BLE_MASTER=right`. The right half if not given.
+///
+/// Both halves must be built with the same value. The master is the half which talks to the
+/// host, so this is the half to plug into the computer when BLE is not used.
+pub const BLE_MASTER_HAND: Hand = match option_env!("NEGL_BLE_MASTER") {
+    None => Hand::Right,
+    Some(hand) => match hand.as_bytes() {
+        b"left" => Hand::Left,
+        b"right" => Hand::Right,
+        _ => panic!("NEGL_BLE_MASTER must be `left` or `right`"),
+    },
 };
+
+/// Decides the role of this half on boot. Exactly one half is master.
+///
+/// Builds without BLE make the half powered by USB the master, so either half can be plugged
+/// into the computer. BLE builds cannot do the same: a half on its battery cannot tell whether
+/// the other one has USB power before the split link is up, and the first connection decides
+/// nothing when both halves run on their batteries. They make [`BLE_MASTER_HAND`] the master
+/// whether it is powered by USB or by its battery, and the other half is a slave which only
+/// charges from USB.
+pub fn detect_role() -> Role {
+    let master = if cfg!(any(feature = "sd", feature = "trouble")) {
+        hand() == Some(BLE_MASTER_HAND)
+    } else {
+        embassy_nrf::pac::POWER.usbregstatus().read().vbusdetect()
+    };
+    if master {
+        Role::Master
+    } else {
+        Role::Slave
+    }
+}
@@ -0,0 +1,240 @@
+//! One-shot modifiers and layers resolved in front of the key manager.
+//!
+//! `OS(modifier)` and `OSL(layer)` in `keymap.json` are compiled to the plain modifier and to
+//! `MO(layer)`, so the key manager sees ordinary keys. [`OneShot`] only decides when their
+//! release reaches it:
+//!
+//! - held while another key is pressed: released when let go, like the plain key
+//! - tapped: the release is held back until the next key is released, so that this key gets
+//!   the modifier or is looked up on the layer. Tapping several one-shot modifiers stacks them,
+//!   and a one-shot modifier tapped on a one-shot layer ends the layer.
+//! - tapped and nothing else pressed within `timeout_ms`: released then
+//! - tapped again, or a cancel key (Esc) pressed while pending: released, and the key press
+//!   which cancelled is dropped
+//!
+//! At most `N` one-shots (`constant.key_manager.oneshot_state_size` in `rktk.json`) are
+//! tracked at once; further ones act as plain keys.
+
+use crate::key_events::{EventQueue, KeyEvent};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OneShotKind {
+    /// HID modifier bits.
+    Modifier(u8),
+    Layer(u8),
+}
+
+/// A one-shot key at `row, col` while `layer` is the highest active layer.
+#[derive(Debug, Clone, Copy)]
+pub struct OneShotKey {
+    pub layer: u8,
+    pub row: u8,
+    pub col: u8,
+    pub kind: OneShotKind,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OneShotConfig {
+    pub keys: &'static [OneShotKey],
+    /// 0 disables the timeout.
+    pub timeout_ms: u32,
+    /// Positions whose press cancels the pending one-shots.
+    pub cancel: &'static [(u8, u8)],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Physically held. `used` once another key was pressed meanwhile.
+    Held { used: bool },
+    /// Tapped, waiting for the next key.
+    Pending { since_ms: u64 },
+    /// Tapped, and released together with the key at this position.
+    Triggered { by: (u8, u8) },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Active {
+    key: OneShotKey,
+    state: State,
+}
+
+/// Key presses dropped by a cancel, whose releases must be dropped as well.
+const MAX_SWALLOWED: usize = 4;
+
+pub struct OneShot<const N: usize> {
+    config: OneShotConfig,
+    active: [Option<Active>; N],
+    swallowed: [Option<(u8, u8)>; MAX_SWALLOWED],
+    out: EventQueue,
+    layer: u8,
+}
+
+impl<const N: usize> OneShot<N> {
+    pub const fn new(config: OneShotConfig) -> Self {
+        Self {
+            config,
+            active: [None; N],
+            swallowed: [None; MAX_SWALLOWED],
+            out: EventQueue::new(),
+            layer: 0,
+        }
+    }
+
+    pub fn set_layer(&mut self, highest_layer: u8) {
+        self.layer = highest_layer;
+    }
+
+    /// Feeds a key event. Events to pass on are returned by [`Self::pop_event`].
+    pub fn event(&mut self, event: KeyEvent, now_ms: u64) {
+        let position = (event.row, event.col);
+
+        if !event.pressed {
+            if let Some(slot) = self.swallowed.iter_mut().find(|s| **s == Some(position)) {
+                *slot = None;
+                return;
+            }
+            match self.find(position) {
+                Some(active) if active.state == (State::Held { used: false }) => {
+                    active.state = State::Pending { since_ms: now_ms };
+                }
+                Some(_) => {
+                    self.remove(position);
+                    self.out.push(event);
+                }
+                None => self.out.push(event),
+            }
+            self.release_where(|state| state == State::Triggered { by: position });
+            return;
+        }
+
+        if self.config.cancel.contains(&position) && self.any_waiting() {
+            self.release_where(|state| {
+                matches!(state, State::Pending { .. } | State::Triggered { .. })
+            });
+            self.swallow(position);
+            return;
+        }
+
+        if self.find(position).is_some() {
+            // Tapped again while pending: cancel it.
+            self.remove(position);
+            self.out.push(KeyEvent {
+                pressed: false,
+                ..event
+            });
+            self.swallow(position);
+            return;
+        }
+
+        let key = self
+            .config
+            .keys
+            .iter()
+            .find(|k| k.layer == self.layer && (k.row, k.col) == position)
+            .copied();
+        for active in self.active.iter_mut().flatten() {
+            match (active.state, key) {
+                (State::Held { .. }, _) => active.state = State::Held { used: true },
+                // One-shot modifiers stack, everything else is the key they are waiting for.
+                (State::Pending { .. }, Some(_)) if !active.is_layer() => {}
+                (State::Pending { .. }, _) => active.state = State::Triggered { by: position },
+                (State::Triggered { .. }, _) => {}
+            }
+        }
+        if let Some(key) = key {
+            if let Some(slot) = self.active.iter_mut().find(|s| s.is_none()) {
+                *slot = Some(Active {
+                    key,
+                    state: State::Held { used: false },
+                });
+            }
+        }
+        self.out.push(event);
+    }
+
+    /// Releases one-shots which timed out. Call this on every scan.
+    pub fn tick(&mut self, now_ms: u64) {
+        let timeout = self.config.timeout_ms as u64;
+        if timeout == 0 {
+            return;
+        }
+        self.release_where(|state| match state {
+            State::Pending { since_ms } => now_ms.saturating_sub(since_ms) >= timeout,
+            _ => false,
+        });
+    }
+
+    /// Next event to pass to the key manager.
+    pub fn pop_event(&mut self) -> Option<KeyEvent> {
+        self.out.pop()
+    }
+
+    /// The one-shots which wait for the next key, for an indicator. A layer is returned
+    /// before modifiers; the modifiers of all pending one-shots are combined.
+    pub fn pending(&self) -> Option<OneShotKind> {
+        let waiting = || {
+            self.active
+                .iter()
+                .flatten()
+                .filter(|a| matches!(a.state, State::Pending { .. } | State::Triggered { .. }))
+        };
+        waiting()
+            .find(|a| a.is_layer())
+            .map(|a| a.key.kind)
+            .or_else(|| {
+                let modifiers = waiting().fold(0, |acc, a| match a.key.kind {
+                    OneShotKind::Modifier(bits) => acc | bits,
+                    OneShotKind::Layer(_) => acc,
+                });
+                (modifiers != 0).then_some(OneShotKind::Modifier(modifiers))
+            })
+    }
+
+    fn find(&mut self, position: (u8, u8)) -> Option<&mut Active> {
+        self.active
+            .iter_mut()
+            .flatten()
+            .find(|a| (a.key.row, a.key.col) == position)
+    }
+
+    fn remove(&mut self, position: (u8, u8)) {
+        for slot in &mut self.active {
+            if slot.is_some_and(|a| (a.key.row, a.key.col) == position) {
+                *slot = None;
+            }
+        }
+    }
+
+    fn any_waiting(&self) -> bool {
+        self.active
+            .iter()
+            .flatten()
+            .any(|a| matches!(a.state, State::Pending { .. } | State::Triggered { .. }))
+    }
+
+    /// Sends the held back release of the one-shots whose state matches, and forgets them.
+    fn release_where(&mut self, matches: impl Fn(State) -> bool) {
+        for slot in &mut self.active {
+            if let Some(active) = slot.filter(|a| matches(a.state)) {
+                self.out.push(KeyEvent {
+                    row: active.key.row,
+                    col: active.key.col,
+                    pressed: false,
+                });
+                *slot = None;
+            }
+        }
+    }
+
+    fn swallow(&mut self, position: (u8, u8)) {
+        if let Some(slot) = self.swallowed.iter_mut().find(|s| s.is_none()) {
+            *slot = Some(position);
+        }
+    }
+}
+
+impl Active {
+    fn is_layer(&self) -> bool {
+        matches!(self.key.kind, OneShotKind::Layer(_))
+    }
+}
@@ -0,0 +1,108 @@
+//! Host OS profile, applied to the modifiers of the keyboard reports of the key manager.
+//!
+//! The keymap is written for Windows and Linux hosts. With the Mac profile, every modifier in
+//! the reports is replaced by the one the `os_profile.mac` section of `keymap.json` maps it
+//! to, e.g. Ctrl and GUI swapped so that Cmd is where Ctrl is on Windows. Key overrides with
+//! an `os` only apply with that profile, which is how shortcuts that differ between the two
+//! are remapped.
+//!
+//! The `OS_PROFILE` key toggles the profile. The master hooks keep the profile in storage and
+//! show it on the display. The host is not detected from its USB enumeration, since the USB
+//! driver of rktk handles the setup requests without exposing them.
+
+use crate::key_events::KeyEvent;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Os {
+    Windows,
+    Mac,
+}
+
+impl Os {
+    pub fn name(self) -> &'static str {
+        match self {
+            Os::Windows => "Windows",
+            Os::Mac => "Mac",
+        }
+    }
+
+    /// Value kept in storage.
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Os::Windows => 0,
+            Os::Mac => 1,
+        }
+    }
+
+    /// Unknown values are [`Os::Windows`], the profile the keymap is written for.
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Os::Mac,
+            _ => Os::Windows,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OsProfileConfig {
+    /// `(layer, row, col)` of the keys toggling the profile, with `layer` the highest active
+    /// layer.
+    pub keys: &'static [(u8, u8, u8)],
+    /// HID modifier bits sent on a Mac for each modifier bit of the keymap, from bit 0
+    /// (left Ctrl) to bit 7 (right GUI).
+    pub mac_modifiers: [u8; 8],
+}
+
+pub struct OsProfile {
+    config: OsProfileConfig,
+    layer: u8,
+    os: Os,
+}
+
+impl OsProfile {
+    pub const fn new(config: OsProfileConfig) -> Self {
+        Self {
+            config,
+            layer: 0,
+            os: Os::Windows,
+        }
+    }
+
+    pub fn set_layer(&mut self, highest_layer: u8) {
+        self.layer = highest_layer;
+    }
+
+    pub fn os(&self) -> Os {
+        self.os
+    }
+
+    /// Selects a profile, e.g. the one read from storage.
+    pub fn select(&mut self, os: Os) {
+        self.os = os;
+    }
+
+    /// Feeds a key event passed to the key manager, and toggles on an `OS_PROFILE` key press.
+    pub fn key_event(&mut self, event: KeyEvent) {
+        if event.pressed
+            && self
+                .config
+                .keys
+                .contains(&(self.layer, event.row, event.col))
+        {
+            self.os = match self.os {
+                Os::Windows => Os::Mac,
+                Os::Mac => Os::Windows,
+            };
+        }
+    }
+
+    /// The modifier bits to send to the host for the modifier bits of the keymap.
+    pub fn modifiers(&self, modifier: u8) -> u8 {
+        match self.os {
+            Os::Windows => modifier,
+            Os::Mac => (0..8)
+                .filter(|bit| modifier & (1 << bit) != 0)
+                .fold(0, |out, bit| out | self.config.mac_modifiers[bit]),
+        }
+    }
+}
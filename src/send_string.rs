@@ -0,0 +1,108 @@
+//! Typing text as a sequence of keyboard reports, assuming a US host layout.
+
+use crate::key_events::KeyboardState;
+
+const SHIFT: u8 = 0x02;
+
+/// HID modifier and key code which type `c`, for printable ASCII, `\n` and `\t`.
+pub const fn ascii_key(c: u8) -> Option<(u8, u8)> {
+    let key = match c {
+        b'a'..=b'z' => (0, c - b'a' + 0x04),
+        b'A'..=b'Z' => (SHIFT, c - b'A' + 0x04),
+        b'1'..=b'9' => (0, c - b'1' + 0x1e),
+        b'0' => (0, 0x27),
+        b'\n' => (0, 0x28),
+        b'\t' => (0, 0x2b),
+        b' ' => (0, 0x2c),
+        b'!' => (SHIFT, 0x1e),
+        b'@' => (SHIFT, 0x1f),
+        b'#' => (SHIFT, 0x20),
+        b'$' => (SHIFT, 0x21),
+        b'%' => (SHIFT, 0x22),
+        b'^' => (SHIFT, 0x23),
+        b'&' => (SHIFT, 0x24),
+        b'*' => (SHIFT, 0x25),
+        b'(' => (SHIFT, 0x26),
+        b')' => (SHIFT, 0x27),
+        b'-' => (0, 0x2d),
+        b'_' => (SHIFT, 0x2d),
+        b'=' => (0, 0x2e),
+        b'+' => (SHIFT, 0x2e),
+        b'[' => (0, 0x2f),
+        b'{' => (SHIFT, 0x2f),
+        b']' => (0, 0x30),
+        b'}' => (SHIFT, 0x30),
+        b'\\' => (0, 0x31),
+        b'|' => (SHIFT, 0x31),
+        b';' => (0, 0x33),
+        b':' => (SHIFT, 0x33),
+        b'\'' => (0, 0x34),
+        b'"' => (SHIFT, 0x34),
+        b'`' => (0, 0x35),
+        b'~' => (SHIFT, 0x35),
+        b',' => (0, 0x36),
+        b'<' => (SHIFT, 0x36),
+        b'.' => (0, 0x37),
+        b'>' => (SHIFT, 0x37),
+        b'/' => (0, 0x38),
+        b'?' => (SHIFT, 0x38),
+        _ => return None,
+    };
+    Some(key)
+}
+
+/// Text being typed: each character is pressed in one report and released in the next.
+/// Characters without a key are skipped.
+pub struct SendString {
+    text: &'static [u8],
+    pos: usize,
+    pressed: bool,
+}
+
+impl SendString {
+    pub const fn new() -> Self {
+        Self {
+            text: &[],
+            pos: 0,
+            pressed: false,
+        }
+    }
+
+    /// Starts typing `text`, dropping what is left of the previous one.
+    pub fn start(&mut self, text: &'static str) {
+        *self = Self {
+            text: text.as_bytes(),
+            pos: 0,
+            pressed: false,
+        };
+    }
+
+    pub fn is_sending(&self) -> bool {
+        self.pressed || self.pos < self.text.len()
+    }
+
+    /// Next report to send, or `None` once the text is typed.
+    pub fn next_report(&mut self) -> Option<KeyboardState> {
+        if self.pressed {
+            self.pressed = false;
+            return Some(KeyboardState::default());
+        }
+        while let Some(&c) = self.text.get(self.pos) {
+            self.pos += 1;
+            if let Some((modifier, keycode)) = ascii_key(c) {
+                self.pressed = true;
+                return Some(KeyboardState {
+                    modifier,
+                    keycodes: [keycode, 0, 0, 0, 0, 0],
+                });
+            }
+        }
+        None
+    }
+}
+
+impl Default for SendString {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,68 @@
+//! Persistent storage used by rktk for keymap (rrp) and BLE bond data, and by the master
+//! hooks for their [`settings`].
+//!
+//! Every build keeps it in the region reserved as `STORAGE` in `memory.x`, laid out by
+//! [`FlashStorage`]: through the flash driver of the softdevice with `sd`, through the one of
+//! the MPSL with `trouble`, and through the raw NVMC otherwise.
+//!
+//! Data is laid out by `sequential-storage`: items are appended across all pages
+//! of the region, and a page is only erased once every other page is full, which
+//! spreads erase cycles evenly. Each item carries a CRC and page states are written
+//! after their contents, so an item interrupted by a reset is skipped on the next
+//! read instead of corrupting the map.
+//!
+//! [`FlashStorage`] only depends on an async `NorFlash`, so it can be driven by
+//! an in-memory flash on the host to check that written values read back.
+
+mod flash;
+pub mod schema;
+pub mod settings;
+
+pub use flash::*;
+
+#[cfg(feature = "sd")]
+mod sd;
+
+#[cfg(feature = "sd")]
+pub use sd::*;
+
+#[cfg(feature = "trouble")]
+mod mpsl;
+
+#[cfg(feature = "trouble")]
+pub use mpsl::*;
+
+#[cfg(not(any(feature = "sd", feature = "trouble")))]
+mod nvmc;
+
+#[cfg(not(any(feature = "sd", feature = "trouble")))]
+pub use nvmc::*;
+
+extern "C" {
+    static __storage_start: u32;
+    static __storage_end: u32;
+}
+
+/// Addresses of the `STORAGE` region of `memory.x`, exposed to the firmware through the
+/// `__storage_start` and `__storage_end` linker symbols.
+fn region() -> core::ops::Range<u32> {
+    use embassy_nrf::nvmc::PAGE_SIZE;
+
+    let (start, end) = unsafe {
+        (
+            &raw const __storage_start as u32,
+            &raw const __storage_end as u32,
+        )
+    };
+
+    assert!(
+        start % PAGE_SIZE as u32 == 0 && end % PAGE_SIZE as u32 == 0,
+        "storage region is not page aligned"
+    );
+    assert!(
+        end - start >= 2 * PAGE_SIZE as u32,
+        "storage region must span at least two pages"
+    );
+
+    start..end
+}
@@ -0,0 +1,133 @@
+//! [`FlashStorage`], the key-value store shared by every build, and [`SharedStorage`].
+//!
+//! Nothing here touches the hardware, so the host tools run the same code on an in-memory
+//! flash.
+
+use core::ops::Range;
+
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex};
+use embedded_storage_async::nor_flash::NorFlash;
+use rktk::drivers::interface::storage::StorageDriver;
+use sequential_storage::{
+    cache::NoCache,
+    map::{fetch_item, store_item},
+};
+
+use super::schema::ReadError;
+use crate::{dynamic_macro, text_macro};
+
+/// Size of the scratch buffer used to (de)serialize a single item, which holds its 8 byte key
+/// and its value. This must be larger than the biggest value stored: one keymap layer of rktk,
+/// or a macro of the hooks.
+pub const DATA_BUFFER_SIZE: usize = 2048;
+
+const _: () = assert!(
+    dynamic_macro::ENCODED_SIZE + 8 <= DATA_BUFFER_SIZE,
+    "a dynamic macro does not fit in the data buffer of the storage"
+);
+const _: () = assert!(
+    text_macro::MAX_SIZE + 8 <= DATA_BUFFER_SIZE,
+    "the text macros do not fit in the data buffer of the storage"
+);
+
+#[derive(Debug)]
+pub enum FlashStorageError<E> {
+    Flash(sequential_storage::Error<E>),
+    NotFound,
+    SizeMismatch,
+}
+
+impl<E> ReadError for FlashStorageError<E> {
+    fn is_not_found(&self) -> bool {
+        matches!(self, Self::NotFound)
+    }
+}
+
+impl<E> From<sequential_storage::Error<E>> for FlashStorageError<E> {
+    fn from(e: sequential_storage::Error<E>) -> Self {
+        Self::Flash(e)
+    }
+}
+
+/// Key-value storage on top of a [`NorFlash`] region, backed by `sequential-storage`.
+pub struct FlashStorage<F: NorFlash> {
+    flash: Mutex<CriticalSectionRawMutex, F>,
+    range: Range<u32>,
+}
+
+impl<F: NorFlash> FlashStorage<F> {
+    /// `range` must be aligned to `F::ERASE_SIZE` and span at least two pages.
+    pub const fn new(flash: F, range: Range<u32>) -> Self {
+        Self {
+            flash: Mutex::new(flash),
+            range,
+        }
+    }
+}
+
+impl<F: NorFlash> StorageDriver for FlashStorage<F> {
+    type Error = FlashStorageError<F::Error>;
+
+    async fn format(&self) -> Result<(), Self::Error> {
+        let mut flash = self.flash.lock().await;
+        sequential_storage::erase_all(&mut *flash, self.range.clone()).await?;
+        Ok(())
+    }
+
+    async fn read<const N: usize>(&self, key: u64, buf: &mut [u8; N]) -> Result<(), Self::Error> {
+        let mut flash = self.flash.lock().await;
+        let mut data_buffer = [0u8; DATA_BUFFER_SIZE];
+        let value = fetch_item::<u64, &[u8], _>(
+            &mut *flash,
+            self.range.clone(),
+            &mut NoCache::new(),
+            &mut data_buffer,
+            &key,
+        )
+        .await?
+        .ok_or(FlashStorageError::NotFound)?;
+
+        if value.len() != N {
+            return Err(FlashStorageError::SizeMismatch);
+        }
+        buf.copy_from_slice(value);
+
+        Ok(())
+    }
+
+    async fn write<const N: usize>(&self, key: u64, buf: &[u8; N]) -> Result<(), Self::Error> {
+        let mut flash = self.flash.lock().await;
+        let mut data_buffer = [0u8; DATA_BUFFER_SIZE];
+        store_item::<u64, &[u8], _>(
+            &mut *flash,
+            self.range.clone(),
+            &mut NoCache::new(),
+            &mut data_buffer,
+            &key,
+            &buf.as_slice(),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Lends a storage driver to rktk while the master hooks keep a reference to it for their
+/// [`settings`](super::settings).
+pub struct SharedStorage<'a, S>(pub &'a S);
+
+impl<S: StorageDriver> StorageDriver for SharedStorage<'_, S> {
+    type Error = S::Error;
+
+    async fn format(&self) -> Result<(), Self::Error> {
+        self.0.format().await
+    }
+
+    async fn read<const N: usize>(&self, key: u64, buf: &mut [u8; N]) -> Result<(), Self::Error> {
+        self.0.read(key, buf).await
+    }
+
+    async fn write<const N: usize>(&self, key: u64, buf: &[u8; N]) -> Result<(), Self::Error> {
+        self.0.write(key, buf).await
+    }
+}
@@ -0,0 +1,21 @@
+//! Storage through the flash driver of the MPSL, used with `trouble`.
+//!
+//! The SoftDevice Controller needs the CPU at exact times during radio events, which the NVMC
+//! would stall for up to 85 ms while erasing a page. [`Flash`] runs each write and erase in a
+//! timeslot the MPSL grants between radio events instead.
+
+use embassy_nrf::peripherals::NVMC;
+use nrf_sdc::mpsl::{Flash, MultiprotocolServiceLayer};
+
+use super::{region, FlashStorage};
+
+pub type MpslStorage = FlashStorage<Flash<'static>>;
+
+/// The flash driver addresses the whole flash, so the range of [`FlashStorage`] is the
+/// `STORAGE` region itself.
+pub fn create_mpsl_storage(
+    mpsl: &'static MultiprotocolServiceLayer<'static>,
+    nvmc: NVMC,
+) -> MpslStorage {
+    FlashStorage::new(Flash::take(mpsl, nvmc), region())
+}
@@ -0,0 +1,84 @@
+//! Storage on the raw NVMC, used by `cs-impl` builds which run no BLE stack. With a BLE
+//! stack, flash has to be scheduled around radio events, see [`super::mpsl`].
+
+use embassy_embedded_hal::adapter::BlockingAsync;
+use embassy_nrf::{
+    nvmc::{Error, Nvmc},
+    peripherals::NVMC,
+    Peripheral,
+};
+use embedded_storage::nor_flash::{ErrorType, MultiwriteNorFlash, NorFlash, ReadNorFlash};
+
+use super::{region, FlashStorage};
+
+/// A page aligned window into the NVMC. Offsets are relative to the start of the window and
+/// any access outside of it is rejected, so a bug in the storage layer cannot touch firmware.
+pub struct NvmcPartition {
+    nvmc: Nvmc<'static>,
+    start: u32,
+    len: u32,
+}
+
+impl NvmcPartition {
+    /// Creates the partition described by the `STORAGE` region of `memory.x`.
+    pub fn new(nvmc: impl Peripheral<P = NVMC> + 'static) -> Self {
+        let region = region();
+        Self {
+            nvmc: Nvmc::new(nvmc),
+            start: region.start,
+            len: region.end - region.start,
+        }
+    }
+
+    fn check(&self, offset: u32, len: usize) -> Result<u32, Error> {
+        match offset.checked_add(len as u32) {
+            Some(end) if end <= self.len => Ok(self.start + offset),
+            _ => Err(Error::OutOfBounds),
+        }
+    }
+}
+
+impl ErrorType for NvmcPartition {
+    type Error = Error;
+}
+
+impl ReadNorFlash for NvmcPartition {
+    const READ_SIZE: usize = <Nvmc<'static> as ReadNorFlash>::READ_SIZE;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let addr = self.check(offset, bytes.len())?;
+        self.nvmc.read(addr, bytes)
+    }
+
+    fn capacity(&self) -> usize {
+        self.len as usize
+    }
+}
+
+impl NorFlash for NvmcPartition {
+    const WRITE_SIZE: usize = <Nvmc<'static> as NorFlash>::WRITE_SIZE;
+    const ERASE_SIZE: usize = <Nvmc<'static> as NorFlash>::ERASE_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        if from > to {
+            return Err(Error::OutOfBounds);
+        }
+        let addr = self.check(from, (to - from) as usize)?;
+        self.nvmc.erase(addr, addr + (to - from))
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let addr = self.check(offset, bytes.len())?;
+        self.nvmc.write(addr, bytes)
+    }
+}
+
+impl MultiwriteNorFlash for NvmcPartition {}
+
+pub type NvmcStorage = FlashStorage<BlockingAsync<NvmcPartition>>;
+
+pub fn create_nvmc_storage(nvmc: impl Peripheral<P = NVMC> + 'static) -> NvmcStorage {
+    let partition = NvmcPartition::new(nvmc);
+    let len = partition.len;
+    FlashStorage::new(BlockingAsync::new(partition), 0..len)
+}
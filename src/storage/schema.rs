@@ -0,0 +1,185 @@
+//! Versioned header for data kept in [`super`] storage.
+//!
+//! Everything rktk persists (keymap overrides, bond slots, ...) is laid out according to the
+//! shape of [`crate::keymap::KEYMAP`] and the constants in `rktk.json`. A header holding the
+//! schema version and a fingerprint of that shape is stored next to the data and checked on
+//! boot by [`migrate`]. Data from an older schema is migrated; data that cannot be migrated, or
+//! whose header cannot be read, is erased, so rktk falls back to the compiled keymap.
+
+use rktk::drivers::interface::storage::StorageDriver;
+
+use crate::keymap::KEYMAP;
+
+/// Storage key of the header. Chosen to be far from the keys used by rktk itself.
+pub const HEADER_KEY: u64 = u64::from_be_bytes(*b"neglschm");
+
+/// Bump this when the layout of stored data changes, and add a step to [`migrate`].
+pub const SCHEMA_VERSION: u16 = 1;
+
+/// Fingerprint of the keymap shape the firmware was built with.
+pub const FINGERPRINT: u32 = {
+    let shape = [
+        KEYMAP.layers.len(),
+        KEYMAP.layers[0].keymap.len(),
+        KEYMAP.layers[0].keymap[0].len(),
+        KEYMAP.layers[0].encoder_keys.len(),
+        KEYMAP.tap_dance.len(),
+        KEYMAP.combo.len(),
+    ];
+
+    // FNV-1a
+    let mut hash: u32 = 0x811c_9dc5;
+    let mut i = 0;
+    while i < shape.len() {
+        let bytes = (shape[i] as u32).to_le_bytes();
+        let mut j = 0;
+        while j < bytes.len() {
+            hash ^= bytes[j] as u32;
+            hash = hash.wrapping_mul(0x0100_0193);
+            j += 1;
+        }
+        i += 1;
+    }
+    hash
+};
+
+const MAGIC: [u8; 4] = *b"NEGL";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchemaHeader {
+    pub version: u16,
+    pub fingerprint: u32,
+}
+
+impl SchemaHeader {
+    pub const SIZE: usize = 10;
+
+    pub const CURRENT: Self = Self {
+        version: SCHEMA_VERSION,
+        fingerprint: FINGERPRINT,
+    };
+
+    pub fn encode(&self) -> [u8; Self::SIZE] {
+        let mut buf = [0; Self::SIZE];
+        buf[0..4].copy_from_slice(&MAGIC);
+        buf[4..6].copy_from_slice(&self.version.to_le_bytes());
+        buf[6..10].copy_from_slice(&self.fingerprint.to_le_bytes());
+        buf
+    }
+
+    pub fn decode(buf: &[u8; Self::SIZE]) -> Option<Self> {
+        if buf[0..4] != MAGIC {
+            return None;
+        }
+        Some(Self {
+            version: u16::from_le_bytes([buf[4], buf[5]]),
+            fingerprint: u32::from_le_bytes([buf[6], buf[7], buf[8], buf[9]]),
+        })
+    }
+}
+
+/// What has to be done with the stored data on boot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Plan {
+    /// Data was written by this schema.
+    UpToDate,
+    /// Data was written by an older schema with the same keymap shape and can be upgraded.
+    Migrate { from: u16 },
+    /// Data is unreadable by this firmware and must be erased.
+    Reset,
+}
+
+/// Errors of a storage driver, which tell a key that was never written from a failed read.
+pub trait ReadError {
+    fn is_not_found(&self) -> bool;
+}
+
+/// What was found under [`HEADER_KEY`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stored {
+    /// No header. Firmware before [`SCHEMA_VERSION`] 1 did not write one, so this is either
+    /// data of version 0 or erased storage.
+    Missing,
+    Header(SchemaHeader),
+    /// An item which is not a header, or a read which failed.
+    Corrupt,
+}
+
+/// Reads the header in `storage`.
+pub async fn read_header<S: StorageDriver>(storage: &S) -> Stored
+where
+    S::Error: ReadError,
+{
+    let mut buf = [0; SchemaHeader::SIZE];
+    match storage
+        .read::<{ SchemaHeader::SIZE }>(HEADER_KEY, &mut buf)
+        .await
+    {
+        Ok(()) => SchemaHeader::decode(&buf).map_or(Stored::Corrupt, Stored::Header),
+        Err(e) if e.is_not_found() => Stored::Missing,
+        Err(_) => Stored::Corrupt,
+    }
+}
+
+/// Decides how to treat stored data described by `stored`.
+///
+/// A missing header is treated as version 0 with the current shape. Anything that cannot be
+/// read as a header is reset, as the data next to it cannot be trusted either.
+pub fn plan(stored: Stored) -> Plan {
+    let stored = match stored {
+        Stored::Missing => SchemaHeader {
+            version: 0,
+            fingerprint: FINGERPRINT,
+        },
+        Stored::Header(header) => header,
+        Stored::Corrupt => return Plan::Reset,
+    };
+
+    if stored.fingerprint != FINGERPRINT || stored.version > SCHEMA_VERSION {
+        Plan::Reset
+    } else if stored.version < SCHEMA_VERSION {
+        Plan::Migrate {
+            from: stored.version,
+        }
+    } else {
+        Plan::UpToDate
+    }
+}
+
+/// Checks the header in `storage` and migrates or erases the stored data accordingly.
+/// This must run before the storage is handed to rktk.
+pub async fn migrate<S: StorageDriver>(storage: &S)
+where
+    S::Error: ReadError,
+{
+    match plan(read_header(storage).await) {
+        Plan::UpToDate => return,
+        Plan::Migrate { from } => {
+            rktk_log::info!("Migrating storage from schema version {}", from);
+            let mut version = from;
+            while version < SCHEMA_VERSION {
+                match version {
+                    // v0 -> v1: only the header was added.
+                    0 => {}
+                    _ => unreachable!(),
+                }
+                version += 1;
+            }
+        }
+        Plan::Reset => {
+            rktk_log::warn!("Stored data is incompatible with this firmware. Erasing storage.");
+            if storage.format().await.is_err() {
+                rktk_log::error!("Failed to erase storage");
+                return;
+            }
+        }
+    }
+
+    if storage
+        .write::<{ SchemaHeader::SIZE }>(HEADER_KEY, &SchemaHeader::CURRENT.encode())
+        .await
+        .is_err()
+    {
+        rktk_log::error!("Failed to write storage header");
+    }
+}
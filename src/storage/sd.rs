@@ -0,0 +1,51 @@
+//! Storage through the flash driver of the softdevice, used with `sd`.
+//!
+//! The softdevice owns the NVMC while it runs and schedules each write and erase between radio
+//! events. Its flash is shared with the BLE reporter, which keeps bond data in it, so
+//! [`SdFlash`] only holds it locked for one operation at a time.
+
+use embedded_storage_async::nor_flash::{ErrorType, NorFlash, ReadNorFlash};
+use nrf_softdevice::{Flash, FlashError};
+use rktk_drivers_nrf::softdevice::flash::SharedFlash;
+
+use super::{region, FlashStorage};
+
+/// The flash of the softdevice, shared with the BLE reporter.
+pub struct SdFlash(&'static SharedFlash);
+
+impl ErrorType for SdFlash {
+    type Error = FlashError;
+}
+
+impl ReadNorFlash for SdFlash {
+    const READ_SIZE: usize = <Flash as ReadNorFlash>::READ_SIZE;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.0.lock().await.read(offset, bytes).await
+    }
+
+    fn capacity(&self) -> usize {
+        embassy_nrf::nvmc::FLASH_SIZE
+    }
+}
+
+impl NorFlash for SdFlash {
+    const WRITE_SIZE: usize = <Flash as NorFlash>::WRITE_SIZE;
+    const ERASE_SIZE: usize = <Flash as NorFlash>::ERASE_SIZE;
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        self.0.lock().await.erase(from, to).await
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0.lock().await.write(offset, bytes).await
+    }
+}
+
+pub type SdStorage = FlashStorage<SdFlash>;
+
+/// The flash driver addresses the whole flash, so the range of [`FlashStorage`] is the
+/// `STORAGE` region itself.
+pub fn create_sd_storage(flash: &'static SharedFlash) -> SdStorage {
+    FlashStorage::new(SdFlash(flash), region())
+}
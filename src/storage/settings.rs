@@ -0,0 +1,145 @@
+//! Settings of the master hooks, kept in [`super`] storage next to the data of rktk.
+//!
+//! Each setting is one small item under its own key. A setting which was never written, or
+//! cannot be read, has its default value.
+
+use rktk::drivers::interface::storage::StorageDriver;
+
+use crate::{
+    dynamic_macro::{ENCODED_SIZE, SLOTS},
+    host_layout::Host,
+    os_profile::Os,
+    text_macro::{validate, DecodeError, MAX_SIZE},
+};
+
+/// Storage key of the selected base layout, see [`crate::base_layout`].
+pub const BASE_LAYOUT_KEY: u64 = u64::from_be_bytes(*b"neglbase");
+
+/// Index of the selected base layout, 0 (layer 0 of the keymap) if none was stored.
+pub async fn load_base_layout<S: StorageDriver>(storage: &S) -> u8 {
+    let mut buf = [0; 1];
+    match storage.read::<1>(BASE_LAYOUT_KEY, &mut buf).await {
+        Ok(()) => buf[0],
+        Err(_) => 0,
+    }
+}
+
+pub async fn store_base_layout<S: StorageDriver>(storage: &S, index: u8) {
+    if storage.write::<1>(BASE_LAYOUT_KEY, &[index]).await.is_err() {
+        rktk_log::error!("Failed to store the base layout");
+    }
+}
+
+/// Storage key of the OS profile, see [`crate::os_profile`].
+pub const OS_PROFILE_KEY: u64 = u64::from_be_bytes(*b"neglos__");
+
+/// The stored OS profile, [`Os::Windows`] if none was stored.
+pub async fn load_os_profile<S: StorageDriver>(storage: &S) -> Os {
+    let mut buf = [0; 1];
+    match storage.read::<1>(OS_PROFILE_KEY, &mut buf).await {
+        Ok(()) => Os::from_u8(buf[0]),
+        Err(_) => Os::Windows,
+    }
+}
+
+pub async fn store_os_profile<S: StorageDriver>(storage: &S, os: Os) {
+    if storage
+        .write::<1>(OS_PROFILE_KEY, &[os.to_u8()])
+        .await
+        .is_err()
+    {
+        rktk_log::error!("Failed to store the OS profile");
+    }
+}
+
+/// Storage key of the keyboard layout of the host, see [`crate::host_layout`].
+pub const HOST_LAYOUT_KEY: u64 = u64::from_be_bytes(*b"negllang");
+
+/// The stored layout of the host, [`Host::Us`] if none was stored.
+pub async fn load_host_layout<S: StorageDriver>(storage: &S) -> Host {
+    let mut buf = [0; 1];
+    match storage.read::<1>(HOST_LAYOUT_KEY, &mut buf).await {
+        Ok(()) => Host::from_u8(buf[0]),
+        Err(_) => Host::Us,
+    }
+}
+
+pub async fn store_host_layout<S: StorageDriver>(storage: &S, host: Host) {
+    if storage
+        .write::<1>(HOST_LAYOUT_KEY, &[host.to_u8()])
+        .await
+        .is_err()
+    {
+        rktk_log::error!("Failed to store the host layout");
+    }
+}
+
+/// Storage keys of the dynamic macros, see [`crate::dynamic_macro`].
+pub const DYNAMIC_MACRO_KEYS: [u64; SLOTS] = [
+    u64::from_be_bytes(*b"neglmac1"),
+    u64::from_be_bytes(*b"neglmac2"),
+];
+
+/// The stored dynamic macro of `slot`, `None` if none was stored.
+pub async fn load_dynamic_macro<S: StorageDriver>(
+    storage: &S,
+    slot: usize,
+) -> Option<[u8; ENCODED_SIZE]> {
+    let mut buf = [0; ENCODED_SIZE];
+    storage
+        .read::<ENCODED_SIZE>(DYNAMIC_MACRO_KEYS[slot], &mut buf)
+        .await
+        .ok()
+        .map(|()| buf)
+}
+
+pub async fn store_dynamic_macro<S: StorageDriver>(
+    storage: &S,
+    slot: usize,
+    buf: &[u8; ENCODED_SIZE],
+) {
+    if storage
+        .write::<ENCODED_SIZE>(DYNAMIC_MACRO_KEYS[slot], buf)
+        .await
+        .is_err()
+    {
+        rktk_log::error!("Failed to store dynamic macro {}", slot + 1);
+    }
+}
+
+/// Storage key of the text macros, see [`crate::text_macro`].
+pub const TEXT_MACROS_KEY: u64 = u64::from_be_bytes(*b"neglmacr");
+
+/// The stored text macros in their wire format, `None` if none were stored.
+pub async fn load_text_macros<S: StorageDriver>(storage: &S) -> Option<[u8; MAX_SIZE]> {
+    let mut buf = [0; MAX_SIZE];
+    storage
+        .read::<MAX_SIZE>(TEXT_MACROS_KEY, &mut buf)
+        .await
+        .ok()
+        .map(|()| buf)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextMacrosWriteError {
+    /// The macros failed [`validate`]. Nothing was stored.
+    Invalid(DecodeError),
+    /// The storage driver failed to write them.
+    Storage,
+}
+
+/// Checks text macros in their wire format with [`validate`] and stores them, padded to
+/// [`MAX_SIZE`], in place of the stored ones. Returns them as stored.
+pub async fn write_text_macros<S: StorageDriver>(
+    storage: &S,
+    bytes: &[u8],
+) -> Result<[u8; MAX_SIZE], TextMacrosWriteError> {
+    let len = validate(bytes).map_err(TextMacrosWriteError::Invalid)?;
+    let mut buf = [0; MAX_SIZE];
+    buf[..len].copy_from_slice(&bytes[..len]);
+    storage
+        .write::<MAX_SIZE>(TEXT_MACROS_KEY, &buf)
+        .await
+        .map_err(|_| TextMacrosWriteError::Storage)?;
+    Ok(buf)
+}
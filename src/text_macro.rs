@@ -0,0 +1,427 @@
+//! Named text macros: text, key taps and delays typed by a key, read from storage so that they
+//! can be changed without reflashing.
+//!
+//! `MACRO(n)` in `keymap.json` compiles to the custom key code `Custom1(n)`. [`TextMacros`]
+//! looks the key events up in the keymap with [`TextMacroConfig::macro_at`], and types macro
+//! `n` for a `Custom1(n)` key, one report per scan. The `text_macros` section of `keymap.json`
+//! is compiled into [`TextMacroConfig::defaults`], and macros read from storage replace all of
+//! them.
+//!
+//! Both are kept in the same wire format, which is also what a host would send:
+//!
+//! ```text
+//! macros := VERSION count:u8 macro*
+//! macro  := name_len:u8 name steps_len:u16le step*
+//! step   := STEP_TEXT len:u8 text           types ASCII text, like crate::send_string
+//!         | STEP_TAP modifier:u8 key:u8     taps a key with modifiers
+//!         | STEP_DELAY ms:u16le             waits
+//! ```
+//!
+//! The master hooks read the macros from storage on boot. A host writes them with the
+//! [`Encoder`] and sends them to the `TEXT_MACROS_WRITE` endpoint of the hooks, which checks
+//! them with [`validate`], stores them under `TEXT_MACROS_KEY` and types them from then on.
+//! rktk's RRP server only answers its own fixed set of requests, so the endpoint is served by
+//! the hooks next to it.
+
+use crate::{
+    key_events::{KeyEvent, KeyboardState},
+    send_string::ascii_key,
+};
+
+pub const VERSION: u8 = 1;
+/// Largest size of the encoded macros, which storage keeps as one item.
+pub const MAX_SIZE: usize = 512;
+
+pub const STEP_TEXT: u8 = 1;
+pub const STEP_TAP: u8 = 2;
+pub const STEP_DELAY: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// Written with another version of the format.
+    Version(u8),
+    TooLarge,
+    /// A macro or step runs past the end of the data.
+    Truncated,
+    UnknownStep(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodeError {
+    /// The macros do not fit in the buffer, or in [`MAX_SIZE`].
+    TooLarge,
+    /// More than 255 macros, or a name longer than 255 bytes.
+    TooMany,
+    /// A step was added before the first macro.
+    NoMacro,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step<'a> {
+    Text(&'a [u8]),
+    Tap { modifier: u8, key: u8 },
+    Delay(u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextMacro<'a> {
+    pub name: &'a [u8],
+    steps: &'a [u8],
+}
+
+impl<'a> TextMacro<'a> {
+    pub fn steps(&self) -> Steps<'a> {
+        Steps { bytes: self.steps }
+    }
+}
+
+/// Length of the step at `pos`, which has to end before `end`.
+const fn step_len(bytes: &[u8], pos: usize, end: usize) -> Result<usize, DecodeError> {
+    let len = match bytes[pos] {
+        STEP_TEXT if pos + 1 >= end => return Err(DecodeError::Truncated),
+        STEP_TEXT => 2 + bytes[pos + 1] as usize,
+        STEP_TAP | STEP_DELAY => 3,
+        step => return Err(DecodeError::UnknownStep(step)),
+    };
+    if pos + len > end {
+        Err(DecodeError::Truncated)
+    } else {
+        Ok(len)
+    }
+}
+
+/// Position of the steps of the macro whose header is at `pos`, and their end.
+const fn macro_bounds(bytes: &[u8], pos: usize) -> Result<(usize, usize), DecodeError> {
+    if pos >= bytes.len() {
+        return Err(DecodeError::Truncated);
+    }
+    let start = pos + 1 + bytes[pos] as usize + 2;
+    if start > bytes.len() {
+        return Err(DecodeError::Truncated);
+    }
+    let end = start + u16::from_le_bytes([bytes[start - 2], bytes[start - 1]]) as usize;
+    if end > bytes.len() {
+        return Err(DecodeError::Truncated);
+    }
+    Ok((start, end))
+}
+
+/// Checks encoded macros, and returns their length. Bytes after the last macro, such as the
+/// padding of storage, are ignored.
+pub const fn validate(bytes: &[u8]) -> Result<usize, DecodeError> {
+    if bytes.len() > MAX_SIZE {
+        return Err(DecodeError::TooLarge);
+    }
+    if bytes.len() < 2 {
+        return Err(DecodeError::Truncated);
+    }
+    if bytes[0] != VERSION {
+        return Err(DecodeError::Version(bytes[0]));
+    }
+    let mut pos = 2;
+    let mut i = 0;
+    while i < bytes[1] {
+        let (start, end) = match macro_bounds(bytes, pos) {
+            Ok(bounds) => bounds,
+            Err(e) => return Err(e),
+        };
+        pos = start;
+        while pos < end {
+            match step_len(bytes, pos, end) {
+                Ok(len) => pos += len,
+                Err(e) => return Err(e),
+            }
+        }
+        i += 1;
+    }
+    Ok(pos)
+}
+
+/// The macros in `bytes`, after checking them with [`validate`].
+pub fn decode(bytes: &[u8]) -> Result<Macros<'_>, DecodeError> {
+    validate(bytes)?;
+    Ok(Macros {
+        bytes,
+        pos: 2,
+        left: bytes[1],
+    })
+}
+
+pub struct Macros<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    left: u8,
+}
+
+impl<'a> Iterator for Macros<'a> {
+    type Item = TextMacro<'a>;
+
+    fn next(&mut self) -> Option<TextMacro<'a>> {
+        if self.left == 0 {
+            return None;
+        }
+        let (start, end) = macro_bounds(self.bytes, self.pos).ok()?;
+        let name = &self.bytes[self.pos + 1..start - 2];
+        self.pos = end;
+        self.left -= 1;
+        Some(TextMacro {
+            name,
+            steps: &self.bytes[start..end],
+        })
+    }
+}
+
+pub struct Steps<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Iterator for Steps<'a> {
+    type Item = Step<'a>;
+
+    fn next(&mut self) -> Option<Step<'a>> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+        let len = step_len(self.bytes, 0, self.bytes.len()).ok()?;
+        let (step, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(match step[0] {
+            STEP_TEXT => Step::Text(&step[2..]),
+            STEP_TAP => Step::Tap {
+                modifier: step[1],
+                key: step[2],
+            },
+            _ => Step::Delay(u16::from_le_bytes([step[1], step[2]])),
+        })
+    }
+}
+
+/// Writes macros in the wire format: [`Self::begin`] starts a macro, and the steps added
+/// after it belong to it.
+pub struct Encoder<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    /// Position of the steps of the macro being written, 0 before the first one.
+    steps_start: usize,
+}
+
+impl<'a> Encoder<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Result<Self, EncodeError> {
+        let mut encoder = Self {
+            buf,
+            len: 0,
+            steps_start: 0,
+        };
+        encoder.push(&[VERSION, 0])?;
+        Ok(encoder)
+    }
+
+    pub fn begin(&mut self, name: &[u8]) -> Result<(), EncodeError> {
+        let count = self.buf[1].checked_add(1).ok_or(EncodeError::TooMany)?;
+        let name_len = u8::try_from(name.len()).map_err(|_| EncodeError::TooMany)?;
+        self.close();
+        self.push(&[name_len])?;
+        self.push(name)?;
+        self.push(&[0, 0])?;
+        self.buf[1] = count;
+        self.steps_start = self.len;
+        Ok(())
+    }
+
+    /// Adds text, split into steps of at most 255 bytes.
+    pub fn text(&mut self, text: &[u8]) -> Result<(), EncodeError> {
+        for chunk in text.chunks(255) {
+            self.step(&[STEP_TEXT, chunk.len() as u8])?;
+            self.push(chunk)?;
+        }
+        Ok(())
+    }
+
+    pub fn tap(&mut self, modifier: u8, key: u8) -> Result<(), EncodeError> {
+        self.step(&[STEP_TAP, modifier, key])
+    }
+
+    pub fn delay(&mut self, ms: u16) -> Result<(), EncodeError> {
+        let [low, high] = ms.to_le_bytes();
+        self.step(&[STEP_DELAY, low, high])
+    }
+
+    /// Length of the encoded macros.
+    pub fn finish(mut self) -> usize {
+        self.close();
+        self.len
+    }
+
+    fn step(&mut self, bytes: &[u8]) -> Result<(), EncodeError> {
+        if self.steps_start == 0 {
+            return Err(EncodeError::NoMacro);
+        }
+        self.push(bytes)
+    }
+
+    fn push(&mut self, bytes: &[u8]) -> Result<(), EncodeError> {
+        let end = self.len + bytes.len();
+        if end > self.buf.len().min(MAX_SIZE) {
+            return Err(EncodeError::TooLarge);
+        }
+        self.buf[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    /// Writes the length of the steps of the macro being written.
+    fn close(&mut self) {
+        if self.steps_start > 0 {
+            let len = (self.len - self.steps_start) as u16;
+            self.buf[self.steps_start - 2..self.steps_start].copy_from_slice(&len.to_le_bytes());
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TextMacroConfig {
+    /// Index of the macro typed by a key, from the highest active layer and the row and column
+    /// of the key.
+    pub macro_at: fn(u8, u8, u8) -> Option<u8>,
+    /// The macros of `keymap.json`, encoded.
+    pub defaults: &'static [u8],
+}
+
+pub struct TextMacros {
+    config: TextMacroConfig,
+    layer: u8,
+    bytes: [u8; MAX_SIZE],
+    playing: bool,
+    /// Position of the next step of the macro being typed, and the end of its steps.
+    cursor: usize,
+    end: usize,
+    /// End of the text step being typed. `cursor` is in the text while it is smaller.
+    text_end: usize,
+    /// Set after a key press, which is released in the next report.
+    pressed: bool,
+    wait_until_ms: u64,
+}
+
+impl TextMacros {
+    pub const fn new(config: TextMacroConfig) -> Self {
+        assert!(
+            validate(config.defaults).is_ok(),
+            "keymap.json: invalid text macros"
+        );
+        let mut bytes = [0; MAX_SIZE];
+        let mut i = 0;
+        while i < config.defaults.len() {
+            bytes[i] = config.defaults[i];
+            i += 1;
+        }
+        Self {
+            config,
+            layer: 0,
+            bytes,
+            playing: false,
+            cursor: 0,
+            end: 0,
+            text_end: 0,
+            pressed: false,
+            wait_until_ms: 0,
+        }
+    }
+
+    pub fn set_layer(&mut self, highest_layer: u8) {
+        self.layer = highest_layer;
+    }
+
+    /// Replaces the macros, e.g. with the ones read from storage. Invalid macros are not
+    /// taken.
+    pub fn load(&mut self, bytes: &[u8; MAX_SIZE]) -> Result<(), DecodeError> {
+        validate(bytes)?;
+        self.bytes = *bytes;
+        self.playing = false;
+        self.pressed = false;
+        Ok(())
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Feeds a key event passed to the key manager, and starts typing on a `MACRO` key press.
+    pub fn key_event(&mut self, event: KeyEvent) {
+        if !event.pressed {
+            return;
+        }
+        let Some(index) = (self.config.macro_at)(self.layer, event.row, event.col) else {
+            return;
+        };
+        if index >= self.bytes[1] {
+            return;
+        }
+        // The macros were validated when loaded.
+        let mut pos = 2;
+        for _ in 0..index {
+            let Ok((_, end)) = macro_bounds(&self.bytes, pos) else {
+                return;
+            };
+            pos = end;
+        }
+        let Ok((start, end)) = macro_bounds(&self.bytes, pos) else {
+            return;
+        };
+        self.playing = true;
+        self.cursor = start;
+        self.end = end;
+        self.text_end = start;
+        self.pressed = false;
+        self.wait_until_ms = 0;
+    }
+
+    /// Next report of the macro being typed, which replaces the key manager's. `None` while
+    /// a delay runs.
+    pub fn next_output(&mut self, now_ms: u64) -> Option<KeyboardState> {
+        if !self.playing {
+            return None;
+        }
+        if self.pressed {
+            self.pressed = false;
+            return Some(KeyboardState::default());
+        }
+        if now_ms < self.wait_until_ms {
+            return None;
+        }
+        loop {
+            if self.cursor < self.text_end {
+                let c = self.bytes[self.cursor];
+                self.cursor += 1;
+                if let Some((modifier, keycode)) = ascii_key(c) {
+                    return Some(self.press(modifier, keycode));
+                }
+                continue;
+            }
+            if self.cursor >= self.end {
+                self.playing = false;
+                return None;
+            }
+            let (step, a) = (self.bytes[self.cursor], self.bytes[self.cursor + 1]);
+            if step == STEP_TEXT {
+                self.text_end = self.cursor + 2 + usize::from(a);
+                self.cursor += 2;
+                continue;
+            }
+            let b = self.bytes[self.cursor + 2];
+            self.cursor += 3;
+            if step == STEP_TAP {
+                return Some(self.press(a, b));
+            }
+            self.wait_until_ms = now_ms + u64::from(u16::from_le_bytes([a, b]));
+            return None;
+        }
+    }
+
+    fn press(&mut self, modifier: u8, keycode: u8) -> KeyboardState {
+        self.pressed = true;
+        KeyboardState {
+            modifier,
+            keycodes: [keycode, 0, 0, 0, 0, 0],
+        }
+    }
+}
@@ -0,0 +1,18 @@
+//! Generates the same `config.rs` and `keymap.rs` as the firmware build script, so that the
+//! tools see exactly the keymap which is flashed.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+#[path = "../build/config.rs"]
+mod config;
+#[path = "../build/keymap.rs"]
+mod keymap;
+
+fn main() {
+    let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
+
+    println!("cargo:rerun-if-changed=../build");
+    let (config, limits) = config::generate_config(out);
+    config::generate_keymap(out, Path::new("../keymap.json"), &config, &limits);
+}
@@ -0,0 +1,44 @@
+//! Renders every layer of `keymap.json` to `<dir>/layer<N>-<name>.svg`.
+//!
+//! Usage: `cargo run --bin render-svg [dir]` (default: `svg`)
+
+use std::{fs, path::PathBuf, process::ExitCode};
+
+use negl_tools::{keymap_file, layout, rktk_config, svg};
+
+fn slug(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+fn run() -> Result<(), String> {
+    let dir = PathBuf::from(std::env::args().nth(1).unwrap_or_else(|| "svg".to_string()));
+    let file = keymap_file()?;
+    let geometry = layout::parse(&rktk_config()?).map_err(|e| format!("rktk.json: {e}"))?;
+
+    fs::create_dir_all(&dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+    for (i, layer) in file.layers.iter().enumerate() {
+        let path = dir.join(format!("layer{i}-{}.svg", slug(&layer.name)));
+        fs::write(&path, svg::render(&file, i, &geometry))
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        println!("wrote {}", path.display());
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! Converts the keymap to and from Vial's `.vil` format.
+//!
+//! Usage:
+//!
+//! - `cargo run --bin vial export [out.vil]` writes `keymap.json` as a `.vil` file
+//!   (default: `negl.vil`)
+//! - `cargo run --bin vial import <in.vil> [out.json]` writes the keymap of a `.vil` file in
+//!   the format of `keymap.json` (default: print it)
+
+use std::{fs, process::ExitCode};
+
+use negl_tools::{keymap_file, keymap_json, rktk_config, vial};
+
+fn export(out: &str) -> Result<(), String> {
+    let config = rktk_config()?;
+    let layout = keymap_json::layout_positions(&config)?;
+    let (rows, cols) = (negl_tools::config::ROWS, negl_tools::config::COLS);
+
+    let (vil, warnings) = vial::export(&keymap_file()?, &layout, rows, cols)?;
+    for warning in warnings {
+        eprintln!("warning: {warning}");
+    }
+    let json = serde_json::to_string_pretty(&vil).map_err(|e| e.to_string())?;
+    fs::write(out, json).map_err(|e| format!("{out}: {e}"))?;
+    println!("wrote {out}");
+    Ok(())
+}
+
+fn import(input: &str, out: Option<&str>) -> Result<(), String> {
+    let src = fs::read_to_string(input).map_err(|e| format!("{input}: {e}"))?;
+    let vil = serde_json::from_str(&src).map_err(|e| format!("{input}: {e}"))?;
+    let file = vial::import(&vil).map_err(|e| format!("{input}: {e}"))?;
+
+    // Check the result like the build script would, so that it can be used as is.
+    let layout = keymap_json::layout_positions(&rktk_config()?)?;
+    let json = serde_json::to_string_pretty(&file.to_json()).map_err(|e| e.to_string())?;
+    keymap_json::parse(&json, &layout).map_err(|e| format!("{input}: {e}"))?;
+
+    match out {
+        Some(out) => {
+            fs::write(out, json + "\n").map_err(|e| format!("{out}: {e}"))?;
+            println!("wrote {out}");
+        }
+        None => println!("{json}"),
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args.as_slice() {
+        ["export"] => export("negl.vil"),
+        ["export", out] => export(out),
+        ["import", input] => import(input, None),
+        ["import", input, out] => import(input, Some(out)),
+        _ => Err("usage: vial export [out.vil] | vial import <in.vil> [out.json]".to_string()),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Names for HID usages, so that recorded reports can be read without a usage table at hand.
+
+/// Name of a usage on the keyboard page (0x07), or its hex value if it has no name here.
+pub fn key_name(usage: u8) -> String {
+    let name = match usage {
+        0x04..=0x1d => return char::from(b'A' + (usage - 0x04)).to_string(),
+        0x1e..=0x26 => return char::from(b'1' + (usage - 0x1e)).to_string(),
+        0x27 => "0",
+        0x28 => "Enter",
+        0x29 => "Escape",
+        0x2a => "Backspace",
+        0x2b => "Tab",
+        0x2c => "Space",
+        0x2d => "Minus",
+        0x2e => "Equal",
+        0x2f => "LeftBracket",
+        0x30 => "RightBracket",
+        0x31 => "Backslash",
+        0x32 => "NonUsHash",
+        0x33 => "Semicolon",
+        0x34 => "Quote",
+        0x35 => "Grave",
+        0x36 => "Comma",
+        0x37 => "Dot",
+        0x38 => "Slash",
+        0x39 => "CapsLock",
+        0x3a..=0x45 => return format!("F{}", usage - 0x3a + 1),
+        0x46 => "PrintScreen",
+        0x47 => "ScrollLock",
+        0x48 => "Pause",
+        0x49 => "Insert",
+        0x4a => "Home",
+        0x4b => "PageUp",
+        0x4c => "Delete",
+        0x4d => "End",
+        0x4e => "PageDown",
+        0x4f => "Right",
+        0x50 => "Left",
+        0x51 => "Down",
+        0x52 => "Up",
+        0x53 => "NumLock",
+        0x54 => "KpSlash",
+        0x55 => "KpAsterisk",
+        0x56 => "KpMinus",
+        0x57 => "KpPlus",
+        0x58 => "KpEnter",
+        0x59..=0x61 => return format!("Kp{}", usage - 0x59 + 1),
+        0x62 => "Kp0",
+        0x63 => "KpDot",
+        0x87 => "International1",
+        0x88 => "International2",
+        0x89 => "International3",
+        0x8a => "International4",
+        0x8b => "International5",
+        0x90 => "Lang1",
+        0x91 => "Lang2",
+        _ => return format!("0x{usage:02x}"),
+    };
+    name.to_string()
+}
+
+const MODIFIER_NAMES: [&str; 8] = [
+    "LCtrl", "LShift", "LAlt", "LGui", "RCtrl", "RShift", "RAlt", "RGui",
+];
+
+/// Names of the modifiers set in a keyboard report modifier byte, joined with `+`.
+pub fn modifier_names(modifier: u8) -> String {
+    MODIFIER_NAMES
+        .iter()
+        .enumerate()
+        .filter(|(bit, _)| modifier & (1 << bit) != 0)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join("+")
+}
+
+const MOUSE_BUTTON_NAMES: [&str; 5] = ["Left", "Right", "Middle", "Back", "Forward"];
+
+/// Names of the pressed buttons in a mouse report button byte, joined with `+`.
+pub fn mouse_button_names(buttons: u8) -> String {
+    MOUSE_BUTTON_NAMES
+        .iter()
+        .enumerate()
+        .filter(|(bit, _)| buttons & (1 << bit) != 0)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join("+")
+}
+
+/// Name of a usage on the consumer page (0x0c), or its hex value if it has no name here.
+pub fn media_name(usage: u16) -> String {
+    let name = match usage {
+        0x00 => "None",
+        0xb5 => "NextTrack",
+        0xb6 => "PrevTrack",
+        0xb7 => "Stop",
+        0xcd => "PlayPause",
+        0xe2 => "Mute",
+        0xe9 => "VolumeUp",
+        0xea => "VolumeDown",
+        _ => return format!("0x{usage:04x}"),
+    };
+    name.to_string()
+}
@@ -0,0 +1,99 @@
+//! Physical key geometry from the KLE-style `dynamic.keyboard.layout.keymap` of `rktk.json`.
+//!
+//! Only the properties used by rktk layouts are interpreted: `x`, `y`, `w`, `h`, `r`, `rx`
+//! and `ry`. Their meaning follows keyboard-layout-editor: `rx`/`ry` set the rotation origin
+//! and move the cursor there, `x`/`y` move the cursor, `w`/`h` size the next key only, and
+//! every row starts one unit lower, at the x of the rotation origin.
+
+use serde_json::Value;
+
+/// One key, in key units (1u = the width of a standard key).
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyGeometry {
+    pub position: (usize, usize),
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+    /// Clockwise rotation in degrees around `(rx, ry)`.
+    pub r: f64,
+    pub rx: f64,
+    pub ry: f64,
+}
+
+impl KeyGeometry {
+    /// Corners of the key after rotation, for computing bounds.
+    pub fn corners(&self) -> [(f64, f64); 4] {
+        let (sin, cos) = self.r.to_radians().sin_cos();
+        let rotate = |x: f64, y: f64| {
+            let (dx, dy) = (x - self.rx, y - self.ry);
+            (self.rx + dx * cos - dy * sin, self.ry + dx * sin + dy * cos)
+        };
+        [
+            rotate(self.x, self.y),
+            rotate(self.x + self.w, self.y),
+            rotate(self.x, self.y + self.h),
+            rotate(self.x + self.w, self.y + self.h),
+        ]
+    }
+}
+
+/// Parses the layout of `rktk.json` into key geometries, in layout order.
+pub fn parse(config: &Value) -> Result<Vec<KeyGeometry>, String> {
+    let rows = config
+        .pointer("/dynamic/keyboard/layout/keymap")
+        .and_then(|l| l.as_array())
+        .ok_or("`dynamic.keyboard.layout.keymap` is missing")?;
+
+    let (mut r, mut rx, mut ry) = (0.0, 0.0, 0.0);
+    let (mut x, mut y) = (0.0, 0.0);
+    let mut keys = Vec::new();
+
+    for row in rows {
+        let row = row.as_array().ok_or("layout rows must be arrays")?;
+        let (mut w, mut h) = (1.0, 1.0);
+        for item in row {
+            match item {
+                Value::Object(props) => {
+                    let get = |name: &str| props.get(name).and_then(|v| v.as_f64());
+                    if let Some(v) = get("r") {
+                        r = v;
+                    }
+                    if let Some(v) = get("rx") {
+                        rx = v;
+                        (x, y) = (rx, ry);
+                    }
+                    if let Some(v) = get("ry") {
+                        ry = v;
+                        (x, y) = (rx, ry);
+                    }
+                    x += get("x").unwrap_or(0.0);
+                    y += get("y").unwrap_or(0.0);
+                    w = get("w").unwrap_or(w);
+                    h = get("h").unwrap_or(h);
+                }
+                Value::String(label) => {
+                    let position = crate::keymap_json::parse_position(label)
+                        .ok_or(format!("invalid key position `{label}` in layout"))?;
+                    keys.push(KeyGeometry {
+                        position,
+                        x,
+                        y,
+                        w,
+                        h,
+                        r,
+                        rx,
+                        ry,
+                    });
+                    x += w;
+                    (w, h) = (1.0, 1.0);
+                }
+                _ => return Err(format!("unexpected `{item}` in layout")),
+            }
+        }
+        y += 1.0;
+        x = rx;
+    }
+
+    Ok(keys)
+}
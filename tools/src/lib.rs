@@ -0,0 +1,79 @@
+//! Host-side tools for the negL keymap.
+//!
+//! The keymap is compiled from `keymap.json` exactly like the firmware does, and
+//! `src/keymap.rs` of the firmware is included as is. Run `cargo test` in this directory to
+//! check the keymap's behaviour on the host, and `NEGL_UPDATE_GOLDEN=1 cargo test` to
+//! rewrite the expected reports in `golden/` after an intended change.
+
+pub mod config {
+    include!(concat!(env!("OUT_DIR"), "/config.rs"));
+}
+
+#[path = "../../src/base_layout.rs"]
+pub mod base_layout;
+
+#[path = "../../src/caps_word.rs"]
+pub mod caps_word;
+
+#[path = "../../src/dynamic_macro.rs"]
+pub mod dynamic_macro;
+
+#[path = "../../src/home_row_mods.rs"]
+pub mod home_row_mods;
+
+#[path = "../../src/host_layout.rs"]
+pub mod host_layout;
+
+#[path = "../../src/key_events.rs"]
+pub mod key_events;
+
+#[path = "../../src/key_overrides.rs"]
+pub mod key_overrides;
+
+#[path = "../../src/key_pipeline.rs"]
+pub mod key_pipeline;
+
+#[path = "../../src/leader.rs"]
+pub mod leader;
+
+#[path = "../../src/oneshot.rs"]
+pub mod oneshot;
+
+#[path = "../../src/os_profile.rs"]
+pub mod os_profile;
+
+#[path = "../../src/send_string.rs"]
+pub mod send_string;
+
+#[path = "../../src/text_macro.rs"]
+pub mod text_macro;
+
+#[path = "../../src/keymap.rs"]
+pub mod keymap;
+
+/// The `keymap.json` parser shared with the build scripts.
+#[path = "../../build/keymap.rs"]
+pub mod keymap_json;
+
+pub mod hid;
+pub mod layout;
+pub mod lint;
+pub mod script;
+pub mod sim;
+pub mod storage;
+pub mod svg;
+pub mod vial;
+
+pub const KEYMAP_JSON: &str = include_str!("../../keymap.json");
+pub const RKTK_JSON: &str = include_str!("../../rktk.json");
+
+/// `rktk.json`, which the tools are built against.
+pub fn rktk_config() -> Result<serde_json::Value, String> {
+    serde_json::from_str(RKTK_JSON).map_err(|e| format!("rktk.json: {e}"))
+}
+
+/// `keymap.json`, parsed and checked against the layout in `rktk.json`.
+pub fn keymap_file() -> Result<keymap_json::KeymapFile, String> {
+    let layout = keymap_json::layout_positions(&rktk_config()?)?;
+    keymap_json::parse(KEYMAP_JSON, &layout).map_err(|e| format!("keymap.json: {e}"))
+}
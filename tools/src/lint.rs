@@ -0,0 +1,281 @@
+//! Lints for mistakes in `keymap.json` which compile fine but make the keyboard awkward or
+//! impossible to use.
+//!
+//! - **Unreachable layer** (error): no key on a reachable layer activates it.
+//! - **One-way toggle** (error): after `TG(n)`, no key on layer `n` (or falling through to the
+//!   base layer) toggles it off again.
+//! - **Shadowed key** (warning): a key on layer `n` sits under the key which holds layer `n`,
+//!   so it can never be pressed while the layer is active.
+//! - **Empty layer** (warning): a key or tap-dance slot activates a layer without any keys.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::keymap_json::{Expr, KeymapFile};
+
+/// Layer enabled by rktk when the trackball moves, without any key.
+pub const AUTO_MOUSE_LAYER: usize = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{severity}: {}", self.message)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LayerOp {
+    Momentary(usize),
+    Toggle(usize),
+    /// `OSL(n)`: active for one key after a tap, so it shadows nothing.
+    OneShot(usize),
+}
+
+impl LayerOp {
+    fn layer(self) -> usize {
+        match self {
+            LayerOp::Momentary(n) | LayerOp::Toggle(n) | LayerOp::OneShot(n) => n,
+        }
+    }
+}
+
+/// A layer operation reachable from a key, and how it is reached.
+struct KeyOp {
+    op: LayerOp,
+    /// `Some((n, i))` if the operation is in slot `i` of the tap or hold list of `TD(n)`.
+    tap_dance: Option<(usize, usize)>,
+}
+
+struct Linter<'a> {
+    file: &'a KeymapFile,
+    findings: Vec<Finding>,
+}
+
+impl Linter<'_> {
+    /// Layer operations of `expr`, looking through aliases, tap-holds and tap dances.
+    fn ops(&self, expr: &Expr) -> Vec<KeyOp> {
+        let mut ops = Vec::new();
+        self.collect_ops(expr, None, &mut ops);
+        ops
+    }
+
+    fn collect_ops(&self, expr: &Expr, tap_dance: Option<(usize, usize)>, ops: &mut Vec<KeyOp>) {
+        let Expr::Call(func, args) = self.file.resolve(expr) else {
+            return;
+        };
+        match (func.as_str(), args.as_slice()) {
+            ("MO", [Expr::Number(n)]) => ops.push(KeyOp {
+                op: LayerOp::Momentary(*n as usize),
+                tap_dance,
+            }),
+            ("TG", [Expr::Number(n)]) => ops.push(KeyOp {
+                op: LayerOp::Toggle(*n as usize),
+                tap_dance,
+            }),
+            ("OSL", [Expr::Number(n)]) => ops.push(KeyOp {
+                op: LayerOp::OneShot(*n as usize),
+                tap_dance,
+            }),
+            ("TH", [tap, hold]) => {
+                self.collect_ops(tap, tap_dance, ops);
+                self.collect_ops(hold, tap_dance, ops);
+            }
+            ("TD", [Expr::Number(n)]) if tap_dance.is_none() => {
+                let n = *n as usize;
+                if let Some(def) = self.file.tap_dance.get(n) {
+                    for list in [&def.tap, &def.hold] {
+                        for (slot, expr) in list.iter().enumerate() {
+                            if let Some(expr) = expr {
+                                self.collect_ops(expr, Some((n, slot)), ops);
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn is_transparent(&self, expr: &Expr) -> bool {
+        matches!(self.file.resolve(expr), Expr::Name(name) if name == "_____")
+    }
+
+    /// Keys pressable while `layer` is the highest active layer on top of the base layer.
+    fn effective_keys(&self, layer: usize) -> Vec<&Expr> {
+        let top = &self.file.layers[layer].keys;
+        let base = &self.file.layers[0].keys;
+        let positions: BTreeSet<_> = top.keys().chain(base.keys()).collect();
+        positions
+            .into_iter()
+            .filter_map(|pos| {
+                top.get(pos)
+                    .filter(|e| !self.is_transparent(e))
+                    .or_else(|| base.get(pos))
+            })
+            .collect()
+    }
+
+    fn layer_is_empty(&self, layer: usize) -> bool {
+        let def = &self.file.layers[layer];
+        def.keys.values().all(|e| self.is_transparent(e))
+            && def
+                .encoder
+                .iter()
+                .all(|(cw, ccw)| cw.is_none() && ccw.is_none())
+    }
+
+    fn report(&mut self, severity: Severity, message: String) {
+        self.findings.push(Finding { severity, message });
+    }
+
+    fn check_reachable(&mut self) {
+        let mut reachable = BTreeSet::from([0]);
+        if AUTO_MOUSE_LAYER < self.file.layers.len() {
+            reachable.insert(AUTO_MOUSE_LAYER);
+        }
+        let mut queue: Vec<usize> = reachable.iter().copied().collect();
+        while let Some(layer) = queue.pop() {
+            for expr in self.file.layers[layer].keys.values() {
+                for key_op in self.ops(expr) {
+                    let n = key_op.op.layer();
+                    if n < self.file.layers.len() && reachable.insert(n) {
+                        queue.push(n);
+                    }
+                }
+            }
+        }
+
+        for (i, layer) in self.file.layers.iter().enumerate() {
+            if !reachable.contains(&i) {
+                self.report(
+                    Severity::Error,
+                    format!(
+                        "layer {i} `{}` cannot be reached from the base layer",
+                        layer.name
+                    ),
+                );
+            }
+        }
+    }
+
+    fn check_toggles(&mut self) {
+        let toggled: BTreeSet<usize> = self
+            .file
+            .layers
+            .iter()
+            .flat_map(|l| l.keys.values())
+            .flat_map(|e| self.ops(e))
+            .filter_map(|k| match k.op {
+                LayerOp::Toggle(n) if n != 0 && n < self.file.layers.len() => Some(n),
+                _ => None,
+            })
+            .collect();
+
+        for n in toggled {
+            let can_return = self
+                .effective_keys(n)
+                .into_iter()
+                .any(|expr| self.ops(expr).iter().any(|k| k.op == LayerOp::Toggle(n)));
+            if !can_return {
+                self.report(
+                    Severity::Error,
+                    format!(
+                        "layer {n} `{}` is toggled on, but there is no TG({n}) on it to toggle it off",
+                        self.file.layers[n].name
+                    ),
+                );
+            }
+        }
+    }
+
+    fn check_shadowed(&mut self) {
+        let mut findings = Vec::new();
+        for (from, layer) in self.file.layers.iter().enumerate() {
+            for (&(row, col), expr) in &layer.keys {
+                for key_op in self.ops(expr) {
+                    let LayerOp::Momentary(n) = key_op.op else {
+                        continue;
+                    };
+                    if key_op.tap_dance.is_some() || n == from || n >= self.file.layers.len() {
+                        continue;
+                    }
+                    let target = &self.file.layers[n];
+                    if let Some(shadowed) = target
+                        .keys
+                        .get(&(row, col))
+                        .filter(|e| !self.is_transparent(e))
+                    {
+                        findings.push(format!(
+                            "key {row},{col} `{shadowed}` on layer {n} `{}` is under the key which holds the layer (`{expr}` on layer {from}) and cannot be pressed while it is held",
+                            target.name
+                        ));
+                    }
+                }
+            }
+        }
+        for message in findings {
+            self.report(Severity::Warning, message);
+        }
+    }
+
+    fn check_empty_targets(&mut self) {
+        let mut findings = Vec::new();
+        for (from, layer) in self.file.layers.iter().enumerate() {
+            for (&(row, col), expr) in &layer.keys {
+                for key_op in self.ops(expr) {
+                    let n = key_op.op.layer();
+                    if n >= self.file.layers.len() || !self.layer_is_empty(n) {
+                        continue;
+                    }
+                    let source = match key_op.tap_dance {
+                        Some((td, slot)) => format!("slot {slot} of TD({td})"),
+                        None => format!("`{expr}`"),
+                    };
+                    findings.push(format!(
+                        "{source} (key {row},{col} on layer {from}) activates layer {n} `{}`, which has no keys",
+                        self.file.layers[n].name
+                    ));
+                }
+            }
+        }
+        findings.dedup();
+        for message in findings {
+            self.report(Severity::Warning, message);
+        }
+    }
+}
+
+/// Runs every lint over `file`. Findings are sorted with errors first.
+pub fn lint(file: &KeymapFile) -> Vec<Finding> {
+    let mut linter = Linter {
+        file,
+        findings: Vec::new(),
+    };
+    if file.layers.is_empty() {
+        return linter.findings;
+    }
+
+    linter.check_reachable();
+    linter.check_toggles();
+    linter.check_shadowed();
+    linter.check_empty_targets();
+
+    let mut findings = linter.findings;
+    findings.sort_by_key(|f| std::cmp::Reverse(f.severity));
+    findings
+}
@@ -0,0 +1,90 @@
+//! Text format for recorded input scripts.
+//!
+//! Every line holds a time in milliseconds from the start of the script and an action:
+//!
+//! ```text
+//! # Tap L2LANG2.
+//! 0    press 4,5
+//! 20   release 4,5
+//! 30   move 8,-3
+//! 500  end
+//! ```
+//!
+//! `press` and `release` take a keymap position (`row,col`), `move` a trackball movement
+//! (`x,y`) and `end` only lets time pass, so that reports produced after the last input are
+//! recorded. Times must not decrease. Empty lines and lines starting with `#` are ignored.
+
+use crate::sim::Step;
+
+/// Parses a script into the steps understood by [`crate::sim::run`].
+pub fn parse(src: &str) -> Result<Vec<Step>, String> {
+    let mut steps = Vec::new();
+    let mut now = 0;
+
+    for (i, line) in src.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (time, action) = parse_line(line).map_err(|e| format!("line {}: {e}", i + 1))?;
+
+        if time < now {
+            return Err(format!(
+                "line {}: time {time} is before the previous line ({now})",
+                i + 1
+            ));
+        }
+        if time > now {
+            steps.push(Step::Wait(time - now));
+            now = time;
+        }
+        steps.extend(action);
+    }
+
+    Ok(steps)
+}
+
+fn parse_line(line: &str) -> Result<(u32, Option<Step>), String> {
+    let mut words = line.split_whitespace();
+    let time = words.next().unwrap_or_default();
+    let time = time
+        .parse()
+        .map_err(|_| format!("`{time}` is not a time in milliseconds"))?;
+    let action = words.next().ok_or("missing action")?;
+    let arg = words.next();
+    if let Some(extra) = words.next() {
+        return Err(format!("unexpected `{extra}`"));
+    }
+
+    let step = match (action, arg) {
+        ("press", Some(arg)) => {
+            let (row, col) = pair(arg)?;
+            Some(Step::Press(row, col))
+        }
+        ("release", Some(arg)) => {
+            let (row, col) = pair(arg)?;
+            Some(Step::Release(row, col))
+        }
+        ("move", Some(arg)) => {
+            let (x, y) = pair(arg)?;
+            Some(Step::MouseMove(x, y))
+        }
+        ("end", None) => None,
+        ("press" | "release" | "move", None) => {
+            return Err(format!("`{action}` needs an argument"))
+        }
+        ("end", Some(arg)) => return Err(format!("unexpected `{arg}`")),
+        _ => return Err(format!("unknown action `{action}`")),
+    };
+
+    Ok((time, step))
+}
+
+fn pair<T: core::str::FromStr>(arg: &str) -> Result<(T, T), String> {
+    let invalid = || format!("`{arg}` is not a pair of numbers");
+    let (a, b) = arg.split_once(',').ok_or_else(invalid)?;
+    Ok((
+        a.parse().map_err(|_| invalid())?,
+        b.parse().map_err(|_| invalid())?,
+    ))
+}
@@ -0,0 +1,259 @@
+//! Drives the rktk key manager with [`KEYMAP`] on the host.
+//!
+//! A script of [`Step`]s is replayed against a fresh key manager state, advancing time in
+//! steps of the keyboard scan interval like the firmware does, and every change of the
+//! resulting HID reports is recorded as an [`Output`]. Key events and keyboard reports pass
+//! through the same [`KeyPipeline`] as in the master hooks of the firmware, and key events go
+//! through a model of the keyboard event channel of rktk, into which the hooks send back the
+//! events the resolvers release, tagged, with the same [`Replay`].
+//!
+//! All use of the key manager API is contained in `Master` and `Recorder::record`, so an rktk
+//! upgrade which changes it only needs to touch these.
+
+use core::fmt;
+use core::time::Duration;
+use std::collections::VecDeque;
+
+use rktk::config::{
+    keymap::keymanager::state::{KeyChangeEvent, State, StateReport},
+    CONFIG,
+};
+
+use crate::{
+    hid,
+    key_events::{KeyEvent, KeyboardState, Replay},
+    key_pipeline::KeyPipeline,
+    keymap::KEYMAP,
+};
+
+/// One step of an input script. Positions are keymap positions, i.e. after the left half
+/// has been mirrored by `translate_key_position`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Press(u8, u8),
+    Release(u8, u8),
+    /// Move the trackball by `(x, y)` in one scan.
+    MouseMove(i8, i8),
+    /// Let `ms` milliseconds pass without input.
+    Wait(u32),
+}
+
+/// A change of one of the reports sent to the host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Report {
+    /// Keyboard report. `keys` holds the non-zero key codes in report order.
+    Keyboard {
+        modifier: u8,
+        keys: Vec<u8>,
+    },
+    MouseButtons(u8),
+    Media(u16),
+    /// Highest active layer. Not a report, but what the RGB hook reacts to.
+    Layer(u8),
+}
+
+/// A [`Report`] with the time it was produced, counted from the start of the script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Output {
+    pub time_ms: u32,
+    pub report: Report,
+}
+
+/// Capacity of the keyboard event channel in the model. Smaller than the number of events
+/// the resolvers can release at once, so that scripts can fill it.
+pub const EVENT_CHANNEL_SIZE: usize = 4;
+
+/// Replays `steps` and returns the reports which changed, in order.
+pub fn run(steps: &[Step]) -> Vec<Output> {
+    let interval = CONFIG.rktk.scan_interval_keyboard as u32;
+    let mut master = Master {
+        state: State::new(KEYMAP, CONFIG.key_manager.clone()),
+        pipeline: KeyPipeline::new(),
+        replay: Replay::new(),
+        channel: VecDeque::with_capacity(EVENT_CHANNEL_SIZE),
+        recorder: Recorder::default(),
+    };
+
+    let mut time_ms = 0;
+    for step in steps {
+        match *step {
+            Step::Press(row, col) | Step::Release(row, col) => {
+                let event = KeyEvent {
+                    row,
+                    col,
+                    pressed: matches!(step, Step::Press(..)),
+                };
+                master.key_event(time_ms, event);
+            }
+            Step::MouseMove(x, y) => master.scan(time_ms, (x, y), 0),
+            Step::Wait(ms) => {
+                let end = time_ms + ms;
+                while time_ms < end {
+                    let elapsed = interval.min(end - time_ms);
+                    time_ms += elapsed;
+                    master.scan(time_ms, (0, 0), elapsed);
+                }
+            }
+        }
+    }
+
+    master.recorder.outputs
+}
+
+/// The key manager with the master hooks of the firmware around it.
+struct Master {
+    state: State,
+    pipeline: KeyPipeline,
+    replay: Replay,
+    /// Key events waiting for the key manager, from the key scan or sent back by the hooks.
+    channel: VecDeque<KeyEvent>,
+    recorder: Recorder,
+}
+
+impl Master {
+    /// A key event from the scan.
+    fn key_event(&mut self, time_ms: u32, event: KeyEvent) {
+        self.channel.push_back(event);
+        self.take_events(time_ms);
+    }
+
+    /// Takes the events in the channel like the firmware does, including the ones sent back
+    /// meanwhile. Each goes through `on_keyboard_event`, which passes it on to the key manager
+    /// or takes it.
+    fn take_events(&mut self, time_ms: u32) {
+        let now_ms = time_ms.into();
+        while let Some(event) = self.channel.pop_front() {
+            let event = self.replay.returned(event).or_else(|| {
+                self.pipeline.event(event, now_ms);
+                self.replay.next(|| self.pipeline.next_event(now_ms))
+            });
+            if let Some(event) = event {
+                let mut events = [KeyChangeEvent {
+                    row: event.row,
+                    col: event.col,
+                    pressed: event.pressed,
+                }];
+                let report = self.state.update(&mut events, (0, 0), &[], Duration::ZERO);
+                self.state_update(time_ms, &report);
+            }
+        }
+        // Home-row mods whose press left the pipeline above change the modifiers.
+        if let Some(keyboard) = self.pipeline.modifier_report(now_ms) {
+            self.recorder.record_keyboard(time_ms, keyboard);
+        }
+    }
+
+    /// One scan without key events.
+    fn scan(&mut self, time_ms: u32, mouse: (i8, i8), elapsed_ms: u32) {
+        let elapsed = Duration::from_millis(elapsed_ms.into());
+        let report = self.state.update(&mut [], mouse, &[], elapsed);
+        self.state_update(time_ms, &report);
+        self.take_events(time_ms);
+    }
+
+    /// What `on_state_update` does with `report`, which is then recorded.
+    fn state_update(&mut self, time_ms: u32, report: &StateReport) {
+        self.pipeline.update(report.highest_layer, time_ms.into());
+        self.recorder.record(time_ms, report, &mut self.pipeline);
+        self.send_released(time_ms.into());
+    }
+
+    /// Sends the events released by the pipeline back into the channel.
+    fn send_released(&mut self, now_ms: u64) {
+        let channel = &mut self.channel;
+        self.replay.send(
+            || self.pipeline.next_event(now_ms),
+            |event| {
+                if channel.len() == EVENT_CHANNEL_SIZE {
+                    Err(event)
+                } else {
+                    channel.push_back(event);
+                    Ok(())
+                }
+            },
+        );
+    }
+}
+
+/// Keeps the last value of each report and records only the ones that change.
+#[derive(Default)]
+struct Recorder {
+    keyboard: (u8, Vec<u8>),
+    mouse_buttons: u8,
+    media: u16,
+    layer: u8,
+    outputs: Vec<Output>,
+}
+
+impl Recorder {
+    /// Passes `report` through `pipeline` like `on_state_update` does, and records it.
+    fn record(&mut self, time_ms: u32, report: &StateReport, pipeline: &mut KeyPipeline) {
+        let manager_report = report
+            .keyboard_report
+            .as_ref()
+            .map(|keyboard| KeyboardState {
+                modifier: keyboard.modifier,
+                keycodes: keyboard.keycodes,
+            });
+        if let Some(keyboard) = pipeline.keyboard_report(manager_report, time_ms.into()) {
+            self.record_keyboard(time_ms, keyboard);
+        }
+        if let Some(mouse) = &report.mouse_report {
+            if mouse.buttons != self.mouse_buttons {
+                self.mouse_buttons = mouse.buttons;
+                self.push(time_ms, Report::MouseButtons(mouse.buttons));
+            }
+        }
+        if let Some(media) = &report.media_keyboard_report {
+            if media.usage_id != self.media {
+                self.media = media.usage_id;
+                self.push(time_ms, Report::Media(media.usage_id));
+            }
+        }
+        if report.highest_layer != self.layer {
+            self.layer = report.highest_layer;
+            self.push(time_ms, Report::Layer(report.highest_layer));
+        }
+    }
+
+    /// Records the keyboard report sent to the host if it changed.
+    fn record_keyboard(&mut self, time_ms: u32, keyboard: KeyboardState) {
+        let keys: Vec<_> = keyboard.keycodes.into_iter().filter(|&k| k != 0).collect();
+        if (keyboard.modifier, &keys) != (self.keyboard.0, &self.keyboard.1) {
+            self.keyboard = (keyboard.modifier, keys.clone());
+            let modifier = keyboard.modifier;
+            self.push(time_ms, Report::Keyboard { modifier, keys });
+        }
+    }
+
+    fn push(&mut self, time_ms: u32, report: Report) {
+        self.outputs.push(Output { time_ms, report });
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Report::Keyboard { modifier, keys } => {
+                let keys: Vec<_> = keys.iter().map(|&k| hid::key_name(k)).collect();
+                write!(
+                    f,
+                    "keyboard [{}] [{}]",
+                    hid::modifier_names(*modifier),
+                    keys.join(", ")
+                )
+            }
+            Report::MouseButtons(buttons) => {
+                write!(f, "mouse [{}]", hid::mouse_button_names(*buttons))
+            }
+            Report::Media(usage) => write!(f, "media {}", hid::media_name(*usage)),
+            Report::Layer(layer) => write!(f, "layer {layer}"),
+        }
+    }
+}
+
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>6}ms {}", self.time_ms, self.report)
+    }
+}
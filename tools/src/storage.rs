@@ -0,0 +1,93 @@
+//! The storage layer of the firmware without the flash drivers of the board, and
+//! [`RamFlash`] to run it on.
+
+#[path = "../../src/storage/flash.rs"]
+mod flash;
+
+#[path = "../../src/storage/schema.rs"]
+pub mod schema;
+
+#[path = "../../src/storage/settings.rs"]
+pub mod settings;
+
+pub use flash::*;
+
+use embedded_storage_async::nor_flash::{
+    ErrorType, MultiwriteNorFlash, NorFlash, NorFlashErrorKind, ReadNorFlash,
+};
+
+/// Flash kept in memory, with the page and word sizes of the nRF52840 NVMC.
+///
+/// Like NOR flash, erasing sets every bit of a page and writing can only clear bits.
+pub struct RamFlash {
+    bytes: Vec<u8>,
+}
+
+impl RamFlash {
+    pub const PAGE_SIZE: usize = 4096;
+
+    /// An erased flash of `pages` pages.
+    pub fn new(pages: usize) -> Self {
+        Self {
+            bytes: vec![0xff; pages * Self::PAGE_SIZE],
+        }
+    }
+
+    /// The whole flash, as the range passed to [`FlashStorage::new`].
+    pub fn range(&self) -> core::ops::Range<u32> {
+        0..self.bytes.len() as u32
+    }
+
+    fn check(&self, offset: u32, len: usize, align: usize) -> Result<usize, NorFlashErrorKind> {
+        let offset = offset as usize;
+        if offset % align != 0 || len % align != 0 {
+            Err(NorFlashErrorKind::NotAligned)
+        } else if offset + len > self.bytes.len() {
+            Err(NorFlashErrorKind::OutOfBounds)
+        } else {
+            Ok(offset)
+        }
+    }
+}
+
+impl ErrorType for RamFlash {
+    type Error = NorFlashErrorKind;
+}
+
+impl ReadNorFlash for RamFlash {
+    const READ_SIZE: usize = 1;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let start = self.check(offset, bytes.len(), Self::READ_SIZE)?;
+        bytes.copy_from_slice(&self.bytes[start..start + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+impl NorFlash for RamFlash {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = Self::PAGE_SIZE;
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        if from > to {
+            return Err(NorFlashErrorKind::OutOfBounds);
+        }
+        let start = self.check(from, (to - from) as usize, Self::ERASE_SIZE)?;
+        self.bytes[start..to as usize].fill(0xff);
+        Ok(())
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let start = self.check(offset, bytes.len(), Self::WRITE_SIZE)?;
+        for (stored, byte) in self.bytes[start..].iter_mut().zip(bytes) {
+            *stored &= byte;
+        }
+        Ok(())
+    }
+}
+
+impl MultiwriteNorFlash for RamFlash {}
@@ -0,0 +1,378 @@
+//! Renders one layer of `keymap.json` onto the physical layout as an SVG cheat-sheet.
+//!
+//! Keys show their tap legend in the middle and, for tap-hold keys and home-row mods, the hold
+//! action below it.
+//! Transparent keys are drawn dashed with the base layer legend greyed out. Tap dances and
+//! encoder bindings are listed below the keyboard.
+
+use std::fmt::Write as _;
+
+use crate::keymap_json::{Expr, KeymapFile};
+use crate::layout::KeyGeometry;
+
+/// Size of one key unit in pixels.
+const UNIT: f64 = 60.0;
+const KEY_GAP: f64 = 4.0;
+const MARGIN: f64 = 20.0;
+const TITLE_HEIGHT: f64 = 36.0;
+const FOOTER_LINE_HEIGHT: f64 = 20.0;
+
+const STYLE: &str = "
+  text { font-family: sans-serif; text-anchor: middle; dominant-baseline: middle; }
+  .title { font-size: 20px; font-weight: bold; text-anchor: start; }
+  .footer { font-size: 14px; text-anchor: start; }
+  .key { fill: #f4f4f4; stroke: #555; stroke-width: 1.5; }
+  .key.transparent { fill: #fff; stroke: #bbb; stroke-dasharray: 4 3; }
+  .key.layer { fill: #dbe9f7; }
+  .tap { font-size: 14px; }
+  .hold { font-size: 10px; fill: #1f5fa8; }
+  .inherited { font-size: 12px; fill: #aaa; }
+";
+
+/// Legend of a key: the main (tap) label and an optional hold label.
+struct Legend {
+    tap: String,
+    hold: Option<String>,
+}
+
+/// Label of a single action as printed on a key.
+fn label(file: &KeymapFile, expr: &Expr) -> String {
+    let layer_name = |n: &u8| {
+        file.layers
+            .get(*n as usize)
+            .map(|l| l.name.clone())
+            .unwrap_or_else(|| n.to_string())
+    };
+    match file.resolve(expr) {
+        Expr::Name(name) => name_label(name).to_string(),
+        Expr::Number(n) => n.to_string(),
+        Expr::Call(func, args) => match (func.as_str(), args.as_slice()) {
+            ("Key" | "Media", [Expr::Name(variant)]) => key_variant_label(variant).to_string(),
+            ("SF", [key]) => match file.resolve(key) {
+                Expr::Name(name) => shifted_label(name)
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("⇧{}", name_label(name))),
+                key => format!("⇧{}", label(file, key)),
+            },
+            ("MO", [Expr::Number(n)]) => format!("MO {}", layer_name(n)),
+            ("TG", [Expr::Number(n)]) => format!("TG {}", layer_name(n)),
+            ("OSL", [Expr::Number(n)]) => format!("OSL {}", layer_name(n)),
+            ("OS", [modifier]) => format!("OS {}", label(file, modifier)),
+            ("TD", [Expr::Number(n)]) => format!("TD {n}"),
+            ("MACRO", [Expr::Number(n)]) => file
+                .text_macros
+                .get(*n as usize)
+                .map(|m| m.name.clone())
+                .unwrap_or_else(|| format!("Macro {n}")),
+            ("TH", [tap, hold]) => format!("{}/{}", label(file, tap), label(file, hold)),
+            _ => expr.to_string(),
+        },
+    }
+}
+
+fn legend(file: &KeymapFile, expr: &Expr) -> Legend {
+    match file.resolve(expr) {
+        Expr::Call(func, args) if func == "TH" && args.len() == 2 => Legend {
+            tap: label(file, &args[0]),
+            hold: Some(label(file, &args[1])),
+        },
+        expr => Legend {
+            tap: label(file, expr),
+            hold: None,
+        },
+    }
+}
+
+/// Shorter or more readable labels for prelude names. Other names are printed as is.
+fn name_label(name: &str) -> &str {
+    match name {
+        "__" => "",
+        "D0" | "D1" | "D2" | "D3" | "D4" | "D5" | "D6" | "D7" | "D8" | "D9" => &name[1..],
+        "MINUS" => "-",
+        "EQUAL" => "=",
+        "LBRC" => "[",
+        "BSLSH" => "\\",
+        "SCLN" => ";",
+        "QUOTE" => "'",
+        "COMM" => ",",
+        "DOT" => ".",
+        "SLASH" => "/",
+        "SPACE" => "Space",
+        "BS" => "Bksp",
+        "ESC" => "Esc",
+        "TAB" => "Tab",
+        "DELETE" => "Del",
+        "INSERT" => "Ins",
+        "HOME" => "Home",
+        "PGUP" => "PgUp",
+        "PRTSC" => "PrtSc",
+        "LEFT" => "←",
+        "DOWN" => "↓",
+        "UP" => "↑",
+        "RIGHT" => "→",
+        "L_CTRL" | "R_CTRL" => "Ctrl",
+        "L_SHFT" | "R_SHFT" => "Shift",
+        "L_ALT" => "Alt",
+        "L_GUI" => "Gui",
+        "VOLUP" => "Vol+",
+        "VOLDN" => "Vol-",
+        "M_LEFT" => "Click L",
+        "M_RIGHT" => "Click R",
+        "M_MIDDLE" => "Click M",
+        "M_BACK" => "Back",
+        "M_FORWARD" => "Fwd",
+        "MO_SCRL" => "Scroll",
+        "AML_RESET" => "AML off",
+        "FLASH_CLEAR" => "Flash clr",
+        "BLE_BOND_CLEAR" => "Bond clr",
+        "OUTPUT_BLE" => "→BLE",
+        "OUTPUT_USB" => "→USB",
+        "CAPS_WORD" => "Caps Word",
+        "LEADER" => "Leader",
+        "BASE_LAYOUT" => "Layout",
+        "OS_PROFILE" => "Win/Mac",
+        "HOST_LAYOUT" => "US/JIS",
+        "DM_REC1" => "Rec 1",
+        "DM_REC2" => "Rec 2",
+        "DM_RSTP" => "Rec stop",
+        "DM_PLY1" => "Play 1",
+        "DM_PLY2" => "Play 2",
+        _ => name,
+    }
+}
+
+fn key_variant_label(variant: &str) -> &str {
+    match variant {
+        "Enter" => "Enter",
+        "Space" => "Space",
+        "Grave" => "`",
+        "RightBracket" => "]",
+        "LeftBracket" => "[",
+        "Lang1" => "IME on",
+        "Lang2" => "IME off",
+        _ => variant,
+    }
+}
+
+/// Symbol produced by Shift + `name` on a US layout.
+fn shifted_label(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "D1" => "!",
+        "D2" => "@",
+        "D3" => "#",
+        "D4" => "$",
+        "D5" => "%",
+        "D6" => "^",
+        "D7" => "&",
+        "D8" => "*",
+        "D9" => "(",
+        "D0" => ")",
+        "MINUS" => "_",
+        "EQUAL" => "+",
+        "LBRC" => "{",
+        "BSLSH" => "|",
+        "SCLN" => ":",
+        "QUOTE" => "\"",
+        "COMM" => "<",
+        "DOT" => ">",
+        "SLASH" => "?",
+        _ => return None,
+    })
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn is_transparent(file: &KeymapFile, expr: Option<&Expr>) -> bool {
+    match expr.map(|e| file.resolve(e)) {
+        None => true,
+        Some(Expr::Name(name)) => name == "_____",
+        Some(_) => false,
+    }
+}
+
+fn is_layer_key(file: &KeymapFile, expr: &Expr) -> bool {
+    match file.resolve(expr) {
+        Expr::Call(func, args) => match func.as_str() {
+            "MO" | "TG" | "TD" | "OSL" => true,
+            "TH" => args.iter().any(|a| is_layer_key(file, a)),
+            _ => false,
+        },
+        Expr::Name(_) | Expr::Number(_) => false,
+    }
+}
+
+/// Lines listed below the keyboard: tap dances used on this layer, encoder bindings and, on
+/// the base layer, combos.
+fn footer(file: &KeymapFile, layer: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let def = &file.layers[layer];
+
+    for (i, td) in file.tap_dance.iter().enumerate() {
+        let used = def.keys.values().any(|e| {
+            matches!(file.resolve(e), Expr::Call(f, a) if f == "TD" && a == &[Expr::Number(i as u8)])
+        });
+        if !used {
+            continue;
+        }
+        let slots: Vec<_> = (0..td.tap.len().max(td.hold.len()))
+            .filter_map(|n| {
+                let tap = td.tap.get(n).cloned().flatten();
+                let hold = td.hold.get(n).cloned().flatten();
+                let tap = tap.map(|e| label(file, &e));
+                let hold = hold.map(|e| format!("hold {}", label(file, &e)));
+                let actions: Vec<_> = tap.into_iter().chain(hold).collect();
+                (!actions.is_empty()).then(|| format!("{}× {}", n + 1, actions.join(", ")))
+            })
+            .collect();
+        lines.push(format!("TD {i}: {}", slots.join("   ")));
+    }
+
+    for (i, (cw, ccw)) in def.encoder.iter().enumerate() {
+        let action = |e: &Option<Expr>| e.as_ref().map(|e| label(file, e)).unwrap_or_default();
+        lines.push(format!("Encoder {i}: ↻ {}   ↺ {}", action(cw), action(ccw)));
+    }
+
+    if layer == 0 {
+        for combo in &file.combo {
+            let src: Vec<_> = combo.src.iter().map(|e| label(file, e)).collect();
+            lines.push(format!(
+                "Combo: {} → {}",
+                src.join(" + "),
+                label(file, &combo.dst)
+            ));
+        }
+    }
+
+    lines
+}
+
+/// Renders `layer` of `file` on the keys of `geometry`.
+pub fn render(file: &KeymapFile, layer: usize, geometry: &[KeyGeometry]) -> String {
+    let def = &file.layers[layer];
+    let base = &file.layers[0];
+
+    let (mut max_x, mut max_y) = (0.0f64, 0.0f64);
+    let (mut min_x, mut min_y) = (f64::MAX, f64::MAX);
+    for (x, y) in geometry.iter().flat_map(|k| k.corners()) {
+        (min_x, min_y) = (min_x.min(x), min_y.min(y));
+        (max_x, max_y) = (max_x.max(x), max_y.max(y));
+    }
+    if geometry.is_empty() {
+        (min_x, min_y) = (0.0, 0.0);
+    }
+
+    let footer = footer(file, layer);
+    let offset_x = MARGIN - min_x * UNIT;
+    let offset_y = MARGIN + TITLE_HEIGHT - min_y * UNIT;
+    let width = (max_x - min_x) * UNIT + 2.0 * MARGIN;
+    let keys_bottom = offset_y + max_y * UNIT;
+    let height = keys_bottom + footer.len() as f64 * FOOTER_LINE_HEIGHT + 2.0 * MARGIN;
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width:.0}" height="{height:.0}" viewBox="0 0 {width:.0} {height:.0}">"#
+    )
+    .unwrap();
+    writeln!(out, "<style>{STYLE}</style>").unwrap();
+    writeln!(
+        out,
+        r#"<text class="title" x="{MARGIN}" y="{}">Layer {layer}: {}</text>"#,
+        MARGIN + TITLE_HEIGHT / 2.0,
+        escape(&def.name)
+    )
+    .unwrap();
+
+    for key in geometry {
+        let expr = def.keys.get(&key.position);
+        let transparent = is_transparent(file, expr);
+        let x = offset_x + key.x * UNIT + KEY_GAP / 2.0;
+        let y = offset_y + key.y * UNIT + KEY_GAP / 2.0;
+        let (w, h) = (key.w * UNIT - KEY_GAP, key.h * UNIT - KEY_GAP);
+        let (cx, cy) = (x + w / 2.0, y + h / 2.0);
+
+        let class = match expr {
+            _ if transparent => "key transparent",
+            Some(e) if is_layer_key(file, e) => "key layer",
+            _ => "key",
+        };
+
+        write!(out, "<g").unwrap();
+        if key.r != 0.0 {
+            write!(
+                out,
+                r#" transform="rotate({} {:.2} {:.2})""#,
+                key.r,
+                offset_x + key.rx * UNIT,
+                offset_y + key.ry * UNIT
+            )
+            .unwrap();
+        }
+        writeln!(out, ">").unwrap();
+        writeln!(
+            out,
+            r#"  <rect class="{class}" x="{x:.2}" y="{y:.2}" width="{w:.2}" height="{h:.2}" rx="6"/>"#
+        )
+        .unwrap();
+
+        match expr.filter(|_| !transparent) {
+            Some(expr) => {
+                let mut legend = legend(file, expr);
+                if let Some(def) = file
+                    .home_row_mods
+                    .as_ref()
+                    .and_then(|h| h.keys.get(&key.position))
+                    .filter(|_| layer == 0)
+                {
+                    legend.hold = Some(label(file, &Expr::Name(def.hold.clone())));
+                }
+                let tap_y = if legend.hold.is_some() { cy - 7.0 } else { cy };
+                writeln!(
+                    out,
+                    r#"  <text class="tap" x="{cx:.2}" y="{tap_y:.2}">{}</text>"#,
+                    escape(&legend.tap)
+                )
+                .unwrap();
+                if let Some(hold) = legend.hold {
+                    writeln!(
+                        out,
+                        r#"  <text class="hold" x="{cx:.2}" y="{:.2}">{}</text>"#,
+                        cy + 12.0,
+                        escape(&hold)
+                    )
+                    .unwrap();
+                }
+            }
+            None if layer != 0 => {
+                if let Some(inherited) = base.keys.get(&key.position) {
+                    writeln!(
+                        out,
+                        r#"  <text class="inherited" x="{cx:.2}" y="{cy:.2}">{}</text>"#,
+                        escape(&legend(file, inherited).tap)
+                    )
+                    .unwrap();
+                }
+            }
+            None => {}
+        }
+        writeln!(out, "</g>").unwrap();
+    }
+
+    for (i, line) in footer.iter().enumerate() {
+        writeln!(
+            out,
+            r#"<text class="footer" x="{MARGIN}" y="{:.2}">{}</text>"#,
+            keys_bottom + MARGIN + i as f64 * FOOTER_LINE_HEIGHT,
+            escape(line)
+        )
+        .unwrap();
+    }
+
+    writeln!(out, "</svg>").unwrap();
+    out
+}
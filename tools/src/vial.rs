@@ -0,0 +1,549 @@
+//! Conversion between `keymap.json` and Vial's `.vil` keymap files.
+//!
+//! A `.vil` file stores the keymap as `layout[layer][row][col]` of QMK keycode strings, with
+//! `-1` for matrix positions that have no key, `encoder_layout[layer][encoder]` as
+//! `[clockwise, counterclockwise]`, `tap_dance` as
+//! `[on_tap, on_hold, on_double_tap, on_tap_hold, tapping_term]` and `combo` as four sources
+//! followed by the result.
+//!
+//! Keys map to QMK keycodes as follows:
+//!
+//! - plain keys use the table in [`KEYCODES`]; rktk keys without a QMK equivalent use the Vial
+//!   custom keycodes `USER00`.. in the same table
+//! - `SF(k)` is `LSFT(k)`, `MO`/`TG`/`TD` keep their name
+//! - `TH(k, MO(n))` is `LT(n, k)` and `TH(k, modifier)` is the mod-tap `<MOD>_T(k)`
+//! - `OS(modifier)` is `OSM(MOD_<MOD>)` and `OSL(n)` keeps its name
+//!
+//! rktk tap dances have up to `tap_dance_max_repeats` taps while Vial has two. The full
+//! definitions are also written to `negl_tap_dance`, and the layer names to
+//! `negl_layer_names`. Vial ignores both, and [`import`] prefers them, so that a round trip
+//! through this module keeps them. Home-row mods have no Vial equivalent, since the keys are
+//! plain keys in the keymap, and are kept in `negl_home_row_mods` the same way. The one-shot
+//! and Caps Word settings are kept in `negl_oneshot` and `negl_caps_word`, and the leader
+//! sequences, which Vial does not store, in `negl_leader`. The key overrides are kept in
+//! `negl_key_overrides`, and Vial's own `key_override` list is left empty. The alternative
+//! base layouts are kept in `negl_base_layouts`, the OS profile in `negl_os_profile`
+//! and the dynamic macro settings in `negl_dynamic_macros`. `MACRO(n)` is Vial's `Mn`, but
+//! the text macros are kept in `negl_text_macros` and Vial's own `macro` list is left empty.
+
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+
+use crate::keymap_json::{
+    parse_base_layouts, parse_caps_word, parse_dynamic_macros, parse_home_row_mods,
+    parse_key_overrides, parse_leader, parse_oneshot, parse_os_profile, parse_text_macros,
+    ComboDef, Expr, KeymapFile, LayerDef, TapDanceDef,
+};
+
+/// Tapping term written to Vial tap dances. rktk uses its global setting, so this is not
+/// read back on import.
+const TAPPING_TERM_MS: u64 = 200;
+
+/// `keymap.json` expression and the QMK keycode it corresponds to. When several expressions
+/// share a keycode, the first one is used on import.
+#[rustfmt::skip]
+pub const KEYCODES: &[(&str, &str)] = &[
+    ("A", "KC_A"), ("B", "KC_B"), ("C", "KC_C"), ("D", "KC_D"), ("E", "KC_E"), ("F", "KC_F"),
+    ("G", "KC_G"), ("H", "KC_H"), ("I", "KC_I"), ("J", "KC_J"), ("K", "KC_K"), ("L", "KC_L"),
+    ("M", "KC_M"), ("N", "KC_N"), ("O", "KC_O"), ("P", "KC_P"), ("Q", "KC_Q"), ("R", "KC_R"),
+    ("S", "KC_S"), ("T", "KC_T"), ("U", "KC_U"), ("V", "KC_V"), ("W", "KC_W"), ("X", "KC_X"),
+    ("Y", "KC_Y"), ("Z", "KC_Z"),
+    ("D1", "KC_1"), ("D2", "KC_2"), ("D3", "KC_3"), ("D4", "KC_4"), ("D5", "KC_5"),
+    ("D6", "KC_6"), ("D7", "KC_7"), ("D8", "KC_8"), ("D9", "KC_9"), ("D0", "KC_0"),
+    ("F1", "KC_F1"), ("F2", "KC_F2"), ("F3", "KC_F3"), ("F4", "KC_F4"), ("F5", "KC_F5"),
+    ("F6", "KC_F6"), ("F7", "KC_F7"), ("F8", "KC_F8"), ("F9", "KC_F9"), ("F10", "KC_F10"),
+    ("F11", "KC_F11"), ("F12", "KC_F12"),
+    ("KP0", "KC_KP_0"), ("KP1", "KC_KP_1"), ("KP2", "KC_KP_2"), ("KP3", "KC_KP_3"),
+    ("KP4", "KC_KP_4"), ("KP5", "KC_KP_5"), ("KP6", "KC_KP_6"), ("KP7", "KC_KP_7"),
+    ("KP8", "KC_KP_8"), ("KP9", "KC_KP_9"),
+    ("ESC", "KC_ESCAPE"), ("TAB", "KC_TAB"), ("SPACE", "KC_SPACE"), ("Key(Space)", "KC_SPACE"),
+    ("BS", "KC_BSPACE"),
+    ("Key(Enter)", "KC_ENTER"), ("DELETE", "KC_DELETE"), ("INSERT", "KC_INSERT"),
+    ("Key(Lang1)", "KC_LANG1"), ("Key(Lang2)", "KC_LANG2"),
+    ("HOME", "KC_HOME"), ("PGUP", "KC_PGUP"), ("PRTSC", "KC_PSCREEN"),
+    ("LEFT", "KC_LEFT"), ("DOWN", "KC_DOWN"), ("UP", "KC_UP"), ("RIGHT", "KC_RIGHT"),
+    ("MINUS", "KC_MINUS"), ("EQUAL", "KC_EQUAL"), ("LBRC", "KC_LBRACKET"),
+    ("Key(RightBracket)", "KC_RBRACKET"), ("BSLSH", "KC_BSLASH"), ("SCLN", "KC_SCOLON"),
+    ("QUOTE", "KC_QUOTE"), ("Key(Grave)", "KC_GRAVE"), ("COMM", "KC_COMMA"), ("DOT", "KC_DOT"),
+    ("SLASH", "KC_SLASH"),
+    ("L_CTRL", "KC_LCTRL"), ("L_SHFT", "KC_LSHIFT"), ("L_ALT", "KC_LALT"), ("L_GUI", "KC_LGUI"),
+    ("R_CTRL", "KC_RCTRL"), ("R_SHFT", "KC_RSHIFT"),
+    ("VOLUP", "KC_AUDIO_VOL_UP"), ("VOLDN", "KC_AUDIO_VOL_DOWN"),
+    ("M_LEFT", "KC_MS_BTN1"), ("M_RIGHT", "KC_MS_BTN2"), ("M_MIDDLE", "KC_MS_BTN3"),
+    ("M_BACK", "KC_MS_BTN4"), ("M_FORWARD", "KC_MS_BTN5"),
+    ("__", "KC_NO"), ("CAPS_WORD", "QK_CAPS_WORD_TOGGLE"),
+    ("LEADER", "QK_LEADER"),
+    ("MO_SCRL", "USER00"), ("AML_RESET", "USER01"), ("FLASH_CLEAR", "USER02"),
+    ("BLE_BOND_CLEAR", "USER03"), ("OUTPUT_BLE", "USER04"), ("OUTPUT_USB", "USER05"),
+    ("BASE_LAYOUT", "USER06"), ("OS_PROFILE", "USER07"), ("HOST_LAYOUT", "USER08"),
+    ("DM_REC1", "QK_DYNAMIC_MACRO_RECORD_START_1"),
+    ("DM_REC2", "QK_DYNAMIC_MACRO_RECORD_START_2"),
+    ("DM_RSTP", "QK_DYNAMIC_MACRO_RECORD_STOP"),
+    ("DM_PLY1", "QK_DYNAMIC_MACRO_PLAY_1"), ("DM_PLY2", "QK_DYNAMIC_MACRO_PLAY_2"),
+];
+
+/// Modifier keys usable as the hold action of a mod-tap, and the QMK mod-tap prefix.
+const MOD_TAPS: &[(&str, &str)] = &[
+    ("L_CTRL", "LCTL_T"),
+    ("L_SHFT", "LSFT_T"),
+    ("L_ALT", "LALT_T"),
+    ("L_GUI", "LGUI_T"),
+    ("R_CTRL", "RCTL_T"),
+    ("R_SHFT", "RSFT_T"),
+];
+
+/// Modifier keys usable in a one-shot, and the QMK modifier for `OSM`.
+const ONESHOT_MODS: &[(&str, &str)] = &[
+    ("L_CTRL", "MOD_LCTL"),
+    ("L_SHFT", "MOD_LSFT"),
+    ("L_ALT", "MOD_LALT"),
+    ("L_GUI", "MOD_LGUI"),
+    ("R_CTRL", "MOD_RCTL"),
+    ("R_SHFT", "MOD_RSFT"),
+];
+
+const TRANSPARENT: &str = "_____";
+
+fn to_qmk(file: &KeymapFile, expr: &Expr) -> Result<String, String> {
+    let expr = file.resolve(expr);
+    match expr {
+        Expr::Name(name) if name == TRANSPARENT => return Ok("KC_TRNS".to_string()),
+        Expr::Call(func, args) => match (func.as_str(), args.as_slice()) {
+            ("SF", [key]) => return Ok(format!("LSFT({})", to_qmk(file, key)?)),
+            ("MO" | "TG" | "TD" | "OSL", [Expr::Number(n)]) => return Ok(format!("{func}({n})")),
+            ("MACRO", [Expr::Number(n)]) => return Ok(format!("M{n}")),
+            ("OS", [Expr::Name(m)]) => {
+                return ONESHOT_MODS
+                    .iter()
+                    .find(|(name, _)| name == m)
+                    .map(|(_, qmk)| format!("OSM({qmk})"))
+                    .ok_or(format!("`{expr}` has no QMK equivalent"));
+            }
+            ("TH", [tap, hold]) => {
+                let tap = to_qmk(file, tap)?;
+                return match file.resolve(hold) {
+                    Expr::Call(f, a) if f == "MO" => match a.as_slice() {
+                        [Expr::Number(n)] => Ok(format!("LT({n}, {tap})")),
+                        _ => Err(format!("`{expr}` has no QMK equivalent")),
+                    },
+                    Expr::Name(m) => MOD_TAPS
+                        .iter()
+                        .find(|(name, _)| name == m)
+                        .map(|(_, prefix)| format!("{prefix}({tap})"))
+                        .ok_or(format!("`{expr}` has no QMK equivalent")),
+                    _ => Err(format!("`{expr}` has no QMK equivalent")),
+                };
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+
+    let source = expr.to_string();
+    KEYCODES
+        .iter()
+        .find(|(name, _)| *name == source)
+        .map(|(_, qmk)| qmk.to_string())
+        .ok_or(format!("`{source}` has no QMK equivalent"))
+}
+
+/// Parses a QMK keycode. Returns `None` for `KC_TRNS`.
+fn from_qmk(qmk: &str) -> Result<Option<Expr>, String> {
+    let expr = Expr::parse(qmk)?;
+    from_qmk_expr(&expr).map_err(|e| format!("`{qmk}`: {e}"))
+}
+
+fn from_qmk_expr(expr: &Expr) -> Result<Option<Expr>, String> {
+    let required = |e: &Expr| from_qmk_expr(e)?.ok_or("transparent key in a function".to_string());
+    match expr {
+        Expr::Name(name) if name == "KC_TRNS" || name == "KC_TRANSPARENT" => Ok(None),
+        Expr::Name(name)
+            if name
+                .strip_prefix('M')
+                .is_some_and(|n| n.parse::<u8>().is_ok()) =>
+        {
+            Ok(Some(Expr::Call(
+                "MACRO".into(),
+                vec![Expr::Number(name[1..].parse().unwrap())],
+            )))
+        }
+        Expr::Name(name) => KEYCODES
+            .iter()
+            .find(|(_, qmk)| qmk == name)
+            .map(|(source, _)| Expr::parse(source).map(Some))
+            .unwrap_or_else(|| Err(format!("unsupported keycode `{name}`"))),
+        Expr::Number(_) => Err("unexpected number".to_string()),
+        Expr::Call(func, args) => Ok(Some(match (func.as_str(), args.as_slice()) {
+            ("LSFT", [key]) => Expr::Call("SF".into(), vec![required(key)?]),
+            ("MO" | "TG" | "TD" | "OSL", [n @ Expr::Number(_)]) => {
+                Expr::Call(func.clone(), vec![n.clone()])
+            }
+            ("OSM", [Expr::Name(qmk)]) => {
+                let (modifier, _) = ONESHOT_MODS
+                    .iter()
+                    .find(|(_, m)| m == qmk)
+                    .ok_or(format!("unsupported one-shot modifier `{qmk}`"))?;
+                Expr::Call("OS".into(), vec![Expr::Name(modifier.to_string())])
+            }
+            ("LT", [n @ Expr::Number(_), key]) => Expr::Call(
+                "TH".into(),
+                vec![required(key)?, Expr::Call("MO".into(), vec![n.clone()])],
+            ),
+            (func, [key]) => {
+                let (modifier, _) = MOD_TAPS
+                    .iter()
+                    .find(|(_, prefix)| *prefix == func)
+                    .ok_or(format!("unsupported function `{func}`"))?;
+                Expr::Call(
+                    "TH".into(),
+                    vec![required(key)?, Expr::Name(modifier.to_string())],
+                )
+            }
+            (func, _) => return Err(format!("unsupported function `{func}`")),
+        })),
+    }
+}
+
+fn opt_to_qmk(file: &KeymapFile, expr: &Option<Expr>) -> Result<String, String> {
+    match expr {
+        Some(expr) => to_qmk(file, expr),
+        None => Ok("KC_NO".to_string()),
+    }
+}
+
+fn opt_from_qmk(value: &Value) -> Result<Option<Expr>, String> {
+    let qmk = value
+        .as_str()
+        .ok_or(format!("expected a keycode, got `{value}`"))?;
+    if qmk == "KC_NO" {
+        return Ok(None);
+    }
+    from_qmk(qmk)
+}
+
+/// Converts `file` to a `.vil` document. Warnings about what Vial cannot represent are
+/// returned along with it.
+pub fn export(
+    file: &KeymapFile,
+    layout: &[(usize, usize)],
+    rows: usize,
+    cols: usize,
+) -> Result<(Value, Vec<String>), String> {
+    let mut warnings = Vec::new();
+
+    let mut layers = Vec::new();
+    for layer in &file.layers {
+        let mut matrix = Vec::new();
+        for row in 0..rows {
+            let mut keys = Vec::new();
+            for col in 0..cols {
+                if !layout.contains(&(row, col)) {
+                    keys.push(json!(-1));
+                    continue;
+                }
+                let qmk = match layer.keys.get(&(row, col)) {
+                    Some(expr) => to_qmk(file, expr)
+                        .map_err(|e| format!("layer `{}`, key {row},{col}: {e}", layer.name))?,
+                    None => "KC_TRNS".to_string(),
+                };
+                keys.push(json!(qmk));
+            }
+            matrix.push(keys);
+        }
+        layers.push(matrix);
+    }
+
+    let encoder_count = file
+        .layers
+        .iter()
+        .map(|l| l.encoder.len())
+        .max()
+        .unwrap_or(0);
+    let mut encoders = Vec::new();
+    for layer in &file.layers {
+        let mut list = Vec::new();
+        for i in 0..encoder_count {
+            let (cw, ccw) = layer.encoder.get(i).cloned().unwrap_or((None, None));
+            list.push(json!([opt_to_qmk(file, &cw)?, opt_to_qmk(file, &ccw)?]));
+        }
+        encoders.push(list);
+    }
+
+    let mut tap_dance = Vec::new();
+    for (i, def) in file.tap_dance.iter().enumerate() {
+        let slot =
+            |list: &[Option<Expr>], n: usize| opt_to_qmk(file, &list.get(n).cloned().flatten());
+        if def
+            .tap
+            .iter()
+            .skip(2)
+            .chain(def.hold.iter().skip(2))
+            .any(Option::is_some)
+        {
+            warnings.push(format!(
+                "tap_dance[{i}] has more than two taps; Vial only shows the first two"
+            ));
+        }
+        tap_dance.push(json!([
+            slot(&def.tap, 0)?,
+            slot(&def.hold, 0)?,
+            slot(&def.tap, 1)?,
+            slot(&def.hold, 1)?,
+            TAPPING_TERM_MS,
+        ]));
+    }
+
+    // Vial combos have four sources followed by the result.
+    let mut combo = Vec::new();
+    for (i, def) in file.combo.iter().enumerate() {
+        if def.src.len() > 4 {
+            return Err(format!(
+                "combo[{i}] has more than the four sources Vial supports"
+            ));
+        }
+        let mut entry = def
+            .src
+            .iter()
+            .map(|e| to_qmk(file, e))
+            .collect::<Result<Vec<_>, _>>()?;
+        entry.resize(4, "KC_NO".to_string());
+        entry.push(to_qmk(file, &def.dst)?);
+        combo.push(entry);
+    }
+
+    let full_tap_dance = file
+        .tap_dance
+        .iter()
+        .map(|def| {
+            let list = |l: &[Option<Expr>]| {
+                l.iter()
+                    .map(|e| opt_to_qmk(file, e))
+                    .collect::<Result<Vec<_>, _>>()
+            };
+            Ok(json!({ "tap": list(&def.tap)?, "hold": list(&def.hold)? }))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let vil = json!({
+        "version": 1,
+        "uid": 0,
+        "layout": layers,
+        "encoder_layout": encoders,
+        "layout_options": -1,
+        "macro": [],
+        "vial_protocol": 6,
+        "via_protocol": 9,
+        "tap_dance": tap_dance,
+        "combo": combo,
+        "key_override": [],
+        "settings": {},
+        "negl_layer_names": file.layers.iter().map(|l| l.name.clone()).collect::<Vec<_>>(),
+        "negl_tap_dance": full_tap_dance,
+        "negl_home_row_mods": file.home_row_mods.as_ref().map(|h| h.to_json()),
+        "negl_oneshot": file.oneshot.as_ref().map(|o| o.to_json()),
+        "negl_caps_word": file.caps_word.as_ref().map(|c| c.to_json()),
+        "negl_leader": file.leader.as_ref().map(|l| l.to_json()),
+        "negl_key_overrides": file.key_overrides.iter().map(|o| o.to_json()).collect::<Vec<_>>(),
+        "negl_base_layouts": file.base_layouts.as_ref().map(|b| b.to_json()),
+        "negl_os_profile": file.os_profile.as_ref().map(|p| p.to_json()),
+        "negl_dynamic_macros": file.dynamic_macros.as_ref().map(|d| d.to_json()),
+        "negl_text_macros": file.text_macros.iter().map(|m| m.to_json()).collect::<Vec<_>>(),
+    });
+    Ok((vil, warnings))
+}
+
+/// Converts a `.vil` document back into a keymap. Aliases are not restored.
+pub fn import(vil: &Value) -> Result<KeymapFile, String> {
+    let layout = vil
+        .get("layout")
+        .and_then(|l| l.as_array())
+        .ok_or("`layout` is missing")?;
+    let names: Vec<&str> = vil
+        .get("negl_layer_names")
+        .and_then(|n| n.as_array())
+        .map(|n| n.iter().filter_map(|n| n.as_str()).collect())
+        .unwrap_or_default();
+    let encoders = vil.get("encoder_layout").and_then(|e| e.as_array());
+
+    let mut layers = Vec::new();
+    for (i, matrix) in layout.iter().enumerate() {
+        let name = names
+            .get(i)
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| format!("Layer {i}"));
+        let mut keys = BTreeMap::new();
+        for (row, list) in matrix.as_array().into_iter().flatten().enumerate() {
+            for (col, key) in list.as_array().into_iter().flatten().enumerate() {
+                if key.as_i64() == Some(-1) {
+                    continue;
+                }
+                let qmk = key
+                    .as_str()
+                    .ok_or(format!("layer {i}, key {row},{col}: expected a keycode"))?;
+                if let Some(expr) =
+                    from_qmk(qmk).map_err(|e| format!("layer {i}, key {row},{col}: {e}"))?
+                {
+                    keys.insert((row, col), expr);
+                }
+            }
+        }
+
+        let mut encoder = Vec::new();
+        if let Some(list) = encoders.and_then(|e| e.get(i)).and_then(|l| l.as_array()) {
+            for (j, pair) in list.iter().enumerate() {
+                let context = format!("layer {i}, encoder {j}");
+                let [cw, ccw] = pair
+                    .as_array()
+                    .and_then(|p| <&[Value; 2]>::try_from(p.as_slice()).ok())
+                    .ok_or(format!("{context}: expected [clockwise, counterclockwise]"))?;
+                encoder.push((
+                    opt_from_qmk(cw).map_err(|e| format!("{context}: {e}"))?,
+                    opt_from_qmk(ccw).map_err(|e| format!("{context}: {e}"))?,
+                ));
+            }
+            if encoder
+                .iter()
+                .all(|(cw, ccw)| cw.is_none() && ccw.is_none())
+            {
+                encoder.clear();
+            }
+        }
+
+        layers.push(LayerDef {
+            name,
+            keys,
+            encoder,
+        });
+    }
+
+    let mut tap_dance = Vec::new();
+    if let Some(list) = vil.get("negl_tap_dance").and_then(|t| t.as_array()) {
+        for (i, def) in list.iter().enumerate() {
+            let slots = |name: &str| -> Result<Vec<Option<Expr>>, String> {
+                def.get(name)
+                    .and_then(|l| l.as_array())
+                    .into_iter()
+                    .flatten()
+                    .map(|v| opt_from_qmk(v).map_err(|e| format!("tap dance {i}: {e}")))
+                    .collect()
+            };
+            tap_dance.push(TapDanceDef {
+                tap: slots("tap")?,
+                hold: slots("hold")?,
+            });
+        }
+    } else if let Some(list) = vil.get("tap_dance").and_then(|t| t.as_array()) {
+        for (i, entry) in list.iter().enumerate() {
+            let entry = entry
+                .as_array()
+                .ok_or(format!("tap dance {i}: expected an array"))?;
+            let slot = |n: usize| match entry.get(n) {
+                Some(v) => opt_from_qmk(v).map_err(|e| format!("tap dance {i}: {e}")),
+                None => Ok(None),
+            };
+            tap_dance.push(TapDanceDef {
+                tap: vec![slot(0)?, slot(2)?],
+                hold: vec![slot(1)?, slot(3)?],
+            });
+        }
+        // Vial always lists every tap dance slot of the firmware. Unused ones at the end are
+        // dropped so that `tap_dance_max_definitions` is not exceeded.
+        while tap_dance
+            .last()
+            .is_some_and(|d| d.tap.iter().chain(&d.hold).all(Option::is_none))
+        {
+            tap_dance.pop();
+        }
+    }
+
+    let mut combo = Vec::new();
+    for (i, entry) in vil
+        .get("combo")
+        .and_then(|c| c.as_array())
+        .into_iter()
+        .flatten()
+        .enumerate()
+    {
+        let entry = entry
+            .as_array()
+            .filter(|e| !e.is_empty())
+            .ok_or(format!("combo {i}: expected an array"))?;
+        let (dst, src) = entry.split_last().unwrap();
+        let src = src
+            .iter()
+            .map(opt_from_qmk)
+            .filter_map(Result::transpose)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("combo {i}: {e}"))?;
+        let dst = opt_from_qmk(dst).map_err(|e| format!("combo {i}: {e}"))?;
+        // Unused Vial combo slots are all `KC_NO`.
+        if let Some(dst) = dst.filter(|_| !src.is_empty()) {
+            combo.push(ComboDef { src, dst });
+        }
+    }
+
+    let home_row_mods = vil
+        .get("negl_home_row_mods")
+        .filter(|h| !h.is_null())
+        .map(parse_home_row_mods)
+        .transpose()?;
+    let oneshot = vil
+        .get("negl_oneshot")
+        .filter(|o| !o.is_null())
+        .map(parse_oneshot)
+        .transpose()?;
+    let caps_word = vil
+        .get("negl_caps_word")
+        .filter(|c| !c.is_null())
+        .map(parse_caps_word)
+        .transpose()?;
+    let leader = vil
+        .get("negl_leader")
+        .filter(|l| !l.is_null())
+        .map(parse_leader)
+        .transpose()?;
+    let key_overrides = vil
+        .get("negl_key_overrides")
+        .filter(|o| !o.is_null())
+        .map(parse_key_overrides)
+        .transpose()?
+        .unwrap_or_default();
+    let text_macros = vil
+        .get("negl_text_macros")
+        .filter(|m| !m.is_null())
+        .map(parse_text_macros)
+        .transpose()?
+        .unwrap_or_default();
+    let base_layouts = vil
+        .get("negl_base_layouts")
+        .filter(|b| !b.is_null())
+        .map(parse_base_layouts)
+        .transpose()?;
+    let os_profile = vil
+        .get("negl_os_profile")
+        .filter(|p| !p.is_null())
+        .map(parse_os_profile)
+        .transpose()?;
+    let dynamic_macros = vil
+        .get("negl_dynamic_macros")
+        .filter(|d| !d.is_null())
+        .map(parse_dynamic_macros)
+        .transpose()?;
+
+    Ok(KeymapFile {
+        aliases: Vec::new(),
+        layers,
+        tap_dance,
+        combo,
+        home_row_mods,
+        oneshot,
+        caps_word,
+        leader,
+        key_overrides,
+        base_layouts,
+        os_profile,
+        dynamic_macros,
+        text_macros,
+    })
+}
@@ -0,0 +1,47 @@
+//! The runner of the test targets with `harness = false`: each has a list of named checks,
+//! and `main` runs them with [`run`].
+
+// Not every target uses every helper.
+#![allow(dead_code)]
+
+use std::process::ExitCode;
+
+pub type Check = fn() -> Result<(), String>;
+
+/// Runs `checks`, printing the result of each under the name of the `target`, and returns
+/// how many failed.
+pub fn run_checks(target: &str, checks: &[(&str, Check)]) -> usize {
+    let mut failed = 0;
+    for (name, check) in checks {
+        match check() {
+            Ok(()) => println!("ok      {target}: {name}"),
+            Err(e) => {
+                println!("FAILED  {target}: {name}: {e}");
+                failed += 1;
+            }
+        }
+    }
+    println!("\n{} checks, {failed} failed", checks.len());
+    failed
+}
+
+/// Runs `checks` like [`run_checks`], for a `main` which has nothing else to do.
+pub fn run(target: &str, checks: &[(&str, Check)]) -> ExitCode {
+    exit_code(run_checks(target, checks))
+}
+
+pub fn exit_code(failed: usize) -> ExitCode {
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+pub fn expect_eq<T: PartialEq + std::fmt::Debug>(actual: T, expected: T) -> Result<(), String> {
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(format!("expected {expected:?}, got {actual:?}"))
+    }
+}
@@ -0,0 +1,98 @@
+//! Replays every `golden/*.script` and compares the produced reports with `golden/*.golden`.
+//!
+//! Set `NEGL_UPDATE_GOLDEN=1` to (re)write the golden files instead, then review the diff
+//! before committing it.
+
+use std::{fs, path::Path, process::ExitCode};
+
+use negl_tools::{script, sim};
+
+const UPDATE_ENV: &str = "NEGL_UPDATE_GOLDEN";
+
+fn render(outputs: &[sim::Output]) -> String {
+    outputs.iter().map(|o| format!("{o}\n")).collect()
+}
+
+/// Lines of `expected` and `actual` which differ, marked like a unified diff.
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<_> = expected.lines().collect();
+    let actual: Vec<_> = actual.lines().collect();
+    let mut out = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        let (e, a) = (expected.get(i), actual.get(i));
+        if e == a {
+            continue;
+        }
+        if let Some(e) = e {
+            out += &format!("    - {e}\n");
+        }
+        if let Some(a) = a {
+            out += &format!("    + {a}\n");
+        }
+    }
+    out
+}
+
+fn check(script_path: &Path, update: bool) -> Result<(), String> {
+    let src = fs::read_to_string(script_path).map_err(|e| e.to_string())?;
+    let steps = script::parse(&src)?;
+    let actual = render(&sim::run(&steps));
+
+    let golden_path = script_path.with_extension("golden");
+    if update {
+        return fs::write(&golden_path, actual).map_err(|e| e.to_string());
+    }
+
+    let expected = fs::read_to_string(&golden_path).map_err(|e| {
+        format!(
+            "{}: {e}; write it with `{UPDATE_ENV}=1 cargo test --test golden` and review it",
+            golden_path.display()
+        )
+    })?;
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(format!(
+            "reports differ from {}:\n{}",
+            golden_path.display(),
+            diff(&expected, &actual)
+        ))
+    }
+}
+
+fn main() -> ExitCode {
+    let update = std::env::var_os(UPDATE_ENV).is_some_and(|v| !v.is_empty() && v != "0");
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("golden");
+
+    let mut scripts: Vec<_> = match fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|e| e == "script"))
+            .collect(),
+        Err(e) => {
+            println!("{}: {e}", dir.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    scripts.sort();
+
+    let mut failed = 0;
+    for path in &scripts {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        match check(path, update) {
+            Ok(()) if update => println!("updated {name}"),
+            Ok(()) => println!("ok      {name}"),
+            Err(e) => {
+                println!("FAILED  {name}: {e}");
+                failed += 1;
+            }
+        }
+    }
+
+    println!("\n{} scripts, {failed} failed", scripts.len());
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
@@ -0,0 +1,1163 @@
+//! Checks tap/hold behaviour of the keys in `keymap.json` by replaying input scripts through
+//! the key manager and comparing the reports which reach the host.
+
+mod common;
+
+use std::process::ExitCode;
+
+use common::{expect_eq, Check};
+use negl_tools::send_string::ascii_key;
+use negl_tools::sim::{run, Output, Report, Step, Step::*};
+
+const L4GRV: (u8, u8) = (0, 0);
+const L3SPC: (u8, u8) = (4, 4);
+const L2LANG2: (u8, u8) = (4, 5);
+const L3LANG1: (u8, u8) = (4, 9);
+const L2ENTER: (u8, u8) = (4, 10);
+const TD0: (u8, u8) = (3, 9);
+const KEY_D: (u8, u8) = (2, 3);
+const KEY_F: (u8, u8) = (2, 4);
+const KEY_J: (u8, u8) = (2, 11);
+const KEY_K: (u8, u8) = (2, 12);
+const KEY_A: (u8, u8) = (2, 1);
+const KEY_G: (u8, u8) = (2, 5);
+const KEY_H: (u8, u8) = (2, 10);
+const KEY_Q: (u8, u8) = (1, 1);
+const KEY_W: (u8, u8) = (1, 2);
+const KEY_S: (u8, u8) = (2, 2);
+const KEY_ESC: (u8, u8) = (2, 0);
+const OSL3: (u8, u8) = (4, 7);
+/// `OS(L_SHFT)` on layer 3.
+const OS_SHIFT_L3: (u8, u8) = (4, 6);
+/// `OS(L_CTRL)` on layer 3.
+const OS_CTRL_L3: (u8, u8) = (4, 8);
+/// `CAPS_WORD` on layer 3.
+const CAPS_WORD_L3: (u8, u8) = (3, 14);
+const KEY_1: (u8, u8) = (0, 1);
+const KEY_MINUS: (u8, u8) = (1, 15);
+const KEY_DOT: (u8, u8) = (3, 13);
+const KEY_L_CTRL: (u8, u8) = (4, 0);
+const KEY_L_GUI: (u8, u8) = (4, 1);
+const KEY_L_ALT: (u8, u8) = (4, 3);
+const KEY_L_SHIFT: (u8, u8) = (3, 0);
+/// `LEADER` on layer 3.
+const LEADER_L3: (u8, u8) = (3, 15);
+const KEY_BS: (u8, u8) = (4, 8);
+const KEY_SPACE: (u8, u8) = (4, 6);
+const KEY_E: (u8, u8) = (1, 3);
+const KEY_N: (u8, u8) = (3, 10);
+const KEY_QUOTE: (u8, u8) = (2, 15);
+const BASE_LAYOUT_L3: (u8, u8) = (0, 13);
+const QUOTE_L3: (u8, u8) = (3, 10);
+const KEY_C: (u8, u8) = (3, 3);
+/// `OS_PROFILE` on layer 3.
+const OS_PROFILE_L3: (u8, u8) = (0, 12);
+const KEY_2: (u8, u8) = (0, 2);
+/// `HOST_LAYOUT` on layer 3.
+const HOST_LAYOUT_L3: (u8, u8) = (0, 14);
+/// `DM_REC1`, `DM_REC2`, `DM_PLY1`, `DM_PLY2` and `DM_RSTP` on layer 3.
+const DM_REC1_L3: (u8, u8) = (0, 2);
+const DM_REC2_L3: (u8, u8) = (0, 3);
+const DM_PLY1_L3: (u8, u8) = (0, 4);
+const DM_PLY2_L3: (u8, u8) = (0, 5);
+const DM_RSTP_L3: (u8, u8) = (0, 15);
+/// `MACRO(0)` and `MACRO(1)` on layer 3.
+const MACRO0_L3: (u8, u8) = (1, 0);
+const MACRO1_L3: (u8, u8) = (1, 1);
+
+const CTRL: u8 = 0x01;
+const SHIFT: u8 = 0x02;
+const ALT: u8 = 0x04;
+const GUI: u8 = 0x08;
+const A: u8 = 0x04;
+const B: u8 = 0x05;
+const C: u8 = 0x06;
+const D: u8 = 0x07;
+const E: u8 = 0x08;
+const F: u8 = 0x09;
+const G: u8 = 0x0a;
+const H: u8 = 0x0b;
+const J: u8 = 0x0d;
+const Q: u8 = 0x14;
+const S: u8 = 0x16;
+const W: u8 = 0x1a;
+const K: u8 = 0x0e;
+const N: u8 = 0x11;
+const L: u8 = 0x0f;
+const T: u8 = 0x17;
+const D1: u8 = 0x1e;
+const D2: u8 = 0x1f;
+const D7: u8 = 0x24;
+const ENTER: u8 = 0x28;
+const ESC: u8 = 0x29;
+const TAB: u8 = 0x2b;
+const BACKSPACE: u8 = 0x2a;
+const DELETE: u8 = 0x4c;
+const SPACE: u8 = 0x2c;
+const MINUS: u8 = 0x2d;
+const RIGHT_BRACKET: u8 = 0x30;
+const GRAVE: u8 = 0x35;
+const DOT: u8 = 0x37;
+const QUOTE: u8 = 0x34;
+const LEFT: u8 = 0x50;
+const KP4: u8 = 0x5c;
+const JIS_AT: u8 = 0x2f;
+const JIS_UNDERSCORE: u8 = 0x87;
+const HENKAN: u8 = 0x8a;
+const LANG1: u8 = 0x90;
+const LANG2: u8 = 0x91;
+const F1: u8 = 0x3a;
+const MOUSE_LEFT: u8 = 0x01;
+
+/// Long enough for any tap-hold or tap-dance decision to be made.
+const SETTLE: Step = Wait(500);
+
+fn tap((row, col): (u8, u8)) -> [Step; 3] {
+    [Press(row, col), Wait(20), Release(row, col)]
+}
+
+/// Holds `hold` past the tapping term, taps `key` and releases `hold`.
+fn hold_and_tap((hr, hc): (u8, u8), key: (u8, u8)) -> Vec<Step> {
+    let mut steps = vec![Press(hr, hc), Wait(300)];
+    steps.extend(tap(key));
+    steps.extend([Wait(20), Release(hr, hc), SETTLE]);
+    steps
+}
+
+fn keyboard(modifier: u8, keys: &[u8]) -> Report {
+    Report::Keyboard {
+        modifier,
+        keys: keys.to_vec(),
+    }
+}
+
+/// The keyboard reports, ignoring every other report and the timing.
+fn keyboard_reports(outputs: &[Output]) -> Vec<Report> {
+    outputs
+        .iter()
+        .filter(|o| matches!(o.report, Report::Keyboard { .. }))
+        .map(|o| o.report.clone())
+        .collect()
+}
+
+fn expect_keyboard(outputs: &[Output], expected: &[Report]) -> Result<(), String> {
+    let actual = keyboard_reports(outputs);
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(format!(
+            "expected keyboard reports {expected:?}, got {actual:?}"
+        ))
+    }
+}
+
+fn expect_report(outputs: &[Output], expected: Report) -> Result<(), String> {
+    if outputs.iter().any(|o| o.report == expected) {
+        Ok(())
+    } else {
+        Err(format!("expected {expected:?} to be reported"))
+    }
+}
+
+fn expect_no_report(outputs: &[Output], unexpected: Report) -> Result<(), String> {
+    match outputs.iter().find(|o| o.report == unexpected) {
+        Some(o) => Err(format!("unexpected {unexpected:?} at {}ms", o.time_ms)),
+        None => Ok(()),
+    }
+}
+
+fn l2lang2_tap_sends_lang2() -> Result<(), String> {
+    let outputs = run(&[&tap(L2LANG2)[..], &[SETTLE]].concat());
+    expect_keyboard(&outputs, &[keyboard(0, &[LANG2]), keyboard(0, &[])])?;
+    expect_no_report(&outputs, Report::Layer(2))
+}
+
+fn space_sends_space() -> Result<(), String> {
+    let outputs = run(&[&tap(KEY_SPACE)[..], &[SETTLE]].concat());
+    expect_keyboard(&outputs, &[keyboard(0, &[SPACE]), keyboard(0, &[])])
+}
+
+fn l2lang2_hold_activates_layer_2() -> Result<(), String> {
+    let outputs = run(&hold_and_tap(L2LANG2, (1, 10)));
+    expect_keyboard(&outputs, &[keyboard(0, &[LEFT]), keyboard(0, &[])])?;
+    expect_report(&outputs, Report::Layer(2))
+}
+
+fn l2lang2_hold_clicks_mouse() -> Result<(), String> {
+    let outputs = run(&hold_and_tap(L2LANG2, (2, 11)));
+    expect_report(&outputs, Report::MouseButtons(MOUSE_LEFT))?;
+    expect_keyboard(&outputs, &[])
+}
+
+fn l2enter_tap_sends_enter() -> Result<(), String> {
+    let outputs = run(&[&tap(L2ENTER)[..], &[SETTLE]].concat());
+    expect_keyboard(&outputs, &[keyboard(0, &[ENTER]), keyboard(0, &[])])
+}
+
+fn l3spc_hold_sends_shifted_digit() -> Result<(), String> {
+    let outputs = run(&hold_and_tap(L3SPC, (1, 11)));
+    expect_report(&outputs, Report::Layer(3))?;
+    expect_report(&outputs, keyboard(SHIFT, &[D2]))?;
+    expect_no_report(&outputs, keyboard(0, &[SPACE]))
+}
+
+fn l4grv_tap_sends_grave() -> Result<(), String> {
+    let outputs = run(&[&tap(L4GRV)[..], &[SETTLE]].concat());
+    expect_keyboard(&outputs, &[keyboard(0, &[GRAVE]), keyboard(0, &[])])
+}
+
+fn l4grv_hold_activates_layer_4() -> Result<(), String> {
+    let outputs = run(&hold_and_tap(L4GRV, (0, 1)));
+    expect_report(&outputs, Report::Layer(4))?;
+    expect_no_report(&outputs, keyboard(0, &[GRAVE]))?;
+    // Layer 4 is empty, so the key falls through to the base layer.
+    expect_report(&outputs, keyboard(0, &[0x1e]))
+}
+
+fn td0_single_tap_sends_right_bracket() -> Result<(), String> {
+    let outputs = run(&[&tap(TD0)[..], &[SETTLE]].concat());
+    expect_keyboard(&outputs, &[keyboard(0, &[RIGHT_BRACKET]), keyboard(0, &[])])?;
+    expect_no_report(&outputs, Report::Layer(2))
+}
+
+fn td0_double_tap_toggles_layer_2() -> Result<(), String> {
+    let script = [
+        &tap(TD0)[..],
+        &[Wait(30)],
+        &tap(TD0),
+        &[SETTLE],
+        &tap((0, 1)),
+        &[SETTLE],
+    ]
+    .concat();
+    let outputs = run(&script);
+    expect_report(&outputs, Report::Layer(2))?;
+    expect_no_report(&outputs, keyboard(0, &[RIGHT_BRACKET]))?;
+    expect_keyboard(&outputs, &[keyboard(0, &[F1]), keyboard(0, &[])])
+}
+
+fn td0_triple_tap_toggles_layer_3() -> Result<(), String> {
+    let script = [
+        &tap(TD0)[..],
+        &[Wait(30)],
+        &tap(TD0),
+        &[Wait(30)],
+        &tap(TD0),
+        &[SETTLE],
+    ]
+    .concat();
+    let outputs = run(&script);
+    expect_report(&outputs, Report::Layer(3))?;
+    expect_no_report(&outputs, Report::Layer(2))
+}
+
+/// Presses `first`, then `second` after `gap` ms, and releases both.
+fn chord(first: (u8, u8), second: (u8, u8), gap: u32) -> Vec<Step> {
+    vec![
+        Press(first.0, first.1),
+        Wait(gap),
+        Press(second.0, second.1),
+        Wait(30),
+        Release(first.0, first.1),
+        Wait(30),
+        Release(second.0, second.1),
+        SETTLE,
+    ]
+}
+
+fn combo_jk_sends_esc() -> Result<(), String> {
+    let outputs = run(&chord(KEY_J, KEY_K, 5));
+    expect_keyboard(&outputs, &[keyboard(0, &[ESC]), keyboard(0, &[])])
+}
+
+fn combo_df_sends_tab() -> Result<(), String> {
+    let outputs = run(&chord(KEY_F, KEY_D, 10));
+    expect_keyboard(&outputs, &[keyboard(0, &[TAB]), keyboard(0, &[])])
+}
+
+/// Rolling from J to K faster than a typist lifts the first finger, but outside the combo
+/// window, types both letters. K is a home-row mod held back until its release, which comes
+/// after the release of J.
+fn rolling_jk_does_not_fire() -> Result<(), String> {
+    let outputs = run(&chord(KEY_J, KEY_K, 60));
+    expect_no_report(&outputs, keyboard(0, &[ESC]))?;
+    expect_keyboard(
+        &outputs,
+        &[
+            keyboard(0, &[J]),
+            keyboard(0, &[]),
+            keyboard(0, &[K]),
+            keyboard(0, &[]),
+        ],
+    )
+}
+
+fn rolling_df_does_not_fire() -> Result<(), String> {
+    let script = [
+        Press(KEY_D.0, KEY_D.1),
+        Wait(45),
+        Press(KEY_F.0, KEY_F.1),
+        Wait(10),
+        Release(KEY_D.0, KEY_D.1),
+        Wait(40),
+        Release(KEY_F.0, KEY_F.1),
+        SETTLE,
+    ];
+    let outputs = run(&script);
+    expect_no_report(&outputs, keyboard(0, &[TAB]))?;
+    expect_report(&outputs, keyboard(0, &[D]))?;
+    expect_report(&outputs, keyboard(0, &[F]))
+}
+
+/// Presses `first`, then `second`, and releases both, with `gap` ms between the steps.
+/// `first_released_first` selects whether the keys are rolled or `second` is tapped inside.
+fn overlap(first: (u8, u8), second: (u8, u8), gap: u32, first_released_first: bool) -> Vec<Step> {
+    let (a, b) = if first_released_first {
+        (first, second)
+    } else {
+        (second, first)
+    };
+    vec![
+        Press(first.0, first.1),
+        Wait(gap),
+        Press(second.0, second.1),
+        Wait(gap),
+        Release(a.0, a.1),
+        Wait(gap),
+        Release(b.0, b.1),
+        SETTLE,
+    ]
+}
+
+fn home_row_tap_sends_letter() -> Result<(), String> {
+    let outputs = run(&[&tap(KEY_A)[..], &[SETTLE]].concat());
+    expect_keyboard(&outputs, &[keyboard(0, &[A]), keyboard(0, &[])])
+}
+
+fn home_row_hold_sends_modifier() -> Result<(), String> {
+    let outputs = run(&hold_and_tap(KEY_F, KEY_H));
+    expect_keyboard(
+        &outputs,
+        &[
+            keyboard(SHIFT, &[]),
+            keyboard(SHIFT, &[H]),
+            keyboard(SHIFT, &[]),
+            keyboard(0, &[]),
+        ],
+    )
+}
+
+/// A is a pinky key with a longer tapping term than F, so the same 220ms press is a tap on
+/// A and a hold on F.
+fn home_row_tapping_term_is_per_key() -> Result<(), String> {
+    let press = |key: (u8, u8)| {
+        [
+            Press(key.0, key.1),
+            Wait(220),
+            Release(key.0, key.1),
+            SETTLE,
+        ]
+    };
+    expect_keyboard(&run(&press(KEY_A)), &[keyboard(0, &[A]), keyboard(0, &[])])?;
+    expect_keyboard(
+        &run(&press(KEY_F)),
+        &[keyboard(SHIFT, &[]), keyboard(0, &[])],
+    )
+}
+
+/// Tapping a key of the other hand while F is held is Shift+key, even before the term.
+fn home_row_permissive_hold() -> Result<(), String> {
+    let outputs = run(&overlap(KEY_F, KEY_H, 30, false));
+    expect_report(&outputs, keyboard(SHIFT, &[H]))?;
+    expect_no_report(&outputs, keyboard(0, &[F]))
+}
+
+/// Rolling F into H, releasing F first, is typing. F is a combo source, so whether it reaches
+/// the host in a report of its own or together with H is up to the key manager.
+fn home_row_roll_to_other_hand_types() -> Result<(), String> {
+    let outputs = run(&overlap(KEY_F, KEY_H, 30, true));
+    if let Some(o) = outputs
+        .iter()
+        .find(|o| matches!(o.report, Report::Keyboard { modifier, .. } if modifier != 0))
+    {
+        return Err(format!("unexpected {:?} at {}ms", o.report, o.time_ms));
+    }
+    expect_eq(pressed_keys(&outputs), vec![F, H])?;
+    expect_eq(keyboard_reports(&outputs).last(), Some(&keyboard(0, &[])))
+}
+
+/// The keys whose press reached the host, in order.
+fn pressed_keys(outputs: &[Output]) -> Vec<u8> {
+    let mut pressed = Vec::new();
+    let mut last: &[u8] = &[];
+    for output in outputs {
+        if let Report::Keyboard { keys, .. } = &output.report {
+            pressed.extend(keys.iter().filter(|k| !last.contains(k)));
+            last = keys;
+        }
+    }
+    pressed
+}
+
+/// Rolling F into more keys than the keyboard event channel holds releases them all at once
+/// when F is released first. Each still reaches the host, in order.
+fn home_row_long_roll_types_in_order() -> Result<(), String> {
+    let rolled = [KEY_H, KEY_N, KEY_DOT, KEY_MINUS];
+    let mut script = vec![Press(KEY_F.0, KEY_F.1)];
+    for (row, col) in rolled {
+        script.extend([Wait(15), Press(row, col)]);
+    }
+    script.extend([Wait(15), Release(KEY_F.0, KEY_F.1)]);
+    for (row, col) in rolled {
+        script.extend([Wait(15), Release(row, col)]);
+    }
+    script.push(SETTLE);
+
+    let outputs = run(&script);
+    expect_eq(pressed_keys(&outputs), vec![F, H, N, DOT, MINUS])?;
+    expect_eq(keyboard_reports(&outputs).last(), Some(&keyboard(0, &[])))
+}
+
+/// A key of the same hand pressed while A is pending makes A a tap, even when A is released
+/// last.
+fn home_row_same_hand_roll_types() -> Result<(), String> {
+    let outputs = run(&overlap(KEY_A, KEY_G, 40, false));
+    expect_keyboard(
+        &outputs,
+        &[
+            keyboard(0, &[A]),
+            keyboard(0, &[A, G]),
+            keyboard(0, &[A]),
+            keyboard(0, &[]),
+        ],
+    )
+}
+
+/// D and F pressed together and held are Ctrl+Shift, not the D+F combo.
+fn home_row_chord_holds_both() -> Result<(), String> {
+    let script = [
+        Press(KEY_D.0, KEY_D.1),
+        Wait(10),
+        Press(KEY_F.0, KEY_F.1),
+        Wait(300),
+    ];
+    let script = [&script[..], &tap(KEY_H), &[Wait(20)]].concat();
+    let outputs = run(&script);
+    expect_report(&outputs, keyboard(CTRL | SHIFT, &[H]))?;
+    expect_no_report(&outputs, keyboard(0, &[TAB]))
+}
+
+fn oneshot_shift_applies_to_next_key() -> Result<(), String> {
+    let script = [
+        &tap_l3(OS_SHIFT_L3)[..],
+        &[Wait(100)],
+        &tap(KEY_Q),
+        &[SETTLE],
+        &tap(KEY_W),
+    ]
+    .concat();
+    let outputs = run(&script);
+    expect_keyboard(
+        &outputs,
+        &[
+            keyboard(SHIFT, &[]),
+            keyboard(SHIFT, &[Q]),
+            keyboard(SHIFT, &[]),
+            keyboard(0, &[]),
+            keyboard(0, &[W]),
+            keyboard(0, &[]),
+        ],
+    )
+}
+
+/// Layer 3 stays on while its `OS(L_SHFT)` is held, so the key tapped meanwhile is one which
+/// layer 3 leaves to the base layer.
+fn oneshot_held_acts_as_modifier() -> Result<(), String> {
+    let mut script = [&tap(OSL3)[..], &hold_and_tap(OS_SHIFT_L3, KEY_G)].concat();
+    script.extend(tap(KEY_W));
+    let outputs = run(&script);
+    expect_report(&outputs, keyboard(SHIFT, &[G]))?;
+    expect_report(&outputs, keyboard(0, &[W]))
+}
+
+fn oneshot_times_out() -> Result<(), String> {
+    let script = [&tap_l3(OS_SHIFT_L3)[..], &[Wait(2000)], &tap(KEY_Q)].concat();
+    let outputs = run(&script);
+    expect_no_report(&outputs, keyboard(SHIFT, &[Q]))?;
+    match outputs.iter().find(|o| o.report == keyboard(0, &[])) {
+        Some(o) if (1500..2000).contains(&o.time_ms) => Ok(()),
+        Some(o) => Err(format!("released at {}ms", o.time_ms)),
+        None => Err("never released".to_string()),
+    }
+}
+
+fn oneshot_cancelled_by_esc() -> Result<(), String> {
+    let script = [&tap_l3(OS_SHIFT_L3)[..], &tap(KEY_ESC), &tap(KEY_Q)].concat();
+    let outputs = run(&script);
+    expect_no_report(&outputs, keyboard(SHIFT, &[ESC]))?;
+    expect_no_report(&outputs, keyboard(0, &[ESC]))?;
+    expect_report(&outputs, keyboard(0, &[Q]))
+}
+
+fn oneshot_layer_applies_to_next_key() -> Result<(), String> {
+    let script = [
+        &tap(OSL3)[..],
+        &tap(KEY_S),
+        &[SETTLE],
+        &tap(KEY_S),
+        &[SETTLE],
+    ]
+    .concat();
+    let outputs = run(&script);
+    expect_report(&outputs, Report::Layer(3))?;
+    expect_keyboard(
+        &outputs,
+        &[
+            keyboard(0, &[KP4]),
+            keyboard(0, &[]),
+            keyboard(0, &[S]),
+            keyboard(0, &[]),
+        ],
+    )
+}
+
+/// The one-shot modifiers are on layer 3, so they are reached through `OSL(3)` and apply to
+/// the next key on the base layer.
+fn oneshot_layer_then_modifier() -> Result<(), String> {
+    let script = [&tap(OSL3)[..], &tap(OS_CTRL_L3), &tap(KEY_Q), &[SETTLE]].concat();
+    let outputs = run(&script);
+    expect_report(&outputs, keyboard(CTRL, &[Q]))?;
+    match outputs.last() {
+        Some(o) if o.report == Report::Layer(0) || o.report == keyboard(0, &[]) => Ok(()),
+        _ => Err(format!("a one-shot is still active: {outputs:?}")),
+    }
+}
+
+/// Turns Caps Word on through the one-shot symbol layer.
+fn caps_word_on() -> Vec<Step> {
+    [&tap(OSL3)[..], &tap(CAPS_WORD_L3)].concat()
+}
+
+fn caps_word_shifts_letters_until_non_word_key() -> Result<(), String> {
+    let script = [
+        &caps_word_on()[..],
+        &tap(KEY_Q),
+        &tap(KEY_W),
+        &tap(KEY_DOT),
+        &tap(KEY_Q),
+    ]
+    .concat();
+    let outputs = run(&script);
+    expect_keyboard(
+        &outputs,
+        &[
+            keyboard(SHIFT, &[Q]),
+            keyboard(0, &[]),
+            keyboard(SHIFT, &[W]),
+            keyboard(0, &[]),
+            keyboard(0, &[DOT]),
+            keyboard(0, &[]),
+            keyboard(0, &[Q]),
+            keyboard(0, &[]),
+        ],
+    )
+}
+
+fn caps_word_keeps_digits_and_underscore() -> Result<(), String> {
+    let script = [
+        &caps_word_on()[..],
+        &tap(KEY_Q),
+        &tap(KEY_MINUS),
+        &tap(KEY_1),
+        &tap(KEY_W),
+    ]
+    .concat();
+    let outputs = run(&script);
+    expect_keyboard(
+        &outputs,
+        &[
+            keyboard(SHIFT, &[Q]),
+            keyboard(0, &[]),
+            keyboard(SHIFT, &[MINUS]),
+            keyboard(0, &[]),
+            keyboard(0, &[D1]),
+            keyboard(0, &[]),
+            keyboard(SHIFT, &[W]),
+            keyboard(0, &[]),
+        ],
+    )
+}
+
+fn caps_word_ended_by_ctrl() -> Result<(), String> {
+    let script = [
+        &caps_word_on()[..],
+        &hold_and_tap(KEY_L_CTRL, KEY_Q),
+        &tap(KEY_W),
+    ]
+    .concat();
+    let outputs = run(&script);
+    expect_report(&outputs, keyboard(CTRL, &[Q]))?;
+    expect_no_report(&outputs, keyboard(CTRL | SHIFT, &[Q]))?;
+    expect_report(&outputs, keyboard(0, &[W]))
+}
+
+fn caps_word_times_out() -> Result<(), String> {
+    let script = [&caps_word_on()[..], &[Wait(6000)], &tap(KEY_Q)].concat();
+    let outputs = run(&script);
+    expect_keyboard(&outputs, &[keyboard(0, &[Q]), keyboard(0, &[])])
+}
+
+fn caps_word_toggled_off() -> Result<(), String> {
+    let script = [&caps_word_on()[..], &caps_word_on(), &tap(KEY_Q)].concat();
+    let outputs = run(&script);
+    expect_keyboard(&outputs, &[keyboard(0, &[Q]), keyboard(0, &[])])
+}
+
+/// Starts a leader sequence through the one-shot symbol layer.
+fn leader() -> Vec<Step> {
+    [&tap(OSL3)[..], &tap(LEADER_L3)].concat()
+}
+
+/// The keyboard reports which type `text`.
+fn typed(text: &str) -> Vec<Report> {
+    text.bytes()
+        .flat_map(|c| {
+            let (modifier, keycode) = ascii_key(c).unwrap();
+            [keyboard(modifier, &[keycode]), keyboard(0, &[])]
+        })
+        .collect()
+}
+
+fn leader_types_sequence() -> Result<(), String> {
+    let script = [&leader()[..], &tap(KEY_G), &tap(KEY_S), &[SETTLE]].concat();
+    let outputs = run(&script);
+    expect_keyboard(&outputs, &typed("git status\n"))
+}
+
+/// `G` is a sequence, but also the start of `G S` and `G D`.
+fn leader_prefix_waits_for_timeout() -> Result<(), String> {
+    let script = [&leader()[..], &tap(KEY_G), &[Wait(1500)]].concat();
+    let outputs = run(&script);
+    expect_keyboard(&outputs, &typed("git "))
+}
+
+fn leader_prefix_continued() -> Result<(), String> {
+    let script = [
+        &leader()[..],
+        &tap(KEY_G),
+        &[Wait(500)],
+        &tap(KEY_D),
+        &[SETTLE],
+    ]
+    .concat();
+    let outputs = run(&script);
+    expect_keyboard(&outputs, &typed("git diff\n"))
+}
+
+fn leader_unknown_sequence_types_nothing() -> Result<(), String> {
+    let script = [&leader()[..], &tap(KEY_Q), &[SETTLE], &tap(KEY_W)].concat();
+    let outputs = run(&script);
+    expect_keyboard(&outputs, &[keyboard(0, &[W]), keyboard(0, &[])])
+}
+
+fn leader_times_out() -> Result<(), String> {
+    let script = [&leader()[..], &[Wait(1500)], &tap(KEY_G)].concat();
+    let outputs = run(&script);
+    expect_keyboard(&outputs, &[keyboard(0, &[G]), keyboard(0, &[])])
+}
+
+fn key_override_shift_backspace_is_delete() -> Result<(), String> {
+    let outputs = run(&[&tap(KEY_BS)[..], &hold_and_tap(KEY_F, KEY_BS)].concat());
+    expect_keyboard(
+        &outputs,
+        &[
+            keyboard(0, &[BACKSPACE]),
+            keyboard(0, &[]),
+            keyboard(SHIFT, &[]),
+            keyboard(0, &[DELETE]),
+            keyboard(SHIFT, &[]),
+            keyboard(0, &[]),
+        ],
+    )
+}
+
+fn key_override_shift_esc_is_tilde() -> Result<(), String> {
+    let outputs = run(&hold_and_tap(KEY_F, KEY_ESC));
+    expect_report(&outputs, keyboard(SHIFT, &[GRAVE]))?;
+    expect_no_report(&outputs, keyboard(SHIFT, &[ESC]))
+}
+
+/// Releasing Shift before Backspace keeps sending Delete until Backspace is released.
+fn key_override_outlives_modifier() -> Result<(), String> {
+    let (f, bs) = (KEY_F, KEY_BS);
+    let script = [
+        Press(f.0, f.1),
+        Wait(300),
+        Press(bs.0, bs.1),
+        Wait(20),
+        Release(f.0, f.1),
+        Wait(100),
+        Release(bs.0, bs.1),
+        SETTLE,
+    ];
+    let outputs = run(&script);
+    expect_keyboard(
+        &outputs,
+        &[
+            keyboard(SHIFT, &[]),
+            keyboard(0, &[DELETE]),
+            keyboard(0, &[]),
+        ],
+    )
+}
+
+/// Shift+Esc is only overridden on the base layer.
+fn key_override_is_layer_scoped() -> Result<(), String> {
+    let script = [
+        &[Press(OSL3.0, OSL3.1), Press(KEY_L_SHIFT.0, KEY_L_SHIFT.1)][..],
+        &tap(KEY_ESC),
+        &[
+            Release(KEY_L_SHIFT.0, KEY_L_SHIFT.1),
+            Release(OSL3.0, OSL3.1),
+            SETTLE,
+        ],
+    ]
+    .concat();
+    let outputs = run(&script);
+    expect_report(&outputs, keyboard(SHIFT, &[ESC]))?;
+    expect_no_report(&outputs, keyboard(SHIFT, &[GRAVE]))
+}
+
+/// Selects the next base layout `times` times through the one-shot symbol layer.
+fn next_base_layout(times: usize) -> Vec<Step> {
+    (0..times)
+        .flat_map(|_| [&tap(OSL3)[..], &tap(BASE_LAYOUT_L3)].concat())
+        .collect()
+}
+
+/// K is a home-row mod, so its tap is translated as well.
+fn base_layout_colemak() -> Result<(), String> {
+    let script = [
+        &next_base_layout(1)[..],
+        &tap(KEY_E),
+        &tap(KEY_K),
+        &[SETTLE],
+    ]
+    .concat();
+    let outputs = run(&script);
+    expect_keyboard(
+        &outputs,
+        &[
+            keyboard(0, &[F]),
+            keyboard(0, &[]),
+            keyboard(0, &[E]),
+            keyboard(0, &[]),
+        ],
+    )
+}
+
+/// Dvorak moves `'` on layer 0, but not on the symbol layer.
+fn base_layout_leaves_other_layers() -> Result<(), String> {
+    let script = [
+        &next_base_layout(2)[..],
+        &tap(KEY_QUOTE),
+        &tap(KEY_N),
+        &tap(OSL3),
+        &tap(QUOTE_L3),
+    ]
+    .concat();
+    let outputs = run(&script);
+    expect_keyboard(
+        &outputs,
+        &[
+            keyboard(0, &[MINUS]),
+            keyboard(0, &[]),
+            keyboard(0, &[B]),
+            keyboard(0, &[]),
+            keyboard(0, &[QUOTE]),
+            keyboard(0, &[]),
+        ],
+    )
+}
+
+fn base_layout_cycles_back() -> Result<(), String> {
+    let script = [&next_base_layout(3)[..], &tap(KEY_E), &tap(KEY_N)].concat();
+    let outputs = run(&script);
+    expect_keyboard(
+        &outputs,
+        &[
+            keyboard(0, &[E]),
+            keyboard(0, &[]),
+            keyboard(0, &[N]),
+            keyboard(0, &[]),
+        ],
+    )
+}
+
+/// Toggles the OS profile `times` times through the one-shot symbol layer.
+fn toggle_os_profile(times: usize) -> Vec<Step> {
+    (0..times)
+        .flat_map(|_| [&tap(OSL3)[..], &tap(OS_PROFILE_L3)].concat())
+        .collect()
+}
+
+/// Ctrl of the bottom row and of the home row D are sent as Cmd.
+fn os_profile_mac_swaps_ctrl() -> Result<(), String> {
+    let script = [
+        &toggle_os_profile(1)[..],
+        &hold_and_tap(KEY_L_CTRL, KEY_C),
+        &hold_and_tap(KEY_D, KEY_C),
+    ]
+    .concat();
+    let outputs = run(&script);
+    expect_report(&outputs, keyboard(GUI, &[C]))?;
+    expect_no_report(&outputs, keyboard(CTRL, &[C]))
+}
+
+/// GUI of the bottom row is sent as Ctrl, and Alt is left alone.
+fn os_profile_mac_swaps_gui() -> Result<(), String> {
+    let gui = run(&[&toggle_os_profile(1)[..], &hold_and_tap(KEY_L_GUI, KEY_C)].concat());
+    expect_report(&gui, keyboard(CTRL, &[C]))?;
+    expect_no_report(&gui, keyboard(GUI, &[C]))?;
+    let alt = run(&[&toggle_os_profile(1)[..], &hold_and_tap(KEY_L_ALT, KEY_C)].concat());
+    expect_report(&alt, keyboard(ALT, &[C]))
+}
+
+/// Ctrl+Backspace deletes a word on a Mac as well, where it is Option+Backspace.
+fn os_profile_mac_shortcut() -> Result<(), String> {
+    let outputs = run(&[&toggle_os_profile(1)[..], &hold_and_tap(KEY_L_CTRL, KEY_BS)].concat());
+    expect_report(&outputs, keyboard(ALT, &[BACKSPACE]))?;
+    expect_no_report(&outputs, keyboard(GUI, &[BACKSPACE]))
+}
+
+fn os_profile_toggles_back() -> Result<(), String> {
+    let script = [
+        &toggle_os_profile(2)[..],
+        &hold_and_tap(KEY_L_CTRL, KEY_C),
+        &hold_and_tap(KEY_L_CTRL, KEY_BS),
+    ]
+    .concat();
+    let outputs = run(&script);
+    expect_report(&outputs, keyboard(CTRL, &[C]))?;
+    expect_report(&outputs, keyboard(CTRL, &[BACKSPACE]))?;
+    expect_no_report(&outputs, keyboard(GUI, &[C]))
+}
+
+/// Toggles the layout of the host `times` times through the one-shot symbol layer.
+fn toggle_host_layout(times: usize) -> Vec<Step> {
+    (0..times)
+        .flat_map(|_| [&tap(OSL3)[..], &tap(HOST_LAYOUT_L3)].concat())
+        .collect()
+}
+
+/// `"`, `_` and `'` keep Shift, `@` loses it.
+fn host_layout_jis_symbols() -> Result<(), String> {
+    let script = [
+        &toggle_host_layout(1)[..],
+        &[Press(KEY_F.0, KEY_F.1), Wait(300)],
+        &tap(KEY_QUOTE),
+        &tap(KEY_MINUS),
+        &tap(KEY_2),
+        &[Wait(20), Release(KEY_F.0, KEY_F.1), SETTLE],
+        &tap(KEY_QUOTE),
+    ]
+    .concat();
+    let outputs = run(&script);
+    expect_report(&outputs, keyboard(SHIFT, &[D2]))?;
+    expect_report(&outputs, keyboard(SHIFT, &[JIS_UNDERSCORE]))?;
+    expect_report(&outputs, keyboard(0, &[JIS_AT]))?;
+    expect_report(&outputs, keyboard(SHIFT, &[D7]))?;
+    expect_no_report(&outputs, keyboard(SHIFT, &[QUOTE]))
+}
+
+/// Shortcuts are looked up by key, so Ctrl+' is left alone.
+fn host_layout_jis_keeps_shortcuts() -> Result<(), String> {
+    let outputs = run(&[
+        &toggle_host_layout(1)[..],
+        &hold_and_tap(KEY_L_CTRL, KEY_QUOTE),
+    ]
+    .concat());
+    expect_report(&outputs, keyboard(CTRL, &[QUOTE]))
+}
+
+fn host_layout_lang_keys() -> Result<(), String> {
+    let us = run(&[&tap(L3LANG1)[..], &tap(L2LANG2), &[SETTLE]].concat());
+    expect_keyboard(
+        &us,
+        &[
+            keyboard(0, &[LANG1]),
+            keyboard(0, &[]),
+            keyboard(0, &[LANG2]),
+            keyboard(0, &[]),
+        ],
+    )?;
+    let windows = run(&[&toggle_host_layout(1)[..], &tap(L3LANG1), &[SETTLE]].concat());
+    expect_report(&windows, keyboard(0, &[HENKAN]))?;
+    let mac = [
+        &toggle_host_layout(1)[..],
+        &toggle_os_profile(1),
+        &tap(L3LANG1),
+        &[SETTLE],
+    ]
+    .concat();
+    expect_report(&run(&mac), keyboard(0, &[LANG1]))
+}
+
+/// Taps a key of layer 3 through the one-shot symbol layer.
+fn tap_l3(key: (u8, u8)) -> Vec<Step> {
+    [&tap(OSL3)[..], &tap(key)].concat()
+}
+
+/// How often `report` reaches the host.
+fn count_report(outputs: &[Output], report: Report) -> usize {
+    keyboard_reports(outputs)
+        .into_iter()
+        .filter(|r| *r == report)
+        .count()
+}
+
+/// The recording of Q and Ctrl+C is typed again by the play key.
+fn dynamic_macro_plays_recording() -> Result<(), String> {
+    let script = [
+        &tap_l3(DM_REC1_L3)[..],
+        &tap(KEY_Q),
+        &hold_and_tap(KEY_L_CTRL, KEY_C),
+        &tap_l3(DM_RSTP_L3),
+        &tap_l3(DM_PLY1_L3),
+        &[SETTLE],
+    ]
+    .concat();
+    let outputs = run(&script);
+    for report in [keyboard(0, &[Q]), keyboard(CTRL, &[C])] {
+        if count_report(&outputs, report.clone()) != 2 {
+            return Err(format!("expected {report:?} twice, got {outputs:?}"));
+        }
+    }
+    Ok(())
+}
+
+/// Pressing the record key of the slot again stops the recording, like the stop key.
+fn dynamic_macro_record_key_stops() -> Result<(), String> {
+    let script = [
+        &tap_l3(DM_REC2_L3)[..],
+        &tap(KEY_E),
+        &tap_l3(DM_REC2_L3),
+        &tap_l3(DM_PLY2_L3),
+        &tap_l3(DM_PLY2_L3),
+        &[SETTLE],
+    ]
+    .concat();
+    let outputs = run(&script);
+    match count_report(&outputs, keyboard(0, &[E])) {
+        3 => Ok(()),
+        n => Err(format!("expected E 3 times, got {n}: {outputs:?}")),
+    }
+}
+
+/// Playing a slot which was never recorded sends no keys.
+fn dynamic_macro_empty_slot() -> Result<(), String> {
+    let outputs = run(&[&tap_l3(DM_PLY1_L3)[..], &[SETTLE]].concat());
+    match keyboard_reports(&outputs)
+        .into_iter()
+        .find(|r| *r != keyboard(0, &[]))
+    {
+        None => Ok(()),
+        Some(report) => Err(format!("unexpected {report:?}")),
+    }
+}
+
+/// The sign-off macro of `keymap.json`: `Thanks,`, Shift+Enter and `negL`.
+fn text_macro_types_steps() -> Result<(), String> {
+    let outputs = run(&[&tap_l3(MACRO0_L3)[..], &[SETTLE]].concat());
+    let typed: Vec<_> = keyboard_reports(&outputs)
+        .into_iter()
+        .filter(|r| *r != keyboard(0, &[]))
+        .collect();
+    let expected = [
+        keyboard(SHIFT, &[T]),
+        keyboard(0, &[H]),
+        keyboard(0, &[A]),
+        keyboard(0, &[N]),
+        keyboard(0, &[K]),
+        keyboard(0, &[S]),
+        keyboard(0, &[0x36]),
+        keyboard(SHIFT, &[ENTER]),
+        keyboard(0, &[N]),
+        keyboard(0, &[E]),
+        keyboard(0, &[G]),
+        keyboard(SHIFT, &[L]),
+    ];
+    if typed == expected {
+        Ok(())
+    } else {
+        Err(format!("expected {expected:?}, got {typed:?}"))
+    }
+}
+
+/// The terminal macro waits 500ms after Ctrl+Alt+T before typing.
+fn text_macro_waits_for_delay() -> Result<(), String> {
+    let outputs = run(&[&tap_l3(MACRO1_L3)[..], &[Wait(1000)]].concat());
+    let time = |report: Report| {
+        outputs
+            .iter()
+            .find(|o| o.report == report)
+            .map(|o| o.time_ms)
+            .ok_or(format!("expected {report:?} to be reported"))
+    };
+    let tap = time(keyboard(CTRL | ALT, &[T]))?;
+    let text = time(keyboard(0, &[C]))?;
+    if text >= tap + 500 {
+        Ok(())
+    } else {
+        Err(format!("C was typed {}ms after Ctrl+Alt+T", text - tap))
+    }
+}
+
+const CHECKS: &[(&str, Check)] = &[
+    ("L2LANG2 tap sends Lang2", l2lang2_tap_sends_lang2),
+    ("SPACE sends Space", space_sends_space),
+    (
+        "L2LANG2 hold activates layer 2",
+        l2lang2_hold_activates_layer_2,
+    ),
+    ("L2LANG2 hold + M_LEFT clicks", l2lang2_hold_clicks_mouse),
+    ("L2ENTER tap sends Enter", l2enter_tap_sends_enter),
+    (
+        "L3SPC hold + SF(D2) sends Shift+2",
+        l3spc_hold_sends_shifted_digit,
+    ),
+    ("L4GRV tap sends Grave", l4grv_tap_sends_grave),
+    ("L4GRV hold activates layer 4", l4grv_hold_activates_layer_4),
+    (
+        "TD(0) single tap sends ]",
+        td0_single_tap_sends_right_bracket,
+    ),
+    (
+        "TD(0) double tap toggles layer 2",
+        td0_double_tap_toggles_layer_2,
+    ),
+    (
+        "TD(0) triple tap toggles layer 3",
+        td0_triple_tap_toggles_layer_3,
+    ),
+    ("combo J+K sends Esc", combo_jk_sends_esc),
+    ("combo F+D sends Tab", combo_df_sends_tab),
+    ("rolling J -> K types jk", rolling_jk_does_not_fire),
+    ("rolling D -> F types df", rolling_df_does_not_fire),
+    ("home-row A tap sends A", home_row_tap_sends_letter),
+    (
+        "home-row F hold + H sends Shift+H",
+        home_row_hold_sends_modifier,
+    ),
+    (
+        "home-row tapping term is per key",
+        home_row_tapping_term_is_per_key,
+    ),
+    ("home-row permissive hold", home_row_permissive_hold),
+    (
+        "home-row F -> H roll types fh",
+        home_row_roll_to_other_hand_types,
+    ),
+    (
+        "home-row F roll past the event channel types in order",
+        home_row_long_roll_types_in_order,
+    ),
+    (
+        "home-row A -> G roll types ag",
+        home_row_same_hand_roll_types,
+    ),
+    ("home-row D+F held is Ctrl+Shift", home_row_chord_holds_both),
+    (
+        "one-shot Shift applies to the next key",
+        oneshot_shift_applies_to_next_key,
+    ),
+    (
+        "one-shot Shift held is Shift",
+        oneshot_held_acts_as_modifier,
+    ),
+    ("one-shot Shift times out", oneshot_times_out),
+    (
+        "one-shot Shift is cancelled by Esc",
+        oneshot_cancelled_by_esc,
+    ),
+    (
+        "one-shot layer 3 applies to the next key",
+        oneshot_layer_applies_to_next_key,
+    ),
+    (
+        "one-shot layer 3 -> one-shot Ctrl",
+        oneshot_layer_then_modifier,
+    ),
+    (
+        "Caps Word shifts letters until a non-word key",
+        caps_word_shifts_letters_until_non_word_key,
+    ),
+    (
+        "Caps Word keeps digits and shifts -",
+        caps_word_keeps_digits_and_underscore,
+    ),
+    ("Caps Word is ended by Ctrl", caps_word_ended_by_ctrl),
+    ("Caps Word times out", caps_word_times_out),
+    ("Caps Word key toggles it off", caps_word_toggled_off),
+    ("leader G S types git status", leader_types_sequence),
+    (
+        "leader G waits for the timeout",
+        leader_prefix_waits_for_timeout,
+    ),
+    ("leader G D types git diff", leader_prefix_continued),
+    (
+        "leader with an unknown sequence types nothing",
+        leader_unknown_sequence_types_nothing,
+    ),
+    ("leader times out without keys", leader_times_out),
+    (
+        "Shift+Backspace sends Delete",
+        key_override_shift_backspace_is_delete,
+    ),
+    ("Shift+Esc sends ~", key_override_shift_esc_is_tilde),
+    (
+        "key override outlives its modifier",
+        key_override_outlives_modifier,
+    ),
+    ("key override is layer scoped", key_override_is_layer_scoped),
+    ("Colemak translates layer 0", base_layout_colemak),
+    (
+        "Dvorak leaves the symbol layer alone",
+        base_layout_leaves_other_layers,
+    ),
+    ("base layout cycles back to QWERTY", base_layout_cycles_back),
+    ("Mac profile sends Cmd for Ctrl", os_profile_mac_swaps_ctrl),
+    ("Mac profile sends Ctrl for Cmd", os_profile_mac_swaps_gui),
+    ("Mac profile remaps Ctrl+Backspace", os_profile_mac_shortcut),
+    (
+        "OS profile toggles back to Windows",
+        os_profile_toggles_back,
+    ),
+    (
+        "JIS host layout translates symbols",
+        host_layout_jis_symbols,
+    ),
+    (
+        "JIS host layout keeps shortcuts",
+        host_layout_jis_keeps_shortcuts,
+    ),
+    ("Lang1 is Henkan on JIS Windows", host_layout_lang_keys),
+    (
+        "dynamic macro plays its recording",
+        dynamic_macro_plays_recording,
+    ),
+    (
+        "dynamic macro record key stops",
+        dynamic_macro_record_key_stops,
+    ),
+    (
+        "dynamic macro empty slot types nothing",
+        dynamic_macro_empty_slot,
+    ),
+    ("MACRO(0) types its steps", text_macro_types_steps),
+    ("MACRO(1) waits for its delay", text_macro_waits_for_delay),
+];
+
+fn main() -> ExitCode {
+    common::run("keymap", CHECKS)
+}
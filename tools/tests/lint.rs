@@ -0,0 +1,129 @@
+//! Lints `keymap.json` and fails on errors. Each lint is also checked against a small keymap
+//! which is known to trigger it.
+
+mod common;
+
+use std::process::ExitCode;
+
+use common::Check;
+use negl_tools::{
+    keymap_file, keymap_json,
+    lint::{lint, Finding, Severity},
+};
+
+/// A 1x4 layout, enough for the fixtures below.
+const LAYOUT: &[(usize, usize)] = &[(0, 0), (0, 1), (0, 2), (0, 3)];
+
+fn lint_fixture(json: &str) -> Result<Vec<Finding>, String> {
+    keymap_json::parse(json, LAYOUT).map(|file| lint(&file))
+}
+
+/// Checks that linting `json` produces a finding of `severity` containing `needle`.
+fn expect_finding(json: &str, severity: Severity, needle: &str) -> Result<(), String> {
+    let findings = lint_fixture(json)?;
+    if findings
+        .iter()
+        .any(|f| f.severity == severity && f.message.contains(needle))
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "expected a finding with `{needle}`, got {findings:?}"
+        ))
+    }
+}
+
+fn unreachable_layer() -> Result<(), String> {
+    expect_finding(
+        r#"{ "layers": [
+            { "keys": { "0,0": "A" } },
+            { "keys": { "0,0": "M_LEFT" } },
+            { "name": "Lost", "keys": { "0,0": "B" } }
+        ] }"#,
+        Severity::Error,
+        "layer 2 `Lost` cannot be reached",
+    )
+}
+
+fn one_way_toggle() -> Result<(), String> {
+    expect_finding(
+        r#"{ "layers": [
+            { "keys": { "0,0": "TG(2)", "0,1": "A" } },
+            { "keys": {} },
+            { "name": "Trap", "keys": { "0,0": "B", "0,1": "C" } }
+        ] }"#,
+        Severity::Error,
+        "no TG(2)",
+    )
+}
+
+fn toggle_through_base_is_fine() -> Result<(), String> {
+    let findings = lint_fixture(
+        r#"{ "layers": [
+            { "keys": { "0,0": "TG(2)", "0,1": "A" } },
+            { "keys": {} },
+            { "keys": { "0,1": "C" } }
+        ] }"#,
+    )?;
+    match findings.iter().find(|f| f.severity == Severity::Error) {
+        Some(f) => Err(format!("unexpected {f}")),
+        None => Ok(()),
+    }
+}
+
+fn shadowed_key() -> Result<(), String> {
+    expect_finding(
+        r#"{ "aliases": { "L2A": "TH(A, MO(2))" }, "layers": [
+            { "keys": { "0,0": "L2A" } },
+            { "keys": {} },
+            { "keys": { "0,0": "B", "0,1": "C" } }
+        ] }"#,
+        Severity::Warning,
+        "key 0,0 `B` on layer 2",
+    )
+}
+
+fn tap_dance_to_empty_layer() -> Result<(), String> {
+    expect_finding(
+        r#"{ "layers": [
+            { "keys": { "0,0": "TD(0)" } },
+            { "keys": {} },
+            { "keys": {} }
+        ], "tap_dance": [{ "tap": ["A", "TG(2)"], "hold": [null, null] }] }"#,
+        Severity::Warning,
+        "slot 1 of TD(0)",
+    )
+}
+
+const CHECKS: &[(&str, Check)] = &[
+    ("unreachable layer", unreachable_layer),
+    ("one-way toggle", one_way_toggle),
+    (
+        "toggle back through the base layer",
+        toggle_through_base_is_fine,
+    ),
+    ("shadowed key", shadowed_key),
+    ("tap dance to an empty layer", tap_dance_to_empty_layer),
+];
+
+fn main() -> ExitCode {
+    let mut failed = common::run_checks("lint", CHECKS);
+
+    println!();
+    match keymap_file() {
+        Ok(file) => {
+            for finding in lint(&file) {
+                println!("keymap.json: {finding}");
+                if finding.severity == Severity::Error {
+                    failed += 1;
+                }
+            }
+        }
+        Err(e) => {
+            println!("{e}");
+            failed += 1;
+        }
+    }
+
+    common::exit_code(failed)
+}
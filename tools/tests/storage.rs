@@ -0,0 +1,258 @@
+//! Runs the firmware's [`FlashStorage`] on a [`RamFlash`] and checks that written values read
+//! back, including after the items have filled the flash and pages were reused, and that the
+//! schema header keeps or erases the stored data on boot.
+
+mod common;
+
+use std::process::ExitCode;
+
+use common::{expect_eq, Check};
+use embassy_futures::block_on;
+use negl_tools::dynamic_macro::ENCODED_SIZE;
+use negl_tools::storage::schema::{
+    migrate, read_header, SchemaHeader, Stored, FINGERPRINT, HEADER_KEY, SCHEMA_VERSION,
+};
+use negl_tools::storage::{settings, FlashStorage, FlashStorageError, RamFlash, DATA_BUFFER_SIZE};
+use negl_tools::text_macro::{decode, DecodeError, Encoder, Step, MAX_SIZE};
+use rktk::drivers::interface::storage::StorageDriver;
+
+/// The largest value an item can hold: its key takes 8 bytes of the data buffer.
+const LARGEST: usize = DATA_BUFFER_SIZE - 8;
+
+fn storage() -> FlashStorage<RamFlash> {
+    let flash = RamFlash::new(4);
+    let range = flash.range();
+    FlashStorage::new(flash, range)
+}
+
+fn read<const N: usize>(storage: &FlashStorage<RamFlash>, key: u64) -> Result<[u8; N], String> {
+    let mut buf = [0; N];
+    block_on(storage.read(key, &mut buf)).map_err(|e| format!("read {key:#x}: {e:?}"))?;
+    Ok(buf)
+}
+
+fn write<const N: usize>(
+    storage: &FlashStorage<RamFlash>,
+    key: u64,
+    value: &[u8; N],
+) -> Result<(), String> {
+    block_on(storage.write(key, value)).map_err(|e| format!("write {key:#x}: {e:?}"))
+}
+
+fn store_fetch() -> Result<(), String> {
+    let storage = storage();
+    write(&storage, 1, &[1, 2, 3])?;
+    write(&storage, 2, &[4; 16])?;
+    expect_eq(read::<3>(&storage, 1)?, [1, 2, 3])?;
+    expect_eq(read::<16>(&storage, 2)?, [4; 16])
+}
+
+/// The last value written to a key wins, and other keys are left alone.
+fn overwrite() -> Result<(), String> {
+    let storage = storage();
+    write(&storage, 1, &[1; 8])?;
+    write(&storage, 2, &[2; 8])?;
+    write(&storage, 1, &[3; 8])?;
+    expect_eq(read::<8>(&storage, 1)?, [3; 8])?;
+    expect_eq(read::<8>(&storage, 2)?, [2; 8])
+}
+
+/// Rewriting a key many times fills every page, so pages are erased and reused.
+fn overwrite_across_pages() -> Result<(), String> {
+    let storage = storage();
+    write(&storage, 2, &[0xaa; 64])?;
+    for i in 0..64u8 {
+        write(&storage, 1, &[i; 512])?;
+    }
+    expect_eq(read::<512>(&storage, 1)?, [63; 512])?;
+    expect_eq(read::<64>(&storage, 2)?, [0xaa; 64])
+}
+
+fn largest_value() -> Result<(), String> {
+    let storage = storage();
+    let value: [u8; LARGEST] = std::array::from_fn(|i| i as u8);
+    write(&storage, 1, &value)?;
+    expect_eq(read::<LARGEST>(&storage, 1)?, value)?;
+    if block_on(storage.write(2, &[0; DATA_BUFFER_SIZE])).is_ok() {
+        return Err("a value as large as the data buffer was written".into());
+    }
+    Ok(())
+}
+
+fn missing_and_mismatched() -> Result<(), String> {
+    let storage = storage();
+    write(&storage, 1, &[1, 2, 3])?;
+    match block_on(storage.read(2, &mut [0; 3])) {
+        Err(FlashStorageError::NotFound) => {}
+        r => return Err(format!("missing key: {r:?}")),
+    }
+    match block_on(storage.read(1, &mut [0; 4])) {
+        Err(FlashStorageError::SizeMismatch) => Ok(()),
+        r => Err(format!("wrong size: {r:?}")),
+    }
+}
+
+/// Nothing is left after formatting.
+fn format_erases() -> Result<(), String> {
+    let storage = storage();
+    write(&storage, 1, &[1, 2, 3])?;
+    block_on(storage.format()).map_err(|e| format!("format: {e:?}"))?;
+    match block_on(storage.read(1, &mut [0; 3])) {
+        Err(FlashStorageError::NotFound) => Ok(()),
+        r => Err(format!("after format: {r:?}")),
+    }
+}
+
+/// A dynamic macro of the largest size is stored and read back whole, next to the other slot.
+fn dynamic_macro_round_trip() -> Result<(), String> {
+    let storage = storage();
+    let first = [0x5a; ENCODED_SIZE];
+    let second: [u8; ENCODED_SIZE] = std::array::from_fn(|i| i as u8);
+    let recorded = [first, second];
+    for (slot, buf) in recorded.iter().enumerate() {
+        block_on(settings::store_dynamic_macro(&storage, slot, buf));
+    }
+    for (slot, buf) in recorded.iter().enumerate() {
+        if block_on(settings::load_dynamic_macro(&storage, slot)).as_ref() != Some(buf) {
+            return Err(format!("slot {slot} did not read back"));
+        }
+    }
+    Ok(())
+}
+
+/// Encodes one macro typing `text`.
+fn text_macros(text: &[u8]) -> Result<Vec<u8>, String> {
+    let mut buf = [0; MAX_SIZE];
+    let mut encoder = Encoder::new(&mut buf).map_err(|e| format!("{e:?}"))?;
+    encoder
+        .begin(b"m")
+        .and_then(|()| encoder.text(text))
+        .map_err(|e| format!("encode failed: {e:?}"))?;
+    let len = encoder.finish();
+    Ok(buf[..len].to_vec())
+}
+
+/// The text of the first step of the stored macros.
+fn stored_text(storage: &FlashStorage<RamFlash>) -> Result<Vec<u8>, String> {
+    let buf = block_on(settings::load_text_macros(storage)).ok_or("no macros stored")?;
+    let mut macros = decode(&buf).map_err(|e| format!("stored macros: {e:?}"))?;
+    match macros.next().and_then(|m| m.steps().next()) {
+        Some(Step::Text(text)) => Ok(text.to_vec()),
+        step => Err(format!("stored macros start with {step:?}")),
+    }
+}
+
+/// Text macros written through the write path read back, and invalid ones are rejected
+/// without replacing them.
+fn text_macros_round_trip() -> Result<(), String> {
+    let storage = storage();
+    let written = text_macros(b"hello\n")?;
+    let stored = block_on(settings::write_text_macros(&storage, &written))
+        .map_err(|e| format!("write: {e:?}"))?;
+    expect_eq(&stored[..written.len()], &written[..])?;
+    expect_eq(stored_text(&storage)?, b"hello\n".to_vec())?;
+
+    let mut invalid = text_macros(b"bye")?;
+    invalid[0] = 0xff;
+    expect_eq(
+        block_on(settings::write_text_macros(&storage, &invalid)),
+        Err(settings::TextMacrosWriteError::Invalid(
+            DecodeError::Version(0xff),
+        )),
+    )?;
+    expect_eq(stored_text(&storage)?, b"hello\n".to_vec())
+}
+
+/// Key of an item standing for the data rktk keeps next to the header.
+const DATA_KEY: u64 = 1;
+
+/// Boots on storage holding data and `header` (raw bytes of any length) under
+/// [`HEADER_KEY`], and returns whether the data was kept.
+fn boot_with<const N: usize>(header: Option<&[u8; N]>) -> Result<bool, String> {
+    let storage = storage();
+    write(&storage, DATA_KEY, &[1, 2, 3])?;
+    if let Some(header) = header {
+        write(&storage, HEADER_KEY, header)?;
+    }
+
+    block_on(migrate(&storage));
+
+    expect_eq(
+        block_on(read_header(&storage)),
+        Stored::Header(SchemaHeader::CURRENT),
+    )?;
+    match block_on(storage.read(DATA_KEY, &mut [0; 3])) {
+        Ok(()) => Ok(true),
+        Err(FlashStorageError::NotFound) => Ok(false),
+        Err(e) => Err(format!("read data: {e:?}")),
+    }
+}
+
+fn boot(header: SchemaHeader) -> Result<bool, String> {
+    boot_with(Some(&header.encode()))
+}
+
+/// Storage written before the header existed is version 0, and is migrated.
+fn schema_missing_header() -> Result<(), String> {
+    expect_eq(boot_with::<0>(None)?, true)
+}
+
+fn schema_older_version() -> Result<(), String> {
+    let header = SchemaHeader {
+        version: SCHEMA_VERSION - 1,
+        fingerprint: FINGERPRINT,
+    };
+    expect_eq(boot(header)?, true)
+}
+
+fn schema_current_version() -> Result<(), String> {
+    expect_eq(boot(SchemaHeader::CURRENT)?, true)
+}
+
+fn schema_newer_version() -> Result<(), String> {
+    let header = SchemaHeader {
+        version: SCHEMA_VERSION + 1,
+        fingerprint: FINGERPRINT,
+    };
+    expect_eq(boot(header)?, false)
+}
+
+fn schema_fingerprint_mismatch() -> Result<(), String> {
+    let header = SchemaHeader {
+        version: SCHEMA_VERSION,
+        fingerprint: !FINGERPRINT,
+    };
+    expect_eq(boot(header)?, false)
+}
+
+/// A header which cannot be read is not mistaken for a missing one.
+fn schema_corrupt_header() -> Result<(), String> {
+    let mut bad_magic = SchemaHeader::CURRENT.encode();
+    bad_magic[0] ^= 0xff;
+    expect_eq(boot_with(Some(&bad_magic))?, false)?;
+    expect_eq(boot_with(Some(&[0; SchemaHeader::SIZE + 1]))?, false)
+}
+
+const CHECKS: &[(&str, Check)] = &[
+    ("store -> fetch", store_fetch),
+    ("overwrite", overwrite),
+    ("overwrite across pages", overwrite_across_pages),
+    ("largest value", largest_value),
+    ("missing key and size mismatch", missing_and_mismatched),
+    ("format", format_erases),
+    (
+        "dynamic macro of the largest size",
+        dynamic_macro_round_trip,
+    ),
+    ("text macros: write -> read", text_macros_round_trip),
+    ("schema: missing header", schema_missing_header),
+    ("schema: older version", schema_older_version),
+    ("schema: current version", schema_current_version),
+    ("schema: newer version", schema_newer_version),
+    ("schema: fingerprint mismatch", schema_fingerprint_mismatch),
+    ("schema: corrupt header", schema_corrupt_header),
+];
+
+fn main() -> ExitCode {
+    common::run("storage", CHECKS)
+}
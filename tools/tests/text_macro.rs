@@ -0,0 +1,159 @@
+//! Checks the wire format of the text macros: what the firmware decodes, what a host encodes,
+//! and that the macros compiled from `keymap.json` decode to the same macros.
+
+mod common;
+
+use std::process::ExitCode;
+
+use common::{expect_eq, Check};
+use negl_tools::keymap::TEXT_MACROS;
+use negl_tools::keymap_file;
+use negl_tools::keymap_json::{TextMacroStepDef, MODIFIERS};
+use negl_tools::text_macro::{
+    decode, validate, DecodeError, EncodeError, Encoder, Step, MAX_SIZE, VERSION,
+};
+
+/// Name and steps of each macro.
+type Macros<'a> = Vec<(Vec<u8>, Vec<Step<'a>>)>;
+
+/// The steps of each macro in `bytes`, by name.
+fn decoded(bytes: &[u8]) -> Result<Macros<'_>, String> {
+    let macros = decode(bytes).map_err(|e| format!("decode failed: {e:?}"))?;
+    Ok(macros
+        .map(|m| (m.name.to_vec(), m.steps().collect()))
+        .collect())
+}
+
+/// The macros compiled into the firmware decode to the `text_macros` of `keymap.json`.
+fn defaults_match_keymap_json() -> Result<(), String> {
+    let file = keymap_file()?;
+    let macros = decoded(TEXT_MACROS.defaults)?;
+    expect_eq(macros.len(), file.text_macros.len())?;
+    for ((name, steps), def) in macros.iter().zip(&file.text_macros) {
+        expect_eq(name.as_slice(), def.name.as_bytes())?;
+        expect_eq(steps.len(), def.steps.len())?;
+        for (step, step_def) in steps.iter().zip(&def.steps) {
+            match (step, step_def) {
+                (Step::Text(text), TextMacroStepDef::Text(expected)) => {
+                    expect_eq(*text, expected.as_bytes())?
+                }
+                (Step::Tap { modifier, key }, TextMacroStepDef::Tap(_, mods)) => {
+                    let expected = MODIFIERS
+                        .iter()
+                        .filter(|(name, _)| mods.iter().any(|m| m == name))
+                        .fold(0, |bits, (_, bit)| bits | bit);
+                    expect_eq(*modifier, expected)?;
+                    if *key == 0 {
+                        return Err(format!("{}: tapped key is 0", def.name));
+                    }
+                }
+                (Step::Delay(ms), TextMacroStepDef::Delay(expected)) => expect_eq(ms, expected)?,
+                (step, step_def) => {
+                    return Err(format!("{}: {step_def:?} decoded as {step:?}", def.name))
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Macros written by a host decode to the same steps, with long text split.
+fn encode_decode() -> Result<(), String> {
+    let long = [b'a'; 300];
+    let mut buf = [0; MAX_SIZE];
+    let mut encoder = Encoder::new(&mut buf).map_err(|e| format!("{e:?}"))?;
+    (|| {
+        encoder.begin(b"first")?;
+        encoder.text(b"hi\n")?;
+        encoder.tap(0x05, 0x17)?;
+        encoder.delay(1000)?;
+        encoder.begin(b"")?;
+        encoder.text(&long)
+    })()
+    .map_err(|e| format!("encode failed: {e:?}"))?;
+    let len = encoder.finish();
+
+    expect_eq(validate(&buf[..len]), Ok(len))?;
+    expect_eq(
+        decoded(&buf[..len])?,
+        vec![
+            (
+                b"first".to_vec(),
+                vec![
+                    Step::Text(b"hi\n"),
+                    Step::Tap {
+                        modifier: 0x05,
+                        key: 0x17,
+                    },
+                    Step::Delay(1000),
+                ],
+            ),
+            (
+                Vec::new(),
+                vec![Step::Text(&long[..255]), Step::Text(&long[255..])],
+            ),
+        ],
+    )
+}
+
+/// Storage keeps the macros padded to `MAX_SIZE`.
+fn padding_is_ignored() -> Result<(), String> {
+    let mut buf = [0; MAX_SIZE];
+    let mut encoder = Encoder::new(&mut buf).map_err(|e| format!("{e:?}"))?;
+    encoder
+        .begin(b"x")
+        .and_then(|()| encoder.text(b"x"))
+        .map_err(|e| format!("{e:?}"))?;
+    let len = encoder.finish();
+    expect_eq(validate(&buf), Ok(len))?;
+    expect_eq(decoded(&buf)?.len(), 1)
+}
+
+fn rejects_malformed() -> Result<(), String> {
+    // One macro named "a" with a tap step.
+    let valid = [VERSION, 1, 1, b'a', 3, 0, 2, 0, 4];
+    expect_eq(validate(&valid), Ok(valid.len()))?;
+
+    let with = |i: usize, byte: u8| {
+        let mut bytes = valid;
+        bytes[i] = byte;
+        bytes
+    };
+    expect_eq(validate(&with(0, 2)), Err(DecodeError::Version(2)))?;
+    expect_eq(validate(&with(1, 2)), Err(DecodeError::Truncated))?;
+    expect_eq(validate(&with(2, 9)), Err(DecodeError::Truncated))?;
+    expect_eq(validate(&with(4, 4)), Err(DecodeError::Truncated))?;
+    expect_eq(validate(&with(6, 9)), Err(DecodeError::UnknownStep(9)))?;
+    expect_eq(validate(&valid[..8]), Err(DecodeError::Truncated))?;
+    expect_eq(validate(&[VERSION]), Err(DecodeError::Truncated))?;
+    expect_eq(validate(&[0; MAX_SIZE + 1]), Err(DecodeError::TooLarge))?;
+    // A text step whose text runs past the steps of its macro.
+    expect_eq(
+        validate(&[VERSION, 1, 0, 3, 0, 1, 2, b'a']),
+        Err(DecodeError::Truncated),
+    )
+}
+
+fn encoder_limits() -> Result<(), String> {
+    let mut buf = [0; MAX_SIZE];
+    let mut encoder = Encoder::new(&mut buf).map_err(|e| format!("{e:?}"))?;
+    expect_eq(encoder.tap(0, 4), Err(EncodeError::NoMacro))?;
+    expect_eq(encoder.begin(&[b'a'; 256]), Err(EncodeError::TooMany))?;
+    expect_eq(encoder.begin(b""), Ok(()))?;
+    expect_eq(encoder.text(&[b'a'; MAX_SIZE]), Err(EncodeError::TooLarge))?;
+
+    let mut small = [0; 1];
+    expect_eq(Encoder::new(&mut small).err(), Some(EncodeError::TooLarge))
+}
+
+const CHECKS: &[(&str, Check)] = &[
+    ("defaults match keymap.json", defaults_match_keymap_json),
+    ("encode -> decode", encode_decode),
+    ("storage padding is ignored", padding_is_ignored),
+    ("malformed macros are rejected", rejects_malformed),
+    ("encoder limits", encoder_limits),
+];
+
+fn main() -> ExitCode {
+    common::run("text_macro", CHECKS)
+}
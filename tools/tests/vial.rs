@@ -0,0 +1,88 @@
+//! Round trips `keymap.json` through the Vial format.
+
+mod common;
+
+use std::process::ExitCode;
+
+use common::Check;
+use negl_tools::{config, keymap_file, keymap_json, rktk_config, vial};
+
+fn export(file: &keymap_json::KeymapFile) -> Result<serde_json::Value, String> {
+    let layout = keymap_json::layout_positions(&rktk_config()?)?;
+    vial::export(file, &layout, config::ROWS, config::COLS).map(|(vil, _)| vil)
+}
+
+/// Export, import and export again gives the same `.vil`.
+fn vil_round_trip() -> Result<(), String> {
+    let first = export(&keymap_file()?)?;
+    let second = export(&vial::import(&first)?)?;
+    if first == second {
+        Ok(())
+    } else {
+        Err(format!("second export differs:\n{first:#}\n{second:#}"))
+    }
+}
+
+/// An imported keymap serializes to `keymap.json` that parses to the same keymap.
+fn keymap_json_round_trip() -> Result<(), String> {
+    let layout = keymap_json::layout_positions(&rktk_config()?)?;
+    let imported = vial::import(&export(&keymap_file()?)?)?;
+    let json = imported.to_json().to_string();
+    let parsed = keymap_json::parse(&json, &layout)?;
+    if export(&parsed)? == export(&imported)? {
+        Ok(())
+    } else {
+        Err("keymap.json written from the import does not parse back to it".to_string())
+    }
+}
+
+/// Every keycode in the table converts back to the same expression.
+fn keycode_table() -> Result<(), String> {
+    let layout = [(0, 0)];
+    for (source, qmk) in vial::KEYCODES {
+        let json = format!(r#"{{ "layers": [{{ "keys": {{ "0,0": "{source}" }} }}] }}"#);
+        let file = keymap_json::parse(&json, &layout)?;
+        let (vil, _) = vial::export(&file, &layout, 1, 1)?;
+        if vil["layout"][0][0][0] != *qmk {
+            return Err(format!("`{source}` exported as {}", vil["layout"][0][0][0]));
+        }
+        let back = vial::import(&vil)?;
+        let (again, _) = vial::export(&back, &layout, 1, 1)?;
+        if again != vil {
+            return Err(format!("`{qmk}` did not round trip"));
+        }
+    }
+    Ok(())
+}
+
+/// Tap dances keep all their taps and encoders their bindings.
+fn tap_dance_and_encoder() -> Result<(), String> {
+    let vil = export(&keymap_file()?)?;
+    let imported = vial::import(&vil)?;
+    let original = keymap_file()?;
+    if imported.tap_dance.len() != original.tap_dance.len() {
+        return Err("tap dance count changed".to_string());
+    }
+    for (a, b) in imported.tap_dance.iter().zip(&original.tap_dance) {
+        if a.tap.len() != b.tap.len() || a.hold.len() != b.hold.len() {
+            return Err(format!("tap dance changed: {a:?} vs {b:?}"));
+        }
+    }
+    for (a, b) in imported.layers.iter().zip(&original.layers) {
+        if a.encoder.len() != b.encoder.len() {
+            return Err(format!("encoders of layer `{}` changed", b.name));
+        }
+    }
+    Ok(())
+}
+
+const CHECKS: &[(&str, Check)] = &[
+    ("export -> import -> export", vil_round_trip),
+    ("import -> keymap.json -> parse", keymap_json_round_trip),
+    ("keycode table", keycode_table),
+    ("tap dances and encoders", tap_dance_and_encoder),
+];
+
+fn main() -> ExitCode {
+    common::run("vial", CHECKS)
+}